@@ -535,12 +535,20 @@ class UploadHandler {
             return '<div class="info-message">No se encontró información demográfica</div>';
         }
     
+        const labels = {
+            M: 'Masculino',
+            F: 'Femenino',
+            Undifferentiated: 'Indiferenciado',
+            Other: 'Otro',
+            Unknown: 'No especificado'
+        };
+
         let html = '<div class="stats-list">';
         Object.entries(genderDist).forEach(([gender, count]) => {
             const percentage = ((count / this.currentStats.total_patients) * 100).toFixed(1);
             html += `
                 <div class="stats-item">
-                    <span class="stats-name">${gender === 'M' ? 'Masculino' : gender === 'F' ? 'Femenino' : 'No especificado'}</span>
+                    <span class="stats-name">${labels[gender] || 'No especificado'}</span>
                     <span class="stats-count">${count} (${percentage}%)</span>
                 </div>
             `;
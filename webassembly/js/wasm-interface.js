@@ -427,13 +427,137 @@ class WasmCDAParser {
         }
     }
     
-    getStats() {
+    // Deduce condiciones a partir de la medicación, en inferred_conditions y no en diagnoses
+    setInferConditions(enabled) {
+        if (this.useWasm && this.parser) {
+            this.parser.set_infer_conditions(enabled);
+        }
+    }
+
+    // kind: medication_names, title_mappings, diagnosis_keywords, med_to_diagnosis o all
+    loadDictionary(kind, json, replace = false) {
+        if (!this.useWasm || !this.parser) {
+            throw new Error('Los diccionarios sólo se pueden cargar con el parser WASM');
+        }
+        const text = typeof json === 'string' ? json : JSON.stringify(json);
+        return this.parser.load_dictionary(kind, text, replace);
+    }
+
+    // index: texto generado con buildTerminologyIndex a partir de los extractos CSV/RF2
+    loadTerminology(index, replace = false) {
+        if (!this.useWasm || !this.parser) {
+            throw new Error('La terminología sólo se puede cargar con el parser WASM');
+        }
+        return this.parser.load_terminology(index, replace);
+    }
+
+    // format: 'csv' (con codeSystem) o 'rf2' (descripciones SNOMED CT y refset de idioma opcional)
+    buildTerminologyIndex(format, content, codeSystem = null, languageRefset = null) {
+        if (!this.useWasm || !this.parser) {
+            throw new Error('La terminología sólo se puede cargar con el parser WASM');
+        }
+        return this.parser.build_terminology_index(format, content, codeSystem, languageRefset);
+    }
+
+    // content: refset de mapa extendido SNOMED CT -> CIE-10 (RF2, tabulado)
+    loadIcd10Map(content, replace = false) {
+        if (!this.useWasm || !this.parser) {
+            throw new Error('El mapa CIE-10 sólo se puede cargar con el parser WASM');
+        }
+        return this.parser.load_icd10_map(content, replace);
+    }
+
+    // Relaciones SNOMED CT (sct2_Relationship RF2) para agrupar diagnósticos por antecesor
+    loadSnomedRelationships(content, replace = false) {
+        if (!this.useWasm || !this.parser) {
+            throw new Error('La jerarquía SNOMED CT sólo se puede cargar con el parser WASM');
+        }
+        return this.parser.load_snomed_relationships(content, replace);
+    }
+
+    // Tabla ATC (CSV/TSV con columnas atc, name y opcionalmente code + code_system)
+    loadAtcTable(content, replace = false) {
+        if (!this.useWasm || !this.parser) {
+            throw new Error('La tabla ATC sólo se puede cargar con el parser WASM');
+        }
+        return this.parser.load_atc_table(content, replace);
+    }
+
+    // Tabla de interacciones (CSV/TSV: drug_a, drug_b, severity, description, management)
+    loadInteractions(content, replace = false) {
+        if (!this.useWasm || !this.parser) {
+            throw new Error('La tabla de interacciones sólo se puede cargar con el parser WASM');
+        }
+        return this.parser.load_interactions(content, replace);
+    }
+
+    // Contraindicaciones (CSV/TSV: drug, condition o allergy, severity, description, management)
+    loadContraindications(content, replace = false) {
+        if (!this.useWasm || !this.parser) {
+            throw new Error('La tabla de contraindicaciones sólo se puede cargar con el parser WASM');
+        }
+        return this.parser.load_contraindications(content, replace);
+    }
+
+    // Unidad canónica por código LOINC (CSV/TSV: loinc, unit, molar_mass)
+    loadCanonicalUnits(content, replace = false) {
+        if (!this.useWasm || !this.parser) {
+            throw new Error('La tabla de unidades sólo se puede cargar con el parser WASM');
+        }
+        return this.parser.load_canonical_units(content, replace);
+    }
+
+    // Rangos de referencia por defecto (CSV/TSV: loinc, sex, age_min, age_max, low, high,
+    // critical_low, critical_high, unit)
+    loadReferenceRanges(content, replace = false) {
+        if (!this.useWasm || !this.parser) {
+            throw new Error('La tabla de rangos de referencia sólo se puede cargar con el parser WASM');
+        }
+        return this.parser.load_reference_ranges(content, replace);
+    }
+
+    // Conversión UCUM: convertUnits(5.5, 'mmol/L', 'mg/dL', 180.156)
+    convertUnits(value, from, to, molarMass = undefined) {
+        if (!this.useWasm || !this.parser) {
+            throw new Error('La conversión de unidades sólo está disponible con el parser WASM');
+        }
+        return this.parser.convert_units(value, from, to, molarMass);
+    }
+
+    // Nivel ATC (1-5) de las clases terapéuticas en las estadísticas
+    setAtcLevel(level) {
+        if (this.useWasm && this.parser) {
+            this.parser.set_atc_level(level);
+        }
+    }
+
+    // level: name, icd10_category, icd10_block, icd10_chapter, snomed o snomed:N
+    setRollupLevel(level) {
+        if (this.useWasm && this.parser) {
+            this.parser.set_rollup_level(level);
+        }
+    }
+
+    // grouping: name o ingredient (principio activo sin concentración ni forma)
+    setMedicationGrouping(grouping) {
+        if (this.useWasm && this.parser) {
+            this.parser.set_medication_grouping(grouping);
+        }
+    }
+
+    // Sistemas de codificación conocidos: [{ oid, name, uri }]
+    getCodeSystems() {
+        return this.useWasm && this.parser ? this.parser.get_code_systems() : [];
+    }
+
+    // minConfidence (0-1): cuenta sólo diagnósticos y medicamentos con esa confianza o más
+    getStats(minConfidence) {
         if (!this.isInitialized) {
             return null;
         }
         
         if (this.useWasm && this.parser) {
-            return this.parser.get_statistics();
+            return this.parser.get_statistics(minConfidence);
         } else {
             return this.calculateStatistics();
         }
@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+
+use crate::atc::{name_tokens, AtcCode};
+use crate::code_systems;
+use crate::tables::normalize_header;
+use crate::terminology::split_csv_line;
+
+// Gravedad de una alerta, de menor a mayor
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Minor,
+    Moderate,
+    Major,
+    Contraindicated,
+}
+
+impl Severity {
+    pub fn from_name(name: &str) -> Option<Severity> {
+        match normalize_header(name.trim()).as_str() {
+            "minor" | "menor" | "leve" => Some(Severity::Minor),
+            "moderate" | "moderada" => Some(Severity::Moderate),
+            "major" | "mayor" | "grave" | "severe" => Some(Severity::Major),
+            "contraindicated" | "contraindicada" | "contraindicacion" => Some(Severity::Contraindicated),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Minor => "minor",
+            Severity::Moderate => "moderate",
+            Severity::Major => "major",
+            Severity::Contraindicated => "contraindicated",
+        }
+    }
+}
+
+// Elemento del documento que dispara la alerta y término de la regla con que coincide
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AlertTrigger {
+    // "medication", "diagnosis" o "allergy"
+    pub item_type: String,
+    pub name: String,
+    // Código del elemento con que coincide la regla: ATC, CIE-10, SNOMED CT...
+    pub code: Option<String>,
+    // "B01AA" (prefijo ATC) o "warfarina" (nombre)
+    pub matched: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Alert {
+    // "drug_interaction", "drug_condition" o "drug_allergy"
+    pub alert_type: String,
+    pub severity: Severity,
+    // Regla de la tabla que la genera: "B01AA + M01A", "C07AA + J45"
+    pub rule: String,
+    pub description: String,
+    pub management: Option<String>,
+    pub triggers: Vec<AlertTrigger>,
+    // La menor de las confianzas de los elementos implicados
+    pub confidence: f64,
+}
+
+// Medicamento de una regla: prefijo ATC de cualquier nivel o principio activo por nombre
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrugMatcher {
+    Atc(String),
+    Name(Vec<String>),
+}
+
+impl DrugMatcher {
+    // Un código ATC válido ("B01AA", "M01A") es un prefijo; cualquier otro texto, un nombre
+    pub fn parse(text: &str) -> Option<DrugMatcher> {
+        let atc = text.trim().to_uppercase();
+        let is_atc = code_systems::lookup(code_systems::ATC).map(|s| s.check(&atc).is_ok()).unwrap_or(false);
+        if is_atc {
+            return Some(DrugMatcher::Atc(atc));
+        }
+        let tokens = name_tokens(text);
+        if tokens.is_empty() {
+            None
+        } else {
+            Some(DrugMatcher::Name(tokens))
+        }
+    }
+
+    // Disparador si el medicamento (o la sustancia de una alergia) coincide: por su
+    // clasificación ATC o por las palabras de su nombre
+    pub fn matches(&self, item_type: &str, name: &str, atc: &[AtcCode]) -> Option<AlertTrigger> {
+        let (matched, code) = match self {
+            DrugMatcher::Atc(prefix) => {
+                let atc = atc.iter().find(|c| c.code.starts_with(prefix.as_str()))?;
+                (prefix.clone(), Some(atc.code.clone()))
+            }
+            DrugMatcher::Name(words) => {
+                if !contains_words(&name_tokens(name), words) {
+                    return None;
+                }
+                (words.join(" "), atc.first().map(|c| c.code.clone()))
+            }
+        };
+        Some(AlertTrigger { item_type: item_type.to_string(), name: name.to_string(), code, matched })
+    }
+}
+
+// La secuencia de palabras aparece seguida en el nombre
+pub fn contains_words(tokens: &[String], words: &[String]) -> bool {
+    !words.is_empty() && tokens.windows(words.len()).any(|w| w == words)
+}
+
+// Tabla de reglas en CSV o TSV con cabecera; las líneas en blanco y las que empiezan por # se ignoran
+pub struct RuleTable {
+    headers: Vec<String>,
+    // (número de línea, campos)
+    pub rows: Vec<(usize, Vec<String>)>,
+}
+
+impl RuleTable {
+    pub fn parse(content: &str) -> Result<RuleTable, String> {
+        let mut lines = content
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty() && !l.starts_with('#'));
+        let (_, header_line) = lines.next().ok_or("empty rule table")?;
+        let delimiter = [',', ';', '\t'].into_iter().max_by_key(|d| header_line.matches(*d).count()).unwrap_or(',');
+        let headers = split_csv_line(header_line.trim_start_matches('\u{FEFF}'), delimiter)
+            .iter()
+            .map(|h| normalize_header(h.trim()))
+            .collect();
+        let rows = lines.map(|(number, line)| (number + 1, split_csv_line(line, delimiter))).collect();
+        Ok(RuleTable { headers, rows })
+    }
+
+    pub fn column(&self, names: &[&str]) -> Option<usize> {
+        self.headers.iter().position(|h| names.contains(&h.as_str()))
+    }
+
+    pub fn required(&self, names: &[&str]) -> Result<usize, String> {
+        self.column(names).ok_or_else(|| format!("rule table has no {} column", names[0]))
+    }
+}
+
+// Campo recortado, vacío como None
+pub fn field(fields: &[String], index: Option<usize>) -> Option<&str> {
+    index.and_then(|i| fields.get(i)).map(|f| f.trim()).filter(|f| !f.is_empty())
+}
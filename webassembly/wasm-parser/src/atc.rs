@@ -0,0 +1,202 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::code_systems;
+use crate::tables::normalize_header;
+use crate::terminology::split_csv_line;
+
+// Tabla por defecto, embebida en el WASM
+const DEFAULT_TABLE: &str = include_str!("../resources/atc.tsv");
+
+// Longitud del código en cada nivel: "C", "C10", "C10A", "C10AA", "C10AA05"
+const LEVEL_LENGTHS: [usize; 5] = [1, 3, 4, 5, 7];
+// Principios activos de hasta cuatro palabras ("acido acetilsalicilico")
+const MAX_NAME_WORDS: usize = 4;
+
+const ATC_HEADERS: [&str; 4] = ["atc", "atc_code", "codigo_atc", "atccode"];
+const NAME_HEADERS: [&str; 6] = ["name", "nombre", "atc_name", "substance", "principio_activo", "descripcion"];
+const CODE_HEADERS: [&str; 3] = ["code", "product_code", "codigo_producto"];
+const CODE_SYSTEM_HEADERS: [&str; 3] = ["code_system", "codesystem", "sistema"];
+
+static DEFAULTS: Lazy<AtcTable> = Lazy::new(|| {
+    let mut table = AtcTable::default();
+    table.load(DEFAULT_TABLE, true).expect("resources/atc.tsv no es válido");
+    table
+});
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AtcLevel {
+    pub level: u8,
+    pub code: String,
+    pub name: Option<String>,
+}
+
+// Código ATC de un medicamento con sus niveles, del grupo anatómico al principio activo
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AtcCode {
+    pub code: String,
+    pub levels: Vec<AtcLevel>,
+    // "document" (el documento ya trae el código ATC), "product_code" o "name"
+    pub source: String,
+}
+
+impl AtcCode {
+    pub fn level(&self, level: u8) -> Option<&AtcLevel> {
+        self.levels.iter().find(|l| l.level == level)
+    }
+}
+
+// Tabla ATC: nombres de cada nivel y correspondencias de principios activos y códigos de producto
+#[derive(Debug, Clone, Default)]
+pub struct AtcTable {
+    // Código de cualquier nivel -> nombre
+    titles: HashMap<String, String>,
+    // Principio activo normalizado -> códigos de nivel 5
+    names: HashMap<String, Vec<String>>,
+    // (OID, código de producto) -> códigos ATC
+    products: HashMap<(String, String), Vec<String>>,
+}
+
+impl AtcTable {
+    pub fn defaults() -> AtcTable {
+        DEFAULTS.clone()
+    }
+
+    // CSV o TSV con cabecera: columna atc y, opcionales, name y code + code_system (RxNorm,
+    // nomenclátor nacional...). Las filas de nivel 5 con nombre sirven para reconocer el
+    // principio activo. Devuelve el número de filas cargadas
+    pub fn load(&mut self, content: &str, replace: bool) -> Result<usize, String> {
+        let mut lines = content
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty() && !l.starts_with('#'));
+        let (_, header_line) = lines.next().ok_or("empty ATC table")?;
+        let delimiter = [',', ';', '\t'].into_iter().max_by_key(|d| header_line.matches(*d).count()).unwrap_or(',');
+
+        let headers: Vec<String> = split_csv_line(header_line.trim_start_matches('\u{FEFF}'), delimiter)
+            .iter()
+            .map(|h| normalize_header(h.trim()))
+            .collect();
+        let column = |names: &[&str]| headers.iter().position(|h| names.contains(&h.as_str()));
+        let atc_column = column(&ATC_HEADERS).ok_or("ATC table has no atc column")?;
+        let name_column = column(&NAME_HEADERS);
+        let code_column = column(&CODE_HEADERS);
+        let system_column = column(&CODE_SYSTEM_HEADERS);
+        if code_column.is_some() && system_column.is_none() {
+            return Err("ATC table has a code column but no code_system column".to_string());
+        }
+        let syntax = code_systems::lookup(code_systems::ATC).ok_or("ATC is not in the code system registry")?;
+
+        let mut table = AtcTable::default();
+        let mut count = 0;
+        for (number, line) in lines {
+            let fields = split_csv_line(line, delimiter);
+            let field = |index: Option<usize>| {
+                index
+                    .and_then(|i| fields.get(i))
+                    .map(|f| f.trim())
+                    .filter(|f| !f.is_empty())
+            };
+            let atc = match field(Some(atc_column)) {
+                Some(atc) => atc.to_uppercase(),
+                None => continue,
+            };
+            syntax.check(&atc).map_err(|reason| format!("line {}: ATC code '{}': {}", number + 1, atc, reason))?;
+
+            if let Some(name) = field(name_column) {
+                table.titles.entry(atc.clone()).or_insert_with(|| name.to_string());
+                if atc.len() == LEVEL_LENGTHS[4] {
+                    table.names.entry(name_tokens(name).join(" ")).or_default().push(atc.clone());
+                }
+            }
+            if let Some(code) = field(code_column) {
+                let system = field(system_column).ok_or_else(|| format!("line {}: product code without code_system", number + 1))?;
+                let oid = code_systems::resolve(system)
+                    .ok_or_else(|| format!("line {}: unknown code system '{}'", number + 1, system))?;
+                table.products.entry((oid, code.to_uppercase())).or_default().push(atc);
+            }
+            count += 1;
+        }
+
+        if replace {
+            *self = AtcTable::default();
+        }
+        self.titles.extend(table.titles);
+        for (name, codes) in table.names {
+            merge_codes(self.names.entry(name).or_default(), codes);
+        }
+        for (product, codes) in table.products {
+            merge_codes(self.products.entry(product).or_default(), codes);
+        }
+        Ok(count)
+    }
+
+    // Códigos ATC de un medicamento: el del documento si es ATC; si no, por su código de
+    // producto y, por último, por los principios activos que aparecen en el nombre
+    pub fn classify(&self, code_system: Option<&str>, code: Option<&str>, name: &str) -> Vec<AtcCode> {
+        let oid = code_system.and_then(code_systems::resolve);
+        if let (Some(oid), Some(code)) = (oid.as_deref(), code.map(|c| c.trim().to_uppercase())) {
+            let valid = code_systems::lookup(oid).map(|s| s.check(&code).is_ok()).unwrap_or(false);
+            if oid == code_systems::ATC && valid {
+                return vec![self.code(&code, "document")];
+            }
+            if let Some(codes) = self.products.get(&(oid.to_string(), code.clone())) {
+                return codes.iter().map(|c| self.code(c, "product_code")).collect();
+            }
+        }
+        self.codes_in_name(name).iter().map(|c| self.code(c, "name")).collect()
+    }
+
+    fn code(&self, code: &str, source: &str) -> AtcCode {
+        let levels = LEVEL_LENGTHS
+            .iter()
+            .enumerate()
+            .filter(|(_, length)| **length <= code.len())
+            .map(|(index, length)| AtcLevel {
+                level: index as u8 + 1,
+                code: code[..*length].to_string(),
+                name: self.titles.get(&code[..*length]).cloned(),
+            })
+            .collect();
+        AtcCode { code: code.to_string(), levels, source: source.to_string() }
+    }
+
+    // "Enalapril/Hidroclorotiazida 20 mg" -> C09AA02 y C03AA03. Se prueba primero la
+    // secuencia de palabras más larga para no partir nombres compuestos
+    fn codes_in_name(&self, name: &str) -> Vec<String> {
+        let tokens = name_tokens(name);
+        let mut codes = Vec::new();
+        let mut start = 0;
+        while start < tokens.len() {
+            let longest = (1..=MAX_NAME_WORDS.min(tokens.len() - start))
+                .rev()
+                .find_map(|words| Some((words, self.names.get(&tokens[start..start + words].join(" "))?)));
+            match longest {
+                Some((words, found)) => {
+                    merge_codes(&mut codes, found.clone());
+                    start += words;
+                }
+                None => start += 1,
+            }
+        }
+        codes
+    }
+}
+
+// Palabras en minúsculas y sin tildes
+pub fn name_tokens(name: &str) -> Vec<String> {
+    normalize_header(name)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_string())
+        .collect()
+}
+
+fn merge_codes(target: &mut Vec<String>, codes: Vec<String>) {
+    for code in codes {
+        if !target.contains(&code) {
+            target.push(code);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Documento embebido en <nonXMLBody><text>
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Attachment {
+    pub media_type: String,
+    pub representation: String,
+    pub compression: Option<String>,
+    pub size: usize,
+    pub sha256: Option<String>,
+    pub text: Option<String>,
+}
+
+impl Attachment {
+    pub fn new(media_type: Option<String>, representation: Option<String>, compression: Option<String>) -> Self {
+        Attachment {
+            media_type: media_type.unwrap_or_else(|| "text/plain".to_string()).to_lowercase(),
+            representation: representation.unwrap_or_else(|| "TXT".to_string()).to_uppercase(),
+            compression,
+            size: 0,
+            sha256: None,
+            text: None,
+        }
+    }
+
+    // Decodifica el contenido y, si es texto plano o RTF, obtiene su texto
+    pub fn load(&mut self, content: &str) -> Result<(), String> {
+        let bytes = if self.representation == "B64" {
+            let compact: String = content.chars().filter(|c| !c.is_whitespace()).collect();
+            base64::engine::general_purpose::STANDARD
+                .decode(compact.as_bytes())
+                .map_err(|e| format!("Invalid base64 content: {}", e))?
+        } else {
+            content.as_bytes().to_vec()
+        };
+
+        self.size = bytes.len();
+        self.sha256 = Some(
+            Sha256::digest(&bytes)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+        );
+
+        // El contenido comprimido (DF, GZ...) se expone sin descomprimir
+        if self.compression.is_some() {
+            return Ok(());
+        }
+
+        self.text = match self.media_type.as_str() {
+            "text/plain" => Some(decode_text(&bytes)),
+            "text/rtf" | "application/rtf" => Some(rtf_to_text(&decode_text(&bytes))),
+            _ => None,
+        };
+        Ok(())
+    }
+
+    // Párrafos del texto decodificado, para los extractores narrativos
+    pub fn fragments(&self) -> Vec<String> {
+        self.text
+            .as_deref()
+            .unwrap_or_default()
+            .lines()
+            .map(|l| l.split_whitespace().collect::<Vec<&str>>().join(" "))
+            .filter(|l| !l.is_empty())
+            .collect()
+    }
+}
+
+fn decode_text(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        // Sin UTF-8 válido asumimos Latin-1, habitual en informes antiguos
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+// Conversión mínima de RTF a texto: párrafos, escapes y grupos ignorables
+fn rtf_to_text(rtf: &str) -> String {
+    const SKIP_GROUPS: [&str; 6] = ["fonttbl", "colortbl", "stylesheet", "info", "pict", "header"];
+
+    let chars: Vec<char> = rtf.chars().collect();
+    let mut out = String::new();
+    let mut depth = 0usize;
+    let mut skip_depth: Option<usize> = None;
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '{' => {
+                depth += 1;
+                i += 1;
+            }
+            '}' => {
+                if skip_depth == Some(depth) {
+                    skip_depth = None;
+                }
+                depth = depth.saturating_sub(1);
+                i += 1;
+            }
+            '\\' => {
+                i += 1;
+                let Some(&next) = chars.get(i) else { break };
+
+                if next == '\'' {
+                    // Escape hexadecimal \'hh (Windows-1252 ~ Latin-1)
+                    let hex: String = chars.iter().skip(i + 1).take(2).collect();
+                    if skip_depth.is_none() {
+                        if let Ok(code) = u8::from_str_radix(&hex, 16) {
+                            out.push(code as char);
+                        }
+                    }
+                    i += 3;
+                    continue;
+                }
+                if !next.is_ascii_alphabetic() {
+                    // Símbolo escapado: \\ \{ \} o \* (grupo opcional)
+                    if next == '*' {
+                        skip_depth.get_or_insert(depth);
+                    } else if skip_depth.is_none() && matches!(next, '\\' | '{' | '}') {
+                        out.push(next);
+                    }
+                    i += 1;
+                    continue;
+                }
+
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let num_start = i;
+                if i < chars.len() && chars[i] == '-' {
+                    i += 1;
+                }
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let param: String = chars[num_start..i].iter().collect();
+                if i < chars.len() && chars[i] == ' ' {
+                    i += 1;
+                }
+
+                if skip_depth.is_some() {
+                    continue;
+                }
+                match word.as_str() {
+                    "par" | "line" | "row" => out.push('\n'),
+                    "tab" | "cell" => out.push('\t'),
+                    "u" => {
+                        if let Some(c) = param.parse::<i32>().ok().and_then(|n| {
+                            char::from_u32(if n < 0 { (n + 65536) as u32 } else { n as u32 })
+                        }) {
+                            out.push(c);
+                        }
+                        // Carácter alternativo que sigue a \uN
+                        if i < chars.len() && chars[i] != '\\' && chars[i] != '{' && chars[i] != '}' {
+                            i += 1;
+                        }
+                    }
+                    w if SKIP_GROUPS.contains(&w) => {
+                        skip_depth = Some(depth);
+                    }
+                    _ => {}
+                }
+            }
+            '\r' | '\n' => i += 1,
+            c => {
+                if skip_depth.is_none() {
+                    out.push(c);
+                }
+                i += 1;
+            }
+        }
+    }
+
+    out
+}
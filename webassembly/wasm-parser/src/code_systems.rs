@@ -0,0 +1,329 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const LOINC: &str = "2.16.840.1.113883.6.1";
+pub const SNOMED_CT: &str = "2.16.840.1.113883.6.96";
+pub const RXNORM: &str = "2.16.840.1.113883.6.88";
+pub const ICD10: &str = "2.16.840.1.113883.6.3";
+pub const ICD10_CM: &str = "2.16.840.1.113883.6.90";
+pub const ATC: &str = "2.16.840.1.113883.6.73";
+
+const ICD10_PATTERN: &str = r"^[A-Z]\d[0-9A-Z](\.?[0-9A-Z]{1,4})?$";
+
+static OID: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[0-2](\.(0|[1-9]\d*))+$").unwrap());
+
+// Sintaxis de los códigos de cada sistema
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Syntax {
+    Loinc,
+    Snomed,
+    Digits(usize),
+    // Expresión regular y descripción del formato esperado
+    Pattern(&'static str, &'static str),
+    // Vocabularios HL7 v3 y demás: sin comprobación
+    Any,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CodeSystem {
+    pub oid: &'static str,
+    pub name: &'static str,
+    pub uri: &'static str,
+    #[serde(skip)]
+    syntax: Syntax,
+}
+
+const fn system(oid: &'static str, name: &'static str, uri: &'static str, syntax: Syntax) -> CodeSystem {
+    CodeSystem { oid, name, uri, syntax }
+}
+
+static REGISTRY: [CodeSystem; 30] = [
+    // Terminologías clínicas
+    system(LOINC, "LOINC", "http://loinc.org", Syntax::Loinc),
+    system(SNOMED_CT, "SNOMED CT", "http://snomed.info/sct", Syntax::Snomed),
+    system(RXNORM, "RxNorm", "http://www.nlm.nih.gov/research/umls/rxnorm", Syntax::Digits(8)),
+    system(ICD10, "ICD-10", "http://hl7.org/fhir/sid/icd-10", Syntax::Pattern(ICD10_PATTERN, "a letter, two characters and an optional .suffix")),
+    system(ICD10_CM, "ICD-10-CM", "http://hl7.org/fhir/sid/icd-10-cm", Syntax::Pattern(ICD10_PATTERN, "a letter, two characters and an optional .suffix")),
+    system("2.16.840.1.113883.6.4", "ICD-10-PCS", "http://www.cms.gov/Medicare/Coding/ICD10", Syntax::Pattern(r"^[0-9A-HJ-NP-Z]{7}$", "7 characters without I or O")),
+    system("2.16.840.1.113883.6.103", "ICD-9-CM", "http://hl7.org/fhir/sid/icd-9-cm", Syntax::Pattern(r"^(\d{3}|V\d{2}|E\d{3})(\.\d{1,2})?$", "NNN.NN, VNN.NN or ENNN.N")),
+    system("2.16.840.1.113883.12.292", "CVX", "http://hl7.org/fhir/sid/cvx", Syntax::Digits(3)),
+    system("2.16.840.1.113883.6.101", "NUCC Provider Taxonomy", "http://nucc.org/provider-taxonomy", Syntax::Pattern(r"^\d{3}[0-9A-Z]{6}X$", "10 characters ending in X")),
+    system("2.16.840.1.113883.6.69", "NDC", "http://hl7.org/fhir/sid/ndc", Syntax::Pattern(r"^(\d{4,5}-\d{3,4}-\d{1,2}|\d{10,11})$", "a 10 or 11 digit product code")),
+    system("2.16.840.1.113883.6.12", "CPT", "http://www.ama-assn.org/go/cpt", Syntax::Pattern(r"^\d{4}[0-9FTU]$", "5 characters")),
+    system("2.16.840.1.113883.6.8", "UCUM", "http://unitsofmeasure.org", Syntax::Any),
+    system("2.16.840.1.113883.6.238", "CDC Race and Ethnicity", "urn:oid:2.16.840.1.113883.6.238", Syntax::Pattern(r"^\d{4}-\d$", "NNNN-N")),
+    system(ATC, "ATC", "http://www.whocc.no/atc", Syntax::Pattern(r"^[A-Z](\d{2}([A-Z]([A-Z](\d{2})?)?)?)?$", "an ATC code of level 1 to 5")),
+    // Vocabularios HL7 v3
+    system("2.16.840.1.113883.5.1", "HL7 AdministrativeGender", "http://terminology.hl7.org/CodeSystem/v3-AdministrativeGender", Syntax::Any),
+    system("2.16.840.1.113883.5.2", "HL7 MaritalStatus", "http://terminology.hl7.org/CodeSystem/v3-MaritalStatus", Syntax::Any),
+    system("2.16.840.1.113883.5.4", "HL7 ActCode", "http://terminology.hl7.org/CodeSystem/v3-ActCode", Syntax::Any),
+    system("2.16.840.1.113883.5.6", "HL7 ActClass", "http://terminology.hl7.org/CodeSystem/v3-ActClass", Syntax::Any),
+    system("2.16.840.1.113883.5.14", "HL7 ActStatus", "http://terminology.hl7.org/CodeSystem/v3-ActStatus", Syntax::Any),
+    system("2.16.840.1.113883.5.25", "HL7 Confidentiality", "http://terminology.hl7.org/CodeSystem/v3-Confidentiality", Syntax::Any),
+    system("2.16.840.1.113883.5.83", "HL7 ObservationInterpretation", "http://terminology.hl7.org/CodeSystem/v3-ObservationInterpretation", Syntax::Any),
+    system("2.16.840.1.113883.5.88", "HL7 ParticipationFunction", "http://terminology.hl7.org/CodeSystem/v3-ParticipationFunction", Syntax::Any),
+    system("2.16.840.1.113883.5.90", "HL7 ParticipationType", "http://terminology.hl7.org/CodeSystem/v3-ParticipationType", Syntax::Any),
+    system("2.16.840.1.113883.5.110", "HL7 RoleClass", "http://terminology.hl7.org/CodeSystem/v3-RoleClass", Syntax::Any),
+    system("2.16.840.1.113883.5.111", "HL7 RoleCode", "http://terminology.hl7.org/CodeSystem/v3-RoleCode", Syntax::Any),
+    system("2.16.840.1.113883.5.112", "HL7 RouteOfAdministration", "http://terminology.hl7.org/CodeSystem/v3-RouteOfAdministration", Syntax::Any),
+    system("2.16.840.1.113883.5.1001", "HL7 ActMood", "http://terminology.hl7.org/CodeSystem/v3-ActMood", Syntax::Any),
+    system("2.16.840.1.113883.5.1008", "HL7 NullFlavor", "http://terminology.hl7.org/CodeSystem/v3-NullFlavor", Syntax::Any),
+    system("2.16.840.1.113883.5.1063", "HL7 ObservationValue", "http://terminology.hl7.org/CodeSystem/v3-ObservationValue", Syntax::Any),
+    system("2.16.840.1.113883.5.1119", "HL7 AddressUse", "http://terminology.hl7.org/CodeSystem/v3-AddressUse", Syntax::Any),
+];
+
+// Expresiones de los sistemas con Syntax::Pattern, compiladas una vez
+static PATTERNS: Lazy<HashMap<&'static str, Regex>> = Lazy::new(|| {
+    REGISTRY
+        .iter()
+        .filter_map(|s| match s.syntax {
+            Syntax::Pattern(pattern, _) => Some((pattern, Regex::new(pattern).unwrap())),
+            _ => None,
+        })
+        .collect()
+});
+
+// "urn:oid:2.16..." y "2.16..." son el mismo sistema
+fn bare_oid(code_system: &str) -> &str {
+    let code_system = code_system.trim();
+    code_system.strip_prefix("urn:oid:").unwrap_or(code_system)
+}
+
+pub fn is_oid(code_system: &str) -> bool {
+    OID.is_match(bare_oid(code_system))
+}
+
+pub fn lookup(code_system: &str) -> Option<&'static CodeSystem> {
+    let oid = bare_oid(code_system);
+    REGISTRY.iter().find(|s| s.oid == oid)
+}
+
+// OID de un sistema dado por OID o por nombre del registro ("SNOMED CT", "snomedct", "LOINC"...)
+pub fn resolve(name_or_oid: &str) -> Option<String> {
+    if is_oid(name_or_oid) {
+        return Some(bare_oid(name_or_oid).to_string());
+    }
+    let key = |name: &str| name.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase();
+    let wanted = key(name_or_oid);
+    REGISTRY.iter().find(|s| key(s.name) == wanted).map(|s| s.oid.to_string())
+}
+
+pub fn registry() -> &'static [CodeSystem] {
+    &REGISTRY
+}
+
+impl CodeSystem {
+    // Comprobación sintáctica del código; Err con el motivo
+    pub fn check(&self, code: &str) -> Result<(), String> {
+        let code = code.trim();
+        match self.syntax {
+            Syntax::Loinc => check_loinc(code),
+            Syntax::Snomed => check_snomed(code),
+            Syntax::Digits(max) => {
+                if !code.is_empty() && code.len() <= max && code.bytes().all(|b| b.is_ascii_digit()) {
+                    Ok(())
+                } else {
+                    Err(format!("expected 1 to {} digits", max))
+                }
+            }
+            Syntax::Pattern(pattern, expected) => match PATTERNS.get(pattern) {
+                Some(regex) if !regex.is_match(code) => Err(format!("expected {}", expected)),
+                _ => Ok(()),
+            },
+            Syntax::Any => Ok(()),
+        }
+    }
+}
+
+fn digits(code: &str) -> Option<Vec<u32>> {
+    code.chars().map(|c| c.to_digit(10)).collect()
+}
+
+// LOINC: número-dígito de control (mod 10, como Luhn)
+fn check_loinc(code: &str) -> Result<(), String> {
+    let (number, check) = code.split_once('-').ok_or("expected NNNNN-C")?;
+    let number = digits(number).filter(|d| !d.is_empty() && d.len() <= 7).ok_or("expected 1 to 7 digits before the dash")?;
+    let check = digits(check).filter(|d| d.len() == 1).ok_or("expected a single check digit")?[0];
+
+    let sum: u32 = number
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| if i % 2 == 0 { let d = d * 2; d / 10 + d % 10 } else { d })
+        .sum();
+    let expected = (10 - sum % 10) % 10;
+    if check == expected {
+        Ok(())
+    } else {
+        Err(format!("check digit should be {}", expected))
+    }
+}
+
+const VERHOEFF_D: [[u8; 10]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+    [1, 2, 3, 4, 0, 6, 7, 8, 9, 5],
+    [2, 3, 4, 0, 1, 7, 8, 9, 5, 6],
+    [3, 4, 0, 1, 2, 8, 9, 5, 6, 7],
+    [4, 0, 1, 2, 3, 9, 5, 6, 7, 8],
+    [5, 9, 8, 7, 6, 0, 4, 3, 2, 1],
+    [6, 5, 9, 8, 7, 1, 0, 4, 3, 2],
+    [7, 6, 5, 9, 8, 2, 1, 0, 4, 3],
+    [8, 7, 6, 5, 9, 3, 2, 1, 0, 4],
+    [9, 8, 7, 6, 5, 4, 3, 2, 1, 0],
+];
+
+const VERHOEFF_P: [[u8; 10]; 8] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+    [1, 5, 7, 6, 2, 8, 3, 0, 9, 4],
+    [5, 8, 0, 3, 7, 9, 6, 1, 4, 2],
+    [8, 9, 1, 6, 0, 4, 3, 5, 2, 7],
+    [9, 4, 5, 3, 1, 2, 6, 8, 7, 0],
+    [4, 2, 8, 6, 5, 7, 3, 9, 0, 1],
+    [2, 7, 9, 3, 8, 0, 6, 4, 1, 5],
+    [7, 0, 4, 6, 9, 1, 3, 2, 5, 8],
+];
+
+// SNOMED CT: 6-18 dígitos, identificador de partición válido y Verhoeff sobre el SCTID completo
+fn check_snomed(code: &str) -> Result<(), String> {
+    let number = digits(code).filter(|d| (6..=18).contains(&d.len())).ok_or("expected 6 to 18 digits")?;
+    if number[0] == 0 {
+        return Err("leading zero".to_string());
+    }
+    let partition = (number[number.len() - 3], number[number.len() - 2]);
+    if !matches!(partition, (0 | 1, 0..=2)) {
+        return Err(format!("invalid partition identifier {}{}", partition.0, partition.1));
+    }
+
+    let check = number
+        .iter()
+        .rev()
+        .enumerate()
+        .fold(0u8, |c, (i, &d)| VERHOEFF_D[c as usize][VERHOEFF_P[i % 8][d as usize] as usize]);
+    if check == 0 {
+        Ok(())
+    } else {
+        Err("fails the Verhoeff check digit".to_string())
+    }
+}
+
+// OID sin registrar visto en el documento, con los códigos que lo usan
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UnknownCodeSystem {
+    pub oid: String,
+    // codeSystemName declarado en el documento, si lo hay
+    pub declared_name: Option<String>,
+    pub occurrences: u32,
+    pub codes: Vec<String>,
+}
+
+// Código que no supera la comprobación sintáctica de su sistema
+#[derive(Debug, Clone)]
+pub struct InvalidCode {
+    pub system: &'static str,
+    pub code: String,
+    pub location: String,
+    pub reason: String,
+}
+
+// Resultado de anotar los códigos de un documento
+#[derive(Debug, Default)]
+pub struct CodeSystemReport {
+    pub unknown: Vec<UnknownCodeSystem>,
+    pub invalid: Vec<InvalidCode>,
+}
+
+impl CodeSystemReport {
+    // Nombre del sistema para anotar el elemento; registra OIDs desconocidos y códigos inválidos.
+    // Sin registro se conserva el codeSystemName declarado.
+    pub fn annotate(
+        &mut self,
+        code_system: Option<&str>,
+        code: Option<&str>,
+        declared_name: Option<&str>,
+        location: &str,
+    ) -> Option<String> {
+        let declared_name = declared_name.map(str::trim).filter(|n| !n.is_empty());
+        let code_system = match code_system {
+            Some(code_system) if is_oid(code_system) => code_system,
+            _ => return declared_name.map(|n| n.to_string()),
+        };
+        let code = code.map(str::trim).filter(|c| !c.is_empty());
+
+        match lookup(code_system) {
+            Some(system) => {
+                if let Some(code) = code {
+                    if let Err(reason) = system.check(code) {
+                        // Cada código inválido una sola vez por documento
+                        if !self.invalid.iter().any(|i| i.system == system.name && i.code == code) {
+                            self.invalid.push(InvalidCode {
+                                system: system.name,
+                                code: code.to_string(),
+                                location: location.to_string(),
+                                reason,
+                            });
+                        }
+                    }
+                }
+                Some(system.name.to_string())
+            }
+            None => {
+                let oid = bare_oid(code_system);
+                let index = match self.unknown.iter().position(|u| u.oid == oid) {
+                    Some(index) => index,
+                    None => {
+                        self.unknown.push(UnknownCodeSystem {
+                            oid: oid.to_string(),
+                            declared_name: None,
+                            occurrences: 0,
+                            codes: Vec::new(),
+                        });
+                        self.unknown.len() - 1
+                    }
+                };
+                let unknown = &mut self.unknown[index];
+                unknown.occurrences += 1;
+                if unknown.declared_name.is_none() {
+                    unknown.declared_name = declared_name.map(|n| n.to_string());
+                }
+                if let Some(code) = code {
+                    if !unknown.codes.iter().any(|c| c == code) {
+                        unknown.codes.push(code.to_string());
+                    }
+                }
+                declared_name.map(|n| n.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(code_system: &str, code: &str) -> Result<(), String> {
+        lookup(code_system).expect("sistema registrado").check(code)
+    }
+
+    #[test]
+    fn loinc_check_digit() {
+        assert_eq!(check(LOINC, "2345-7"), Ok(()));
+        assert_eq!(check(LOINC, "2345-8"), Err("check digit should be 7".to_string()));
+        assert!(check(LOINC, "2345").is_err());
+    }
+
+    #[test]
+    fn snomed_verhoeff() {
+        assert_eq!(check(SNOMED_CT, "73211009"), Ok(()));
+        assert_eq!(check(SNOMED_CT, "73211008"), Err("fails the Verhoeff check digit".to_string()));
+        assert!(check(SNOMED_CT, "073211009").is_err());
+    }
+
+    #[test]
+    fn resolves_names_and_urn_oids() {
+        assert_eq!(resolve("SNOMED CT").as_deref(), Some(SNOMED_CT));
+        assert_eq!(resolve("loinc").as_deref(), Some(LOINC));
+        assert_eq!(lookup("urn:oid:2.16.840.1.113883.6.1").map(|s| s.name), Some("LOINC"));
+    }
+}
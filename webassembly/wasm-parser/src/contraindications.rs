@@ -0,0 +1,259 @@
+use once_cell::sync::Lazy;
+
+use crate::alerts::{self, contains_words, Alert, AlertTrigger, DrugMatcher, RuleTable, Severity};
+use crate::atc::name_tokens;
+use crate::code_systems;
+use crate::hierarchy::SnomedHierarchy;
+use crate::{Allergy, Diagnosis, Medication};
+
+// Tabla por defecto, embebida en el WASM
+const DEFAULT_TABLE: &str = include_str!("../resources/contraindications.tsv");
+
+const DRUG_HEADERS: [&str; 3] = ["drug", "medicamento", "farmaco"];
+const CONDITION_HEADERS: [&str; 3] = ["condition", "condicion", "diagnostico"];
+const ALLERGY_HEADERS: [&str; 2] = ["allergy", "alergia"];
+const SEVERITY_HEADERS: [&str; 2] = ["severity", "gravedad"];
+const DESCRIPTION_HEADERS: [&str; 2] = ["description", "descripcion"];
+const MANAGEMENT_HEADERS: [&str; 3] = ["management", "manejo", "recomendacion"];
+
+static DEFAULTS: Lazy<ContraindicationTable> = Lazy::new(|| {
+    let mut table = ContraindicationTable::default();
+    table.load(DEFAULT_TABLE, true).expect("resources/contraindications.tsv no es válido");
+    table
+});
+
+// Diagnóstico de una regla: prefijo CIE-10, concepto SNOMED CT (y sus descendientes) o nombre
+#[derive(Debug, Clone, PartialEq)]
+enum ConditionMatcher {
+    Icd10(String),
+    Snomed(String),
+    Name(Vec<String>),
+}
+
+impl ConditionMatcher {
+    // "icd10:N18.5" o "N18.5", "snomed:195967001" o "195967001", y si no, un nombre
+    fn parse(text: &str) -> Option<ConditionMatcher> {
+        let text = text.trim();
+        let lower = text.to_lowercase();
+        if let Some(code) = lower.strip_prefix("snomed:") {
+            return Some(ConditionMatcher::Snomed(code.trim().to_string()));
+        }
+        if let Some(code) = lower.strip_prefix("icd10:") {
+            return Some(ConditionMatcher::Icd10(code.trim().to_uppercase()));
+        }
+        if text.len() >= 6 && text.bytes().all(|b| b.is_ascii_digit()) {
+            return Some(ConditionMatcher::Snomed(text.to_string()));
+        }
+        let is_icd10 = code_systems::lookup(code_systems::ICD10)
+            .map(|s| s.check(&text.to_uppercase()).is_ok())
+            .unwrap_or(false);
+        if is_icd10 {
+            return Some(ConditionMatcher::Icd10(text.to_uppercase()));
+        }
+        let tokens = name_tokens(text);
+        if tokens.is_empty() {
+            None
+        } else {
+            Some(ConditionMatcher::Name(tokens))
+        }
+    }
+
+    fn matches(&self, diagnosis: &Diagnosis, hierarchy: &SnomedHierarchy) -> Option<AlertTrigger> {
+        let (matched, code) = match self {
+            ConditionMatcher::Icd10(prefix) => {
+                let dotless = prefix.replace('.', "");
+                let code = diagnosis.icd10.iter().find(|c| c.code.replace('.', "").starts_with(&dotless))?;
+                (prefix.clone(), Some(code.code.clone()))
+            }
+            ConditionMatcher::Snomed(concept) => {
+                let code = diagnosis
+                    .codes_in(&[code_systems::SNOMED_CT])
+                    .into_iter()
+                    .find(|c| hierarchy.is_descendant(c, concept))?;
+                (concept.clone(), Some(code.to_string()))
+            }
+            ConditionMatcher::Name(words) => {
+                let names = [Some(&diagnosis.name), diagnosis.display_name_es.as_ref(), diagnosis.display_name_en.as_ref()];
+                if !names.iter().flatten().any(|name| contains_words(&name_tokens(name), words)) {
+                    return None;
+                }
+                (words.join(" "), diagnosis.code.clone())
+            }
+        };
+        Some(AlertTrigger { item_type: "diagnosis".to_string(), name: diagnosis.name.clone(), code, matched })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Trigger {
+    Condition(ConditionMatcher),
+    Allergy(DrugMatcher),
+}
+
+#[derive(Debug, Clone)]
+struct Contraindication {
+    drug: DrugMatcher,
+    trigger: Trigger,
+    rule: String,
+    severity: Severity,
+    description: String,
+    management: Option<String>,
+}
+
+// Contraindicaciones de un medicamento por un diagnóstico o por una alergia del paciente
+#[derive(Debug, Clone, Default)]
+pub struct ContraindicationTable {
+    rules: Vec<Contraindication>,
+}
+
+impl ContraindicationTable {
+    pub fn defaults() -> ContraindicationTable {
+        DEFAULTS.clone()
+    }
+
+    // CSV o TSV con cabecera: drug (prefijo ATC o principio activo), condition (CIE-10,
+    // SNOMED CT o nombre) o allergy (sustancia: prefijo ATC o nombre), severity, description
+    // y management opcional. Devuelve el número de reglas cargadas
+    pub fn load(&mut self, content: &str, replace: bool) -> Result<usize, String> {
+        let table = RuleTable::parse(content)?;
+        let drug = table.required(&DRUG_HEADERS)?;
+        let condition = table.column(&CONDITION_HEADERS);
+        let allergy = table.column(&ALLERGY_HEADERS);
+        if condition.is_none() && allergy.is_none() {
+            return Err("rule table has no condition or allergy column".to_string());
+        }
+        let severity = table.required(&SEVERITY_HEADERS)?;
+        let description = table.required(&DESCRIPTION_HEADERS)?;
+        let management = table.column(&MANAGEMENT_HEADERS);
+
+        let mut rules = Vec::new();
+        for (number, fields) in &table.rows {
+            let drug_text = alerts::field(fields, Some(drug)).ok_or_else(|| format!("line {}: missing drug", number))?;
+            let drug = DrugMatcher::parse(drug_text).ok_or_else(|| format!("line {}: invalid drug '{}'", number, drug_text))?;
+            let (trigger_text, trigger) = match (alerts::field(fields, condition), alerts::field(fields, allergy)) {
+                (Some(text), None) => (text, ConditionMatcher::parse(text).map(Trigger::Condition)),
+                (None, Some(text)) => (text, DrugMatcher::parse(text).map(Trigger::Allergy)),
+                _ => return Err(format!("line {}: expected either a condition or an allergy", number)),
+            };
+            let trigger = trigger.ok_or_else(|| format!("line {}: invalid condition or allergy '{}'", number, trigger_text))?;
+            let severity_name = alerts::field(fields, Some(severity)).unwrap_or_default();
+            let severity = Severity::from_name(severity_name)
+                .ok_or_else(|| format!("line {}: unknown severity '{}'", number, severity_name))?;
+            let description = alerts::field(fields, Some(description))
+                .ok_or_else(|| format!("line {}: missing description", number))?;
+
+            rules.push(Contraindication {
+                drug,
+                trigger,
+                rule: format!("{} + {}", drug_text, trigger_text),
+                severity,
+                description: description.to_string(),
+                management: alerts::field(fields, management).map(|m| m.to_string()),
+            });
+        }
+
+        let count = rules.len();
+        if replace {
+            self.rules.clear();
+        }
+        self.rules.append(&mut rules);
+        Ok(count)
+    }
+
+    // Cada medicamento contra cada diagnóstico y cada alergia; por par, la alerta más grave
+    pub fn check(
+        &self,
+        medications: &[Medication],
+        diagnoses: &[Diagnosis],
+        allergies: &[Allergy],
+        hierarchy: &SnomedHierarchy,
+    ) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for medication in medications {
+            for diagnosis in diagnoses {
+                let found = self.rules.iter().filter_map(|rule| {
+                    let condition = match &rule.trigger {
+                        Trigger::Condition(condition) => condition,
+                        Trigger::Allergy(_) => return None,
+                    };
+                    let drug = rule.drug.matches("medication", &medication.name, &medication.atc)?;
+                    let trigger = condition.matches(diagnosis, hierarchy)?;
+                    Some(self.alert("drug_condition", rule, drug, trigger, medication.confidence.min(diagnosis.confidence)))
+                });
+                alerts.extend(most_severe(found));
+            }
+
+            for allergy in allergies {
+                let confidence = medication.confidence.min(allergy.confidence);
+                let same = same_substance(medication, allergy).map(|(drug, trigger)| Alert {
+                    alert_type: "drug_allergy".to_string(),
+                    severity: Severity::Contraindicated,
+                    rule: format!("{} + {}", drug.matched, trigger.matched),
+                    description: format!("El paciente es alérgico a {}", allergy.name),
+                    management: Some("Suspender y elegir una alternativa de otro grupo terapéutico".to_string()),
+                    triggers: vec![drug, trigger],
+                    confidence,
+                });
+                let found = self.rules.iter().filter_map(|rule| {
+                    let allergen = match &rule.trigger {
+                        Trigger::Allergy(allergen) => allergen,
+                        Trigger::Condition(_) => return None,
+                    };
+                    let drug = rule.drug.matches("medication", &medication.name, &medication.atc)?;
+                    let trigger = allergen.matches("allergy", &allergy.name, &allergy.atc)?;
+                    Some(self.alert("drug_allergy", rule, drug, trigger, confidence))
+                });
+                alerts.extend(most_severe(same.into_iter().chain(found)));
+            }
+        }
+        alerts
+    }
+
+    fn alert(&self, alert_type: &str, rule: &Contraindication, drug: AlertTrigger, trigger: AlertTrigger, confidence: f64) -> Alert {
+        Alert {
+            alert_type: alert_type.to_string(),
+            severity: rule.severity,
+            rule: rule.rule.clone(),
+            description: rule.description.clone(),
+            management: rule.management.clone(),
+            triggers: vec![drug, trigger],
+            confidence,
+        }
+    }
+}
+
+// El medicamento es la propia sustancia de la alergia: mismo principio activo ATC o su nombre
+fn same_substance(medication: &Medication, allergy: &Allergy) -> Option<(AlertTrigger, AlertTrigger)> {
+    let shared = medication
+        .atc
+        .iter()
+        .find(|m| m.code.len() == 7 && allergy.atc.iter().any(|a| a.code == m.code))
+        .map(|m| m.code.clone());
+    let matched = match shared {
+        Some(code) => code,
+        None => {
+            let words = name_tokens(&allergy.name);
+            if !contains_words(&name_tokens(&medication.name), &words) {
+                return None;
+            }
+            words.join(" ")
+        }
+    };
+    let trigger = |item_type: &str, name: &str, code: Option<String>| AlertTrigger {
+        item_type: item_type.to_string(),
+        name: name.to_string(),
+        code,
+        matched: matched.clone(),
+    };
+    Some((
+        trigger("medication", &medication.name, medication.atc.first().map(|c| c.code.clone())),
+        trigger("allergy", &allergy.name, allergy.code.clone()),
+    ))
+}
+
+fn most_severe(alerts: impl Iterator<Item = Alert>) -> Option<Alert> {
+    alerts.fold(None, |best: Option<Alert>, alert| match best {
+        Some(best) if best.severity >= alert.severity => Some(best),
+        _ => Some(alert),
+    })
+}
@@ -0,0 +1,228 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+
+use crate::gender;
+use crate::icd10::{self, Icd10Code};
+
+// Conceptos SNOMED CT que usan las reglas del mapa extendido
+const MALE_FINDING: &str = "248153007";
+const FEMALE_FINDING: &str = "248152002";
+const AGE_AT_ONSET: &str = "445518008";
+
+// "IFA 445518008 | Age at onset of clinical finding (observable entity) | < 15.0 years"
+static IFA_CLAUSE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)^IFA\s+(\d+)\s*(?:\|[^|]*\|)?\s*(?:(<=|>=|<|>|=)\s*(\d+(?:\.\d+)?)\s*([a-z]+)?)?$").unwrap()
+});
+
+#[derive(Debug, Clone, PartialEq)]
+enum Clause {
+    Always,
+    Sex(&'static str),
+    Age { operator: String, years: f64 },
+    // El documento tiene ese otro hallazgo
+    Finding(String),
+    // Regla no reconocida: nunca se cumple
+    Unsupported,
+}
+
+#[derive(Debug, Clone)]
+struct MapRow {
+    group: u32,
+    priority: u32,
+    rule: String,
+    clauses: Vec<Clause>,
+    target: Option<String>,
+    advice: Option<String>,
+}
+
+// Datos del paciente y del documento con que se evalúan las reglas
+pub struct MapContext {
+    pub sex: Option<String>,
+    pub age_years: Option<f64>,
+    pub findings: HashSet<String>,
+}
+
+// Mapa SNOMED CT -> CIE-10 (refset de mapa extendido RF2)
+#[derive(Debug, Clone, Default)]
+pub struct Crosswalk {
+    maps: HashMap<String, Vec<MapRow>>,
+}
+
+impl Crosswalk {
+    // Fichero der2_iisssccRefset_ExtendedMap (o un mapa simple sin mapRule), tabulado y con
+    // cabecera. Devuelve el número de filas activas cargadas
+    pub fn load(&mut self, content: &str, replace: bool) -> Result<usize, String> {
+        let mut lines = content.lines().filter(|l| !l.trim().is_empty());
+        let headers: Vec<String> = lines
+            .next()
+            .ok_or("empty map file")?
+            .split('\t')
+            .map(|h| h.trim().trim_start_matches('\u{FEFF}').to_lowercase())
+            .collect();
+        let column = |name: &str| headers.iter().position(|h| h == name);
+        let concept = column("referencedcomponentid").ok_or("map file has no referencedComponentId column")?;
+        let target = column("maptarget").ok_or("map file has no mapTarget column")?;
+        let (active, group, priority, rule, advice) =
+            (column("active"), column("mapgroup"), column("mappriority"), column("maprule"), column("mapadvice"));
+
+        let mut maps: HashMap<String, Vec<MapRow>> = HashMap::new();
+        let mut count = 0;
+        for (number, line) in lines.enumerate() {
+            let fields: Vec<&str> = line.split('\t').map(|f| f.trim()).collect();
+            let field = |index: Option<usize>| index.and_then(|i| fields.get(i).copied()).filter(|f| !f.is_empty());
+            if field(active) == Some("0") {
+                continue;
+            }
+            let concept = field(Some(concept)).ok_or_else(|| format!("line {}: missing referencedComponentId", number + 2))?;
+            let number_field = |index: Option<usize>, name: &str| -> Result<u32, String> {
+                field(index).map_or(Ok(1), |v| v.parse().map_err(|_| format!("line {}: invalid {} '{}'", number + 2, name, v)))
+            };
+            let rule = field(rule).unwrap_or("TRUE").to_string();
+
+            maps.entry(concept.to_string()).or_default().push(MapRow {
+                group: number_field(group, "mapGroup")?,
+                priority: number_field(priority, "mapPriority")?,
+                clauses: parse_rule(&rule),
+                rule,
+                target: field(Some(target)).map(icd10::normalize),
+                advice: field(advice).map(|a| a.to_string()),
+            });
+            count += 1;
+        }
+
+        if replace {
+            self.maps.clear();
+        }
+        for (concept, mut rows) in maps {
+            let existing = self.maps.entry(concept).or_default();
+            existing.append(&mut rows);
+            existing.sort_by_key(|r| (r.group, r.priority));
+        }
+        Ok(count)
+    }
+
+    // Un código CIE-10 por grupo de mapa: el de la primera regla que se cumple
+    pub fn map(&self, concept: &str, context: &MapContext) -> Vec<Icd10Code> {
+        let rows = match self.maps.get(concept.trim()) {
+            Some(rows) => rows,
+            None => return Vec::new(),
+        };
+
+        let mut codes = Vec::new();
+        let mut groups: Vec<u32> = rows.iter().map(|r| r.group).collect();
+        groups.dedup();
+        for group in groups {
+            let matched = rows
+                .iter()
+                .filter(|r| r.group == group)
+                .find(|r| r.clauses.iter().all(|c| evaluate(c, context)));
+            // Una regla cumplida sin destino significa "no mapeable" en ese grupo
+            if let Some(MapRow { target: Some(target), rule, advice, .. }) = matched {
+                codes.push(Icd10Code {
+                    code: target.clone(),
+                    chapter: icd10::chapter_of(target).map(|c| c.to_string()),
+                    source: "crosswalk".to_string(),
+                    map_group: Some(group),
+                    map_rule: Some(rule.clone()),
+                    map_advice: advice.clone(),
+                });
+            }
+        }
+        codes
+    }
+}
+
+fn parse_rule(rule: &str) -> Vec<Clause> {
+    let rule = rule.trim();
+    if rule.is_empty() || rule.eq_ignore_ascii_case("TRUE") || rule.eq_ignore_ascii_case("OTHERWISE TRUE") {
+        return vec![Clause::Always];
+    }
+    rule.split(" AND ").map(|clause| parse_clause(clause.trim())).collect()
+}
+
+fn parse_clause(clause: &str) -> Clause {
+    let captures = match IFA_CLAUSE.captures(clause) {
+        Some(captures) => captures,
+        None => return Clause::Unsupported,
+    };
+    let concept = &captures[1];
+    match (concept, captures.get(2), captures.get(3)) {
+        (MALE_FINDING, None, _) => Clause::Sex(gender::MALE),
+        (FEMALE_FINDING, None, _) => Clause::Sex(gender::FEMALE),
+        (AGE_AT_ONSET, Some(operator), Some(value)) => {
+            let value: f64 = value.as_str().parse().unwrap_or(0.0);
+            let years = match captures.get(4).map(|u| u.as_str().to_lowercase()).as_deref() {
+                Some("months") | Some("month") => value / 12.0,
+                Some("days") | Some("day") => value / 365.25,
+                _ => value,
+            };
+            Clause::Age { operator: operator.as_str().to_string(), years }
+        }
+        (_, None, _) => Clause::Finding(concept.to_string()),
+        _ => Clause::Unsupported,
+    }
+}
+
+// Sin sexo o edad conocidos la condición no se cumple y se pasa a la regla siguiente
+fn evaluate(clause: &Clause, context: &MapContext) -> bool {
+    match clause {
+        Clause::Always => true,
+        Clause::Sex(sex) => context.sex.as_deref() == Some(*sex),
+        Clause::Age { operator, years } => match context.age_years {
+            Some(age) => match operator.as_str() {
+                "<" => age < *years,
+                "<=" => age <= *years,
+                ">" => age > *years,
+                ">=" => age >= *years,
+                _ => (age - years).abs() < f64::EPSILON,
+            },
+            None => false,
+        },
+        Clause::Finding(concept) => context.findings.contains(concept),
+        Clause::Unsupported => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "referencedComponentId\tactive\tmapGroup\tmapPriority\tmapRule\tmapAdvice\tmapTarget\n\
+        46635009\t1\t1\t1\tIFA 445518008 | Age at onset of clinical finding (observable entity) | < 15.0 years\tIF AGE AT ONSET < 15 CHOOSE E10.9\tE10.9\n\
+        46635009\t1\t1\t2\tOTHERWISE TRUE\tALWAYS E11.9\tE11.9\n\
+        46635009\t1\t2\t1\tIFA 248152002 | Female (finding) | AND IFA 445518008 | Age at onset of clinical finding (observable entity) | >= 180 months\tPREGNANCY\tO24.1\n\
+        46635009\t1\t2\t2\tOTHERWISE TRUE\tMAP OF SOURCE CONCEPT IS CONTEXT DEPENDENT\t\n";
+
+    fn context(sex: &str, age_years: f64) -> MapContext {
+        MapContext { sex: Some(sex.to_string()), age_years: Some(age_years), findings: HashSet::new() }
+    }
+
+    fn targets(crosswalk: &Crosswalk, context: &MapContext) -> Vec<String> {
+        crosswalk.map("46635009", context).into_iter().map(|c| c.code).collect()
+    }
+
+    #[test]
+    fn parses_age_and_sex_clauses() {
+        assert_eq!(
+            parse_rule("IFA 248152002 | Female (finding) | AND IFA 445518008 | Age at onset of clinical finding (observable entity) | >= 180 months"),
+            [Clause::Sex(gender::FEMALE), Clause::Age { operator: ">=".to_string(), years: 15.0 }]
+        );
+        assert_eq!(parse_rule("OTHERWISE TRUE"), [Clause::Always]);
+        assert_eq!(parse_rule("IFA 445518008 | Age at onset | ~ 15"), [Clause::Unsupported]);
+        assert_eq!(parse_rule("IFA 73211009 | Diabetes mellitus (disorder) |"), [Clause::Finding("73211009".to_string())]);
+    }
+
+    #[test]
+    fn maps_by_age_and_sex() {
+        let mut crosswalk = Crosswalk::default();
+        assert_eq!(crosswalk.load(MAP, true), Ok(4));
+
+        assert_eq!(targets(&crosswalk, &context(gender::MALE, 10.0)), ["E10.9"]);
+        assert_eq!(targets(&crosswalk, &context(gender::MALE, 40.0)), ["E11.9"]);
+        assert_eq!(targets(&crosswalk, &context(gender::FEMALE, 30.0)), ["E11.9", "O24.1"]);
+        // Edad desconocida: la regla de edad no se cumple y se aplica OTHERWISE TRUE
+        let unknown_age = MapContext { sex: Some(gender::FEMALE.to_string()), age_years: None, findings: HashSet::new() };
+        assert_eq!(targets(&crosswalk, &unknown_age), ["E11.9"]);
+    }
+}
@@ -0,0 +1,447 @@
+use serde::{Deserialize, Serialize};
+
+// Elemento XML genérico capturado mientras se lee un <value>
+#[derive(Debug, Clone, Default)]
+pub struct RawElement {
+    pub tag: String,
+    pub attributes: Vec<(String, String)>,
+    pub text: String,
+    pub children: Vec<RawElement>,
+}
+
+impl RawElement {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+            .filter(|v| !v.trim().is_empty())
+    }
+
+    pub fn child(&self, tag: &str) -> Option<&RawElement> {
+        self.children.iter().find(|c| c.tag == tag)
+    }
+
+    fn text(&self) -> Option<String> {
+        let text = self.text.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if text.is_empty() { None } else { Some(text) }
+    }
+}
+
+// Construye un RawElement con los eventos SAX del lector principal
+pub struct ElementBuilder {
+    stack: Vec<RawElement>,
+}
+
+impl ElementBuilder {
+    pub fn new(tag: &str, attributes: Vec<(String, String)>) -> Self {
+        ElementBuilder {
+            stack: vec![RawElement { tag: tag.to_string(), attributes, ..Default::default() }],
+        }
+    }
+
+    pub fn start(&mut self, tag: &str, attributes: Vec<(String, String)>) {
+        self.stack.push(RawElement { tag: tag.to_string(), attributes, ..Default::default() });
+    }
+
+    pub fn text(&mut self, text: &str) {
+        if let Some(element) = self.stack.last_mut() {
+            element.text.push_str(text);
+            element.text.push(' ');
+        }
+    }
+
+    // Cierra el elemento actual; devuelve la raíz cuando se cierra el elemento inicial
+    pub fn end(&mut self) -> Option<RawElement> {
+        let element = self.stack.pop()?;
+        match self.stack.last_mut() {
+            Some(parent) => {
+                parent.children.push(element);
+                None
+            }
+            None => Some(element),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PhysicalQuantity {
+    pub value: Option<f64>,
+    pub unit: Option<String>,
+    pub null_flavor: Option<String>,
+    // <translation> (PQR): el mismo valor en otra unidad o sistema de unidades
+    pub translations: Vec<QuantityTranslation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct QuantityTranslation {
+    pub value: Option<f64>,
+    // Unidad en el sistema de codificación de la traducción
+    pub code: Option<String>,
+    pub code_system: Option<String>,
+    pub code_system_name: Option<String>,
+    pub display_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CodedValue {
+    pub code: Option<String>,
+    pub code_system: Option<String>,
+    pub code_system_name: Option<String>,
+    pub display_name: Option<String>,
+    pub original_text: Option<String>,
+    pub original_text_reference: Option<String>,
+    pub null_flavor: Option<String>,
+    pub translations: Vec<CodedValue>,
+}
+
+impl CodedValue {
+    // Mejor etiqueta disponible: displayName, texto original o el propio código
+    pub fn label(&self) -> Option<String> {
+        self.display_name
+            .clone()
+            .or_else(|| self.original_text.clone())
+            .or_else(|| self.translations.iter().find_map(|t| t.display_name.clone()))
+    }
+}
+
+// Valor HL7 v3 decodificado según su xsi:type
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum Value {
+    #[serde(rename = "PQ")]
+    Pq(PhysicalQuantity),
+    #[serde(rename = "CD")]
+    Cd(CodedValue),
+    #[serde(rename = "CE")]
+    Ce(CodedValue),
+    #[serde(rename = "CO")]
+    Co(CodedValue),
+    #[serde(rename = "CV")]
+    Cv(CodedValue),
+    #[serde(rename = "CS")]
+    Cs(CodedValue),
+    #[serde(rename = "ST")]
+    St { text: Option<String>, null_flavor: Option<String> },
+    // Datos encapsulados: texto en línea o <reference> al bloque narrativo
+    #[serde(rename = "ED")]
+    Ed {
+        text: Option<String>,
+        media_type: Option<String>,
+        reference: Option<String>,
+        null_flavor: Option<String>,
+    },
+    #[serde(rename = "INT")]
+    Int { value: Option<i64>, null_flavor: Option<String> },
+    #[serde(rename = "REAL")]
+    Real { value: Option<f64>, null_flavor: Option<String> },
+    #[serde(rename = "BL")]
+    Bl { value: Option<bool>, null_flavor: Option<String> },
+    #[serde(rename = "TS")]
+    Ts { value: Option<String>, null_flavor: Option<String> },
+    #[serde(rename = "IVL_PQ")]
+    IvlPq {
+        low: Option<PhysicalQuantity>,
+        high: Option<PhysicalQuantity>,
+        null_flavor: Option<String>,
+    },
+    #[serde(rename = "RTO_PQ_PQ")]
+    RtoPqPq {
+        numerator: Option<PhysicalQuantity>,
+        denominator: Option<PhysicalQuantity>,
+        null_flavor: Option<String>,
+    },
+    #[serde(rename = "UNKNOWN")]
+    Unknown {
+        xsi_type: Option<String>,
+        text: Option<String>,
+        null_flavor: Option<String>,
+    },
+}
+
+impl Value {
+    pub fn from_element(element: &RawElement) -> Value {
+        let null_flavor = element.attr("nullflavor").map(|v| v.to_string());
+        let xsi_type = element
+            .attr("type")
+            .map(|t| t.rsplit(':').next().unwrap_or(t).to_uppercase())
+            .or_else(|| infer_type(element));
+
+        match xsi_type.as_deref() {
+            Some("PQ") => Value::Pq(parse_pq(element)),
+            Some("CD") => Value::Cd(parse_coded(element)),
+            Some("CE") => Value::Ce(parse_coded(element)),
+            Some("CO") => Value::Co(parse_coded(element)),
+            Some("CV") => Value::Cv(parse_coded(element)),
+            Some("CS") => Value::Cs(parse_coded(element)),
+            Some("ST") => Value::St { text: element.text(), null_flavor },
+            Some("ED") => Value::Ed {
+                text: element.text(),
+                media_type: element.attr("mediatype").map(|v| v.to_string()),
+                reference: element.child("reference").and_then(|r| r.attr("value")).map(|v| v.to_string()),
+                null_flavor,
+            },
+            Some("INT") => Value::Int {
+                value: element.attr("value").and_then(|v| v.trim().parse().ok()),
+                null_flavor,
+            },
+            Some("REAL") => Value::Real {
+                value: element.attr("value").and_then(parse_number),
+                null_flavor,
+            },
+            Some("BL") => Value::Bl {
+                value: element.attr("value").and_then(|v| v.trim().parse().ok()),
+                null_flavor,
+            },
+            Some("TS") => Value::Ts {
+                value: element.attr("value").map(|v| v.to_string()),
+                null_flavor,
+            },
+            Some("IVL_PQ") => Value::IvlPq {
+                low: element.child("low").map(parse_pq),
+                high: element.child("high").map(parse_pq),
+                null_flavor,
+            },
+            Some("RTO_PQ_PQ") | Some("RTO") => Value::RtoPqPq {
+                numerator: element.child("numerator").map(parse_pq),
+                denominator: element.child("denominator").map(parse_pq),
+                null_flavor,
+            },
+            other => Value::Unknown {
+                xsi_type: other.map(|t| t.to_string()),
+                text: element.text().or_else(|| element.attr("value").map(|v| v.to_string())),
+                null_flavor,
+            },
+        }
+    }
+
+    // Valor a partir del texto de una celda narrativa: "148", "2,7", "Negativo"
+    pub fn from_text(text: &str, unit: Option<&str>) -> Value {
+        match parse_number(text) {
+            Some(value) => Value::Pq(PhysicalQuantity {
+                value: Some(value),
+                unit: unit.map(|u| u.to_string()),
+                null_flavor: None,
+                translations: Vec::new(),
+            }),
+            None => Value::St { text: Some(text.trim().to_string()), null_flavor: None },
+        }
+    }
+
+    // Rango de referencia narrativo: "0.6-1.2", "< 200", "> 60"
+    pub fn range_from_text(text: &str, unit: Option<&str>) -> Value {
+        let pq = |v: &str| {
+            parse_number(v).map(|value| PhysicalQuantity {
+                value: Some(value),
+                unit: unit.map(|u| u.to_string()),
+                null_flavor: None,
+                translations: Vec::new(),
+            })
+        };
+        let trimmed = text.trim();
+
+        if let Some(rest) = trimmed.strip_prefix('<') {
+            if let Some(high) = pq(rest.trim_start_matches('=')) {
+                return Value::IvlPq { low: None, high: Some(high), null_flavor: None };
+            }
+        }
+        if let Some(rest) = trimmed.strip_prefix('>') {
+            if let Some(low) = pq(rest.trim_start_matches('=')) {
+                return Value::IvlPq { low: Some(low), high: None, null_flavor: None };
+            }
+        }
+        // Un número suelto ("-1.5") no es un rango
+        if parse_number(trimmed).is_some() {
+            return Value::St { text: Some(trimmed.to_string()), null_flavor: None };
+        }
+        // El guion separador va tras una cifra o un espacio; el de "-2-2" al inicio es el signo
+        let separator = trimmed
+            .char_indices()
+            .skip(1)
+            .find(|(i, c)| *c == '-' && trimmed[..*i].ends_with(|p: char| p.is_ascii_digit() || p.is_whitespace()));
+        if let Some((index, _)) = separator {
+            if let (Some(low), Some(high)) = (pq(&trimmed[..index]), pq(&trimmed[index + 1..])) {
+                return Value::IvlPq { low: Some(low), high: Some(high), null_flavor: None };
+            }
+        }
+        Value::St { text: Some(trimmed.to_string()), null_flavor: None }
+    }
+
+    pub fn coded(&self) -> Option<&CodedValue> {
+        match self {
+            Value::Cd(c) | Value::Ce(c) | Value::Co(c) | Value::Cv(c) | Value::Cs(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn coded_mut(&mut self) -> Option<&mut CodedValue> {
+        match self {
+            Value::Cd(c) | Value::Ce(c) | Value::Co(c) | Value::Cv(c) | Value::Cs(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn null_flavor(&self) -> Option<&str> {
+        match self {
+            Value::Pq(pq) => pq.null_flavor.as_deref(),
+            Value::Cd(c) | Value::Ce(c) | Value::Co(c) | Value::Cv(c) | Value::Cs(c) => c.null_flavor.as_deref(),
+            Value::St { null_flavor, .. }
+            | Value::Ed { null_flavor, .. }
+            | Value::Int { null_flavor, .. }
+            | Value::Real { null_flavor, .. }
+            | Value::Bl { null_flavor, .. }
+            | Value::Ts { null_flavor, .. }
+            | Value::IvlPq { null_flavor, .. }
+            | Value::RtoPqPq { null_flavor, .. }
+            | Value::Unknown { null_flavor, .. } => null_flavor.as_deref(),
+        }
+    }
+}
+
+// Sin xsi:type: deducir el tipo por los atributos presentes
+fn infer_type(element: &RawElement) -> Option<String> {
+    if element.attr("code").is_some() || element.attr("displayname").is_some() {
+        Some("CD".to_string())
+    } else if element.attr("unit").is_some() {
+        Some("PQ".to_string())
+    } else if element.child("low").is_some() || element.child("high").is_some() {
+        Some("IVL_PQ".to_string())
+    } else if element.text().is_some() {
+        Some("ST".to_string())
+    } else {
+        None
+    }
+}
+
+fn parse_pq(element: &RawElement) -> PhysicalQuantity {
+    PhysicalQuantity {
+        value: element.attr("value").and_then(parse_number),
+        unit: element.attr("unit").map(|u| u.to_string()),
+        null_flavor: element.attr("nullflavor").map(|v| v.to_string()),
+        translations: element
+            .children
+            .iter()
+            .filter(|c| c.tag == "translation")
+            .map(|t| QuantityTranslation {
+                value: t.attr("value").and_then(parse_number),
+                code: t.attr("code").map(|v| v.to_string()),
+                code_system: t.attr("codesystem").map(|v| v.to_string()),
+                code_system_name: t.attr("codesystemname").map(|v| v.to_string()),
+                display_name: t.attr("displayname").map(|v| v.to_string()),
+            })
+            .collect(),
+    }
+}
+
+fn parse_coded(element: &RawElement) -> CodedValue {
+    let original_text = element.child("originaltext");
+
+    CodedValue {
+        code: element.attr("code").map(|v| v.to_string()),
+        code_system: element.attr("codesystem").map(|v| v.to_string()),
+        code_system_name: element.attr("codesystemname").map(|v| v.to_string()),
+        display_name: element.attr("displayname").map(|v| v.to_string()),
+        original_text: original_text.and_then(|o| o.text()),
+        original_text_reference: original_text
+            .and_then(|o| o.child("reference"))
+            .and_then(|r| r.attr("value"))
+            .map(|v| v.to_string()),
+        null_flavor: element.attr("nullflavor").map(|v| v.to_string()),
+        translations: element
+            .children
+            .iter()
+            .filter(|c| c.tag == "translation")
+            .map(parse_coded)
+            .collect(),
+    }
+}
+
+pub fn parse_number(text: &str) -> Option<f64> {
+    text.trim().replace(',', ".").parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(tag: &str, attributes: &[(&str, &str)], children: Vec<RawElement>) -> RawElement {
+        RawElement {
+            tag: tag.to_string(),
+            attributes: attributes.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            text: String::new(),
+            children,
+        }
+    }
+
+    fn pq(value: f64, unit: &str) -> PhysicalQuantity {
+        PhysicalQuantity { value: Some(value), unit: Some(unit.to_string()), ..Default::default() }
+    }
+
+    #[test]
+    fn physical_quantity() {
+        let value = element("value", &[("type", "PQ"), ("value", "7,2"), ("unit", "mg/dL")], vec![]);
+        assert_eq!(Value::from_element(&value), Value::Pq(pq(7.2, "mg/dL")));
+        // Sin xsi:type, la unidad basta para reconocerlo
+        let value = element("value", &[("value", "120"), ("unit", "mm[Hg]")], vec![]);
+        assert_eq!(Value::from_element(&value), Value::Pq(pq(120.0, "mm[Hg]")));
+    }
+
+    #[test]
+    fn physical_quantity_translations() {
+        let translation = element(
+            "translation",
+            &[("value", "5.55"), ("code", "mmol/L"), ("codesystem", "2.16.840.1.113883.6.8"), ("codesystemname", "UCUM")],
+            vec![],
+        );
+        let value = element("value", &[("type", "xsi:PQ"), ("value", "100"), ("unit", "mg/dL")], vec![translation]);
+        match Value::from_element(&value) {
+            Value::Pq(quantity) => {
+                assert_eq!(quantity.value, Some(100.0));
+                assert_eq!(
+                    quantity.translations,
+                    [QuantityTranslation {
+                        value: Some(5.55),
+                        code: Some("mmol/L".to_string()),
+                        code_system: Some("2.16.840.1.113883.6.8".to_string()),
+                        code_system_name: Some("UCUM".to_string()),
+                        display_name: None,
+                    }]
+                );
+            }
+            other => panic!("expected PQ, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn interval() {
+        let value = element(
+            "value",
+            &[("type", "IVL_PQ")],
+            vec![element("low", &[("value", "70"), ("unit", "mg/dL")], vec![]), element("high", &[("nullflavor", "PINF")], vec![])],
+        );
+        assert_eq!(
+            Value::from_element(&value),
+            Value::IvlPq {
+                low: Some(pq(70.0, "mg/dL")),
+                high: Some(PhysicalQuantity { null_flavor: Some("PINF".to_string()), ..Default::default() }),
+                null_flavor: None,
+            }
+        );
+    }
+
+    #[test]
+    fn ratio() {
+        let value = element(
+            "value",
+            &[("type", "RTO_PQ_PQ")],
+            vec![
+                element("numerator", &[("value", "1"), ("unit", "mg")], vec![]),
+                element("denominator", &[("value", "5"), ("unit", "mL")], vec![]),
+            ],
+        );
+        assert_eq!(
+            Value::from_element(&value),
+            Value::RtoPqPq { numerator: Some(pq(1.0, "mg")), denominator: Some(pq(5.0, "mL")), null_flavor: None }
+        );
+    }
+}
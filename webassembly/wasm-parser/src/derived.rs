@@ -0,0 +1,346 @@
+use serde::{Deserialize, Serialize};
+
+use crate::code_systems;
+use crate::datatypes::{PhysicalQuantity, Value};
+use crate::gender;
+use crate::ucum::{self, NormalizedValue, Unit};
+use crate::{Observation, Patient};
+
+const WEIGHT_CODES: [&str; 2] = ["29463-7", "3141-9"];
+const HEIGHT_CODES: [&str; 1] = ["8302-2"];
+const CREATININE_CODES: [&str; 2] = ["2160-0", "38483-4"];
+const SYSTOLIC_CODES: [&str; 1] = ["8480-6"];
+const DIASTOLIC_CODES: [&str; 1] = ["8462-4"];
+
+// Observación calculada: código LOINC, nombres, categoría, unidad y decimales del resultado
+struct Target {
+    code: &'static str,
+    name_es: &'static str,
+    name_en: &'static str,
+    category: &'static str,
+    unit: &'static str,
+    decimals: i32,
+}
+
+const BMI: Target = Target {
+    code: "39156-5",
+    name_es: "Índice de masa corporal",
+    name_en: "Body mass index",
+    category: "vital_signs",
+    unit: "kg/m2",
+    decimals: 1,
+};
+const EGFR: Target = Target {
+    code: "98979-8",
+    name_es: "Filtrado glomerular estimado (CKD-EPI 2021)",
+    name_en: "Glomerular filtration rate/1.73 sq M.predicted (CKD-EPI 2021)",
+    category: "results",
+    unit: "mL/min/{1.73_m2}",
+    decimals: 0,
+};
+const MAP: Target = Target {
+    code: "8478-0",
+    name_es: "Presión arterial media",
+    name_en: "Mean arterial pressure",
+    category: "vital_signs",
+    unit: "mm[Hg]",
+    decimals: 0,
+};
+
+// CKD-EPI 2021 sólo está validada en adultos
+const EGFR_MIN_AGE: u32 = 18;
+
+// Cómo se calculó un valor derivado: método, fórmula aplicada y valores de entrada
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Derivation {
+    pub method: String,
+    pub formula: String,
+    pub inputs: Vec<DerivationInput>,
+}
+
+// Entrada de la fórmula: una observación (código LOINC) o un dato del paciente (edad, sexo)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DerivationInput {
+    // "weight", "height", "creatinine", "age", "sex", "systolic" o "diastolic"
+    pub role: String,
+    pub code: Option<String>,
+    pub value: Option<f64>,
+    pub unit: Option<String>,
+    pub text: Option<String>,
+    pub effective_time: Option<String>,
+}
+
+// Valor de una observación en la unidad que pide la fórmula
+#[derive(Clone)]
+struct Measure {
+    code: String,
+    value: f64,
+    unit: &'static str,
+    effective_time: Option<String>,
+}
+
+impl Measure {
+    fn input(&self, role: &str) -> DerivationInput {
+        DerivationInput {
+            role: role.to_string(),
+            code: Some(self.code.clone()),
+            value: Some(self.value),
+            unit: Some(self.unit.to_string()),
+            text: None,
+            effective_time: self.effective_time.clone(),
+        }
+    }
+}
+
+// IMC, filtrado glomerular (CKD-EPI 2021) y presión arterial media a partir de las observaciones
+// normalizadas. No se calcula lo que el documento ya informa
+pub fn derive(observations: &[Observation], patient: Option<&Patient>) -> Vec<Observation> {
+    let reported = |code: &str| observations.iter().any(|o| is_loinc(o, &[code]));
+    let mut derived = Vec::new();
+
+    if !reported(BMI.code) {
+        if let (Some(weight), Some(height)) =
+            (latest(observations, &WEIGHT_CODES, "kg"), latest(observations, &HEIGHT_CODES, "m"))
+        {
+            let value = weight.value / (height.value * height.value);
+            derived.push(calculated(
+                &BMI,
+                value,
+                latest_time(&[&weight, &height]),
+                Derivation {
+                    method: "BMI".to_string(),
+                    formula: "weight [kg] / height [m]^2".to_string(),
+                    inputs: vec![weight.input("weight"), height.input("height")],
+                },
+            ));
+        }
+    }
+
+    if !reported(EGFR.code) {
+        if let Some(observation) = egfr(observations, patient) {
+            derived.push(observation);
+        }
+    }
+
+    if !reported(MAP.code) {
+        if let Some((systolic, diastolic)) = blood_pressure(observations) {
+            let value = (systolic.value + 2.0 * diastolic.value) / 3.0;
+            derived.push(calculated(
+                &MAP,
+                value,
+                latest_time(&[&systolic, &diastolic]),
+                Derivation {
+                    method: "MAP".to_string(),
+                    formula: "(SBP + 2 * DBP) / 3".to_string(),
+                    inputs: vec![systolic.input("systolic"), diastolic.input("diastolic")],
+                },
+            ));
+        }
+    }
+    derived
+}
+
+// 142 × min(Scr/κ, 1)^α × max(Scr/κ, 1)^-1.200 × 0.9938^edad [× 1.012 en mujeres]
+fn egfr(observations: &[Observation], patient: Option<&Patient>) -> Option<Observation> {
+    let creatinine = latest(observations, &CREATININE_CODES, "mg/dL")?;
+    let patient = patient?;
+    let age = patient.age.filter(|age| *age >= EGFR_MIN_AGE)?;
+    let sex = match patient.gender.as_deref()? {
+        gender::FEMALE => gender::FEMALE,
+        gender::MALE => gender::MALE,
+        _ => return None,
+    };
+    let (kappa, alpha, factor) = if sex == gender::FEMALE { (0.7, -0.241, 1.012) } else { (0.9, -0.302, 1.0) };
+
+    let ratio = creatinine.value / kappa;
+    let value = 142.0 * ratio.min(1.0).powf(alpha) * ratio.max(1.0).powf(-1.200) * 0.9938f64.powi(age as i32) * factor;
+    let formula = format!(
+        "142 * min(Scr/{}, 1)^{} * max(Scr/{}, 1)^-1.200 * 0.9938^age{}",
+        kappa,
+        alpha,
+        kappa,
+        if sex == gender::FEMALE { " * 1.012" } else { "" }
+    );
+    let mut inputs = vec![creatinine.input("creatinine")];
+    inputs.push(DerivationInput {
+        role: "age".to_string(),
+        code: None,
+        value: Some(age as f64),
+        unit: Some("a".to_string()),
+        text: None,
+        effective_time: None,
+    });
+    inputs.push(DerivationInput {
+        role: "sex".to_string(),
+        code: None,
+        value: None,
+        unit: None,
+        text: Some(sex.to_string()),
+        effective_time: None,
+    });
+
+    Some(calculated(
+        &EGFR,
+        value,
+        creatinine.effective_time.clone(),
+        Derivation { method: "CKD-EPI 2021".to_string(), formula, inputs },
+    ))
+}
+
+// Sistólica y diastólica de la misma toma: la más reciente con ambas
+fn blood_pressure(observations: &[Observation]) -> Option<(Measure, Measure)> {
+    let systolics = measures(observations, &SYSTOLIC_CODES, "mm[Hg]");
+    let diastolics = measures(observations, &DIASTOLIC_CODES, "mm[Hg]");
+    let mut pairs: Vec<(Measure, Measure)> = Vec::new();
+    for systolic in systolics {
+        if let Some(diastolic) = diastolics.iter().find(|d| d.effective_time == systolic.effective_time) {
+            pairs.push((systolic, diastolic.clone()));
+        }
+    }
+    pairs.into_iter().max_by(|a, b| a.0.effective_time.cmp(&b.0.effective_time))
+}
+
+// Medida más reciente de cualquiera de los códigos. Las fechas HL7 (AAAAMMDDhhmmss) se ordenan como texto
+fn latest(observations: &[Observation], codes: &[&str], unit: &'static str) -> Option<Measure> {
+    measures(observations, codes, unit).into_iter().max_by(|a, b| a.effective_time.cmp(&b.effective_time))
+}
+
+fn measures(observations: &[Observation], codes: &[&str], unit: &'static str) -> Vec<Measure> {
+    observations
+        .iter()
+        .filter(|o| is_loinc(o, codes))
+        .filter_map(|o| {
+            let value = value_in(o.normalized_value.as_ref()?, unit)?;
+            // Un peso o una creatinina a cero no sirven como entrada
+            if value <= 0.0 {
+                return None;
+            }
+            Some(Measure {
+                code: o.code.clone()?,
+                value,
+                unit,
+                effective_time: o.effective_time.clone(),
+            })
+        })
+        .collect()
+}
+
+fn value_in(normalized: &NormalizedValue, unit: &str) -> Option<f64> {
+    if normalized.unit == unit {
+        return Some(normalized.value);
+    }
+    let from = Unit::parse(&normalized.unit).ok()?;
+    let to = Unit::parse(unit).ok()?;
+    ucum::convert(normalized.value, &from, &to, None).ok()
+}
+
+fn is_loinc(observation: &Observation, codes: &[&str]) -> bool {
+    observation.code_system.as_deref() == Some(code_systems::LOINC)
+        && observation.code.as_deref().is_some_and(|code| codes.contains(&code))
+}
+
+fn latest_time(measures: &[&Measure]) -> Option<String> {
+    measures.iter().filter_map(|m| m.effective_time.clone()).max()
+}
+
+fn round_to(value: f64, decimals: i32) -> f64 {
+    let scale = 10f64.powi(decimals);
+    (value * scale).round() / scale
+}
+
+fn calculated(target: &Target, value: f64, effective_time: Option<String>, derivation: Derivation) -> Observation {
+    let value = round_to(value, target.decimals);
+    Observation {
+        code: Some(target.code.to_string()),
+        code_system: Some(code_systems::LOINC.to_string()),
+        code_system_name: Some("LOINC".to_string()),
+        name: target.name_es.to_string(),
+        display_name_es: Some(target.name_es.to_string()),
+        display_name_en: Some(target.name_en.to_string()),
+        category: target.category.to_string(),
+        value: Some(Value::Pq(PhysicalQuantity {
+            value: Some(value),
+            unit: Some(target.unit.to_string()),
+            null_flavor: None,
+            translations: Vec::new(),
+        })),
+        normalized_value: Some(NormalizedValue { value, unit: target.unit.to_string(), original_unit: None }),
+        effective_time,
+        reference_range: None,
+        interpretation_code: None,
+        abnormal_flag: None,
+        observation_type: "calculated".to_string(),
+        derivation: Some(derivation),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measured(code: &str, value: f64, unit: &str, effective_time: &str) -> Observation {
+        Observation {
+            code: Some(code.to_string()),
+            code_system: Some(code_systems::LOINC.to_string()),
+            code_system_name: None,
+            name: code.to_string(),
+            display_name_es: None,
+            display_name_en: None,
+            category: "results".to_string(),
+            value: Some(Value::Pq(PhysicalQuantity {
+                value: Some(value),
+                unit: Some(unit.to_string()),
+                null_flavor: None,
+                translations: Vec::new(),
+            })),
+            normalized_value: Some(NormalizedValue { value, unit: unit.to_string(), original_unit: None }),
+            effective_time: Some(effective_time.to_string()),
+            reference_range: None,
+            interpretation_code: None,
+            abnormal_flag: None,
+            observation_type: "structured".to_string(),
+            derivation: None,
+        }
+    }
+
+    fn patient(gender: &str, age: u32) -> Patient {
+        Patient { gender: Some(gender.to_string()), age: Some(age), ..Default::default() }
+    }
+
+    fn value(observations: &[Observation], code: &str) -> Option<f64> {
+        observations.iter().find(|o| o.code.as_deref() == Some(code)).and_then(|o| o.normalized_value.as_ref()).map(|n| n.value)
+    }
+
+    #[test]
+    fn egfr_ckd_epi_2021() {
+        let creatinine = [measured("2160-0", 1.5, "mg/dL", "20240101")];
+        assert_eq!(value(&derive(&creatinine, Some(&patient(gender::MALE, 64))), EGFR.code), Some(52.0));
+        assert_eq!(value(&derive(&creatinine, Some(&patient(gender::FEMALE, 64))), EGFR.code), Some(39.0));
+        // Creatinina por debajo de κ: rama del exponente α
+        let low = [measured("2160-0", 0.6, "mg/dL", "20240101")];
+        assert_eq!(value(&derive(&low, Some(&patient(gender::FEMALE, 40))), EGFR.code), Some(116.0));
+    }
+
+    #[test]
+    fn egfr_needs_adult_with_known_sex() {
+        let creatinine = [measured("2160-0", 1.5, "mg/dL", "20240101")];
+        assert_eq!(value(&derive(&creatinine, Some(&patient(gender::MALE, 16))), EGFR.code), None);
+        assert_eq!(value(&derive(&creatinine, Some(&patient(gender::UNKNOWN, 64))), EGFR.code), None);
+        assert_eq!(value(&derive(&creatinine, None), EGFR.code), None);
+    }
+
+    #[test]
+    fn bmi_and_mean_arterial_pressure() {
+        let observations = [
+            measured("29463-7", 198.0, "[lb_av]", "20240101"),
+            measured("8302-2", 65.0, "[in_i]", "20240101"),
+            measured("8480-6", 120.0, "mm[Hg]", "20240101"),
+            measured("8462-4", 60.0, "mm[Hg]", "20240101"),
+            measured("8480-6", 150.0, "mm[Hg]", "20240102"),
+        ];
+        let derived = derive(&observations, None);
+        assert_eq!(value(&derived, BMI.code), Some(32.9));
+        // La sistólica del día 2 no tiene diastólica de la misma toma
+        assert_eq!(value(&derived, MAP.code), Some(80.0));
+    }
+}
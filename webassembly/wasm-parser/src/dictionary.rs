@@ -0,0 +1,195 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::hierarchy::name_key;
+
+// Tablas por defecto, embebidas en el WASM
+const DEFAULT_DICTIONARIES: &str = include_str!("../resources/dictionaries.json");
+
+// Palabras clave más cortas coincidirían dentro de casi cualquier texto
+const MIN_KEYWORD_LEN: usize = 3;
+
+static DEFAULTS: Lazy<Dictionaries> = Lazy::new(|| {
+    let mut dictionaries = Dictionaries::default();
+    dictionaries
+        .load("all", DEFAULT_DICTIONARIES, true)
+        .expect("resources/dictionaries.json no es válido");
+    dictionaries
+});
+
+// Palabra clave buscada en el texto (en minúsculas) y diagnóstico que produce
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeywordMapping {
+    pub keyword: String,
+    pub diagnosis: String,
+}
+
+// Tablas de terminología que usan los extractores de texto y la inferencia
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Dictionaries {
+    // Alias en minúsculas -> nombre normalizado del medicamento
+    pub medication_names: HashMap<String, String>,
+    pub title_mappings: Vec<KeywordMapping>,
+    pub diagnosis_keywords: Vec<String>,
+    pub med_to_diagnosis: Vec<KeywordMapping>,
+}
+
+impl Dictionaries {
+    pub fn defaults() -> Dictionaries {
+        DEFAULTS.clone()
+    }
+
+    // Carga una tabla ("medication_names", "title_mappings", "diagnosis_keywords",
+    // "med_to_diagnosis") o todas ("all"); sin `replace` amplía la existente.
+    // Devuelve el número de entradas cargadas.
+    pub fn load(&mut self, kind: &str, json: &str, replace: bool) -> Result<usize, String> {
+        let kind = kind.trim().to_lowercase();
+        match kind.as_str() {
+            "medication_names" => {
+                let entries = validate_names(&parse(&kind, json)?)?;
+                Ok(merge_names(&mut self.medication_names, entries, replace))
+            }
+            "title_mappings" => {
+                let entries = validate_mappings(&kind, parse(&kind, json)?)?;
+                Ok(merge_mappings(&mut self.title_mappings, entries, replace))
+            }
+            "diagnosis_keywords" => {
+                let entries = validate_keywords(parse(&kind, json)?)?;
+                Ok(merge_keywords(&mut self.diagnosis_keywords, entries, replace))
+            }
+            "med_to_diagnosis" => {
+                let entries = validate_mappings(&kind, parse(&kind, json)?)?;
+                Ok(merge_mappings(&mut self.med_to_diagnosis, entries, replace))
+            }
+            "all" => {
+                let all: Dictionaries = parse(&kind, json)?;
+                // Validar todo antes de tocar nada: una tabla errónea no deja el resto a medias
+                let names = validate_names(&all.medication_names)?;
+                let titles = validate_mappings("title_mappings", all.title_mappings)?;
+                let keywords = validate_keywords(all.diagnosis_keywords)?;
+                let inferences = validate_mappings("med_to_diagnosis", all.med_to_diagnosis)?;
+
+                Ok(merge_names(&mut self.medication_names, names, replace)
+                    + merge_mappings(&mut self.title_mappings, titles, replace)
+                    + merge_keywords(&mut self.diagnosis_keywords, keywords, replace)
+                    + merge_mappings(&mut self.med_to_diagnosis, inferences, replace))
+            }
+            other => Err(format!("Unknown dictionary kind: {}", other)),
+        }
+    }
+
+    // Nombre normalizado del medicamento, o el original limpio si no hay alias
+    pub fn medication_name(&self, name: &str) -> String {
+        let key = name.to_lowercase().trim().to_string();
+        self.medication_names
+            .get(&key)
+            .cloned()
+            .unwrap_or_else(|| name.trim().to_string())
+    }
+
+    // Diagnóstico de una palabra clave o un nombre sin codificar: el de title_mappings si la
+    // palabra está ahí ("hipertenso" -> "Hipertensión"), o la propia palabra
+    pub fn diagnosis_name(&self, keyword: &str) -> String {
+        let key = name_key(keyword);
+        self.title_mappings
+            .iter()
+            .find(|m| name_key(&m.keyword) == key)
+            .map(|m| m.diagnosis.clone())
+            .unwrap_or_else(|| keyword.to_string())
+    }
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(kind: &str, json: &str) -> Result<T, String> {
+    serde_json::from_str(json).map_err(|e| format!("Invalid {} dictionary: {}", kind, e))
+}
+
+fn validate_keyword(context: &str, keyword: &str, errors: &mut Vec<String>) -> String {
+    let keyword = keyword.split_whitespace().collect::<Vec<&str>>().join(" ").to_lowercase();
+    if keyword.chars().count() < MIN_KEYWORD_LEN {
+        errors.push(format!("{}: keyword '{}' is shorter than {} characters", context, keyword, MIN_KEYWORD_LEN));
+    }
+    keyword
+}
+
+fn validate_names(entries: &HashMap<String, String>) -> Result<HashMap<String, String>, String> {
+    let mut errors = Vec::new();
+    let mut valid = HashMap::new();
+    for (alias, name) in entries {
+        let context = format!("medication_names['{}']", alias);
+        let alias = validate_keyword(&context, alias, &mut errors);
+        if name.trim().is_empty() {
+            errors.push(format!("{}: empty medication name", context));
+        }
+        valid.insert(alias, name.trim().to_string());
+    }
+    if errors.is_empty() { Ok(valid) } else { Err(errors.join("; ")) }
+}
+
+fn validate_mappings(kind: &str, entries: Vec<KeywordMapping>) -> Result<Vec<KeywordMapping>, String> {
+    let mut errors = Vec::new();
+    let mut valid: Vec<KeywordMapping> = Vec::new();
+    for (index, entry) in entries.into_iter().enumerate() {
+        let context = format!("{}[{}]", kind, index);
+        let keyword = validate_keyword(&context, &entry.keyword, &mut errors);
+        if entry.diagnosis.trim().is_empty() {
+            errors.push(format!("{}: empty diagnosis", context));
+        }
+        if valid.iter().any(|m| m.keyword == keyword) {
+            errors.push(format!("{}: duplicated keyword '{}'", context, keyword));
+        }
+        valid.push(KeywordMapping { keyword, diagnosis: entry.diagnosis.trim().to_string() });
+    }
+    if errors.is_empty() { Ok(valid) } else { Err(errors.join("; ")) }
+}
+
+fn validate_keywords(entries: Vec<String>) -> Result<Vec<String>, String> {
+    let mut errors = Vec::new();
+    let mut valid: Vec<String> = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let keyword = validate_keyword(&format!("diagnosis_keywords[{}]", index), entry, &mut errors);
+        if !valid.contains(&keyword) {
+            valid.push(keyword);
+        }
+    }
+    if errors.is_empty() { Ok(valid) } else { Err(errors.join("; ")) }
+}
+
+fn merge_names(target: &mut HashMap<String, String>, entries: HashMap<String, String>, replace: bool) -> usize {
+    let count = entries.len();
+    if replace {
+        target.clear();
+    }
+    target.extend(entries);
+    count
+}
+
+fn merge_keywords(target: &mut Vec<String>, entries: Vec<String>, replace: bool) -> usize {
+    let count = entries.len();
+    if replace {
+        target.clear();
+    }
+    for keyword in entries {
+        if !target.contains(&keyword) {
+            target.push(keyword);
+        }
+    }
+    count
+}
+
+// Una palabra clave ya presente cambia de diagnóstico; las nuevas se añaden al final
+fn merge_mappings(target: &mut Vec<KeywordMapping>, entries: Vec<KeywordMapping>, replace: bool) -> usize {
+    let count = entries.len();
+    if replace {
+        *target = entries;
+        return count;
+    }
+    for entry in entries {
+        match target.iter_mut().find(|m| m.keyword == entry.keyword) {
+            Some(existing) => existing.diagnosis = entry.diagnosis,
+            None => target.push(entry),
+        }
+    }
+    count
+}
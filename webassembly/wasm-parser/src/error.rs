@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use wasm_bindgen::{JsCast, JsValue};
+
+// Posición en el XML de entrada: offset en bytes, línea y columna desde 1
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    // Salta los espacios previos: el lector recorta el texto entre eventos
+    pub fn new(xml: &str, offset: usize) -> Self {
+        let mut offset = offset.min(xml.len());
+        while !xml.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset += xml[offset..].len() - xml[offset..].trim_start().len();
+
+        let before = &xml[..offset];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Position {
+            offset,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+// Cómo reaccionar ante XML dañado y datos dudosos
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    // Errores de XML abortan; los avisos se devuelven con el documento
+    Standard,
+    // Recupera lo extraído ante etiquetas sin cerrar o mal cerradas
+    Lenient,
+    // Los avisos de XML recuperado o datos inválidos se convierten en error
+    Strict,
+}
+
+// Avisos que el modo estricto rechaza. Los informativos (unit_conversion, invalid_code de un
+// código que no pasa su dígito de control) se devuelven con el documento en cualquier modo
+const STRICT_ERRORS: [&str; 6] = [
+    "encoding",
+    "malformed_xml",
+    "unclosed_element",
+    "mismatched_end_tag",
+    "missing_patient_id",
+    "invalid_date",
+];
+
+impl ParseMode {
+    pub fn rejects(&self, warning: &ParseWarning) -> bool {
+        *self == ParseMode::Strict && STRICT_ERRORS.contains(&warning.code.as_str())
+    }
+
+    pub fn from_name(name: &str) -> Option<ParseMode> {
+        match name.trim().to_lowercase().as_str() {
+            "standard" => Some(ParseMode::Standard),
+            "lenient" => Some(ParseMode::Lenient),
+            "strict" => Some(ParseMode::Strict),
+            _ => None,
+        }
+    }
+}
+
+// Problema no fatal: XML recuperado, identificador ausente, fecha ilegible...
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ParseWarning {
+    pub code: String,
+    pub message: String,
+    #[serde(flatten)]
+    pub position: Option<Position>,
+}
+
+impl ParseWarning {
+    pub fn new(code: &str, message: String, position: Option<Position>) -> Self {
+        ParseWarning { code: code.to_string(), message, position }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CdaError {
+    MalformedXml {
+        message: String,
+        #[serde(flatten)]
+        position: Position,
+    },
+    UnexpectedRoot {
+        expected: String,
+        found: Option<String>,
+        #[serde(flatten)]
+        position: Position,
+    },
+    Encoding {
+        message: String,
+        #[serde(flatten)]
+        position: Position,
+    },
+    LimitExceeded {
+        limit: String,
+        max: usize,
+        actual: usize,
+        #[serde(flatten)]
+        position: Position,
+    },
+    // Aviso promovido a error en modo estricto
+    InvalidData(ParseWarning),
+}
+
+impl CdaError {
+    pub fn from_xml(error: quick_xml::Error, position: Position) -> Self {
+        match error {
+            quick_xml::Error::NonDecodable(e) => CdaError::Encoding {
+                message: match e {
+                    Some(e) => format!("invalid UTF-8: {}", e),
+                    None => "input is not valid UTF-8".to_string(),
+                },
+                position,
+            },
+            e => CdaError::MalformedXml { message: e.to_string(), position },
+        }
+    }
+
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            CdaError::MalformedXml { position, .. }
+            | CdaError::UnexpectedRoot { position, .. }
+            | CdaError::Encoding { position, .. }
+            | CdaError::LimitExceeded { position, .. } => Some(*position),
+            CdaError::InvalidData(warning) => warning.position,
+        }
+    }
+
+    // Error de JavaScript con los campos del error como propiedades (kind, line, column...)
+    pub fn to_js(&self, file_name: &str) -> JsValue {
+        let error = js_sys::Error::new(&format!("Error parsing {}: {}", file_name, self));
+        error.set_name("CdaError");
+
+        if let Ok(details) = serde_wasm_bindgen::to_value(self) {
+            if let Ok(details) = details.dyn_into::<js_sys::Object>() {
+                js_sys::Object::assign(&error, &details);
+            }
+        }
+        let _ = js_sys::Reflect::set(&error, &JsValue::from_str("file_name"), &JsValue::from_str(file_name));
+        error.into()
+    }
+}
+
+impl fmt::Display for CdaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location = match self.position() {
+            Some(position) => format!("line {}, column {}", position.line, position.column),
+            None => "document level".to_string(),
+        };
+        match self {
+            CdaError::MalformedXml { message, .. } => write!(f, "malformed XML at {}: {}", location, message),
+            CdaError::UnexpectedRoot { expected, found: Some(found), .. } => {
+                write!(f, "unexpected root element <{}> at {}, expected <{}>", found, location, expected)
+            }
+            CdaError::UnexpectedRoot { expected, found: None, .. } => {
+                write!(f, "no root element found, expected <{}>", expected)
+            }
+            CdaError::Encoding { message, .. } => write!(f, "encoding error at {}: {}", location, message),
+            CdaError::LimitExceeded { limit, max, actual, .. } => {
+                write!(f, "{} limit exceeded at {}: {} > {}", limit, location, actual, max)
+            }
+            CdaError::InvalidData(warning) => write!(f, "{} at {}: {}", warning.code, location, warning.message),
+        }
+    }
+}
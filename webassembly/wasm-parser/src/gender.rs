@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+
+use crate::code_systems;
+
+pub const MALE: &str = "M";
+pub const FEMALE: &str = "F";
+pub const UNDIFFERENTIATED: &str = "Undifferentiated";
+pub const OTHER: &str = "Other";
+pub const UNKNOWN: &str = "Unknown";
+
+// Código de sexo o identidad de género tal como viene en el documento, con su categoría
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GenderCoding {
+    pub normalized: String,
+    pub code: Option<String>,
+    pub code_system: Option<String>,
+    pub code_system_name: Option<String>,
+    pub display_name: Option<String>,
+}
+
+// Vocabulario de <administrativeGenderCode> y sistemas reconocidos en la tabla
+pub const ADMINISTRATIVE_GENDER: &str = "2.16.840.1.113883.5.1";
+const V2_ADMINISTRATIVE_SEX: &str = "2.16.840.1.113883.12.1";
+const FHIR_ADMINISTRATIVE_GENDER: &str = "http://hl7.org/fhir/administrative-gender";
+const NULL_FLAVOR: &str = "2.16.840.1.113883.5.1008";
+
+// Tabla de equivalencias por sistema de codificación: (sistema, código, categoría). Las letras
+// locales sin sistema ("H", "V") sólo valen cuando el documento no declara codeSystem
+const GENDER_TABLE: [(Option<&str>, &str, &str); 44] = [
+    // HL7 v3 AdministrativeGender
+    (Some(ADMINISTRATIVE_GENDER), "M", MALE),
+    (Some(ADMINISTRATIVE_GENDER), "F", FEMALE),
+    (Some(ADMINISTRATIVE_GENDER), "UN", UNDIFFERENTIATED),
+    // HL7 v2, tabla 0001
+    (Some(V2_ADMINISTRATIVE_SEX), "M", MALE),
+    (Some(V2_ADMINISTRATIVE_SEX), "F", FEMALE),
+    (Some(V2_ADMINISTRATIVE_SEX), "A", UNDIFFERENTIATED),
+    (Some(V2_ADMINISTRATIVE_SEX), "O", OTHER),
+    (Some(V2_ADMINISTRATIVE_SEX), "U", UNKNOWN),
+    (Some(V2_ADMINISTRATIVE_SEX), "N", UNKNOWN),
+    // FHIR
+    (Some(FHIR_ADMINISTRATIVE_GENDER), "MALE", MALE),
+    (Some(FHIR_ADMINISTRATIVE_GENDER), "FEMALE", FEMALE),
+    (Some(FHIR_ADMINISTRATIVE_GENDER), "OTHER", OTHER),
+    (Some(FHIR_ADMINISTRATIVE_GENDER), "UNKNOWN", UNKNOWN),
+    // HL7 NullFlavor
+    (Some(NULL_FLAVOR), "OTH", OTHER),
+    (Some(NULL_FLAVOR), "UNK", UNKNOWN),
+    // SNOMED CT: sexo
+    (Some(code_systems::SNOMED_CT), "248153007", MALE),
+    (Some(code_systems::SNOMED_CT), "248152002", FEMALE),
+    (Some(code_systems::SNOMED_CT), "32570681000036106", UNDIFFERENTIATED),
+    (Some(code_systems::SNOMED_CT), "74964007", OTHER),
+    (Some(code_systems::SNOMED_CT), "261665006", UNKNOWN),
+    // SNOMED CT: identidad de género. Los conceptos de transexualidad no equivalen a un sexo
+    (Some(code_systems::SNOMED_CT), "446151000124109", MALE),
+    (Some(code_systems::SNOMED_CT), "446141000124107", FEMALE),
+    (Some(code_systems::SNOMED_CT), "407377005", OTHER),
+    (Some(code_systems::SNOMED_CT), "407376001", OTHER),
+    (Some(code_systems::SNOMED_CT), "446131000124102", OTHER),
+    (Some(code_systems::SNOMED_CT), "33791000087105", OTHER),
+    (Some(code_systems::SNOMED_CT), "394743007", UNKNOWN),
+    // Códigos locales en español, sin sistema
+    (None, "H", MALE),
+    (None, "V", MALE),
+    // Texto libre en inglés y español
+    (None, "MALE", MALE),
+    (None, "FEMALE", FEMALE),
+    (None, "AMBIGUOUS", UNDIFFERENTIATED),
+    (None, "UNDIFFERENTIATED", UNDIFFERENTIATED),
+    (None, "OTHER", OTHER),
+    (None, "UNKNOWN", UNKNOWN),
+    (None, "HOMBRE", MALE),
+    (None, "VARON", MALE),
+    (None, "MASCULINO", MALE),
+    (None, "MUJER", FEMALE),
+    (None, "FEMENINO", FEMALE),
+    (None, "INDETERMINADO", UNDIFFERENTIATED),
+    (None, "INTERSEXUAL", UNDIFFERENTIATED),
+    (None, "OTRO", OTHER),
+    (None, "DESCONOCIDO", UNKNOWN),
+];
+
+// Categoría normalizada de un código en su sistema, o de un texto libre si no hay sistema.
+// Un sistema fuera de la tabla no se interpreta
+pub fn normalize(code_system: Option<&str>, code: &str) -> &'static str {
+    let key: String = code
+        .trim()
+        .to_uppercase()
+        .chars()
+        .map(|c| if c == 'Ó' { 'O' } else { c })
+        .collect();
+    let system = code_system.map(|s| code_systems::resolve(s).unwrap_or_else(|| s.trim().to_string()));
+
+    GENDER_TABLE
+        .iter()
+        .find(|(s, k, _)| s.as_deref() == system.as_deref() && *k == key)
+        .map(|(_, _, v)| *v)
+        .unwrap_or(UNKNOWN)
+}
+
+// Código sin sistema o texto de una tabla de configuración: letra de AdministrativeGender
+// ("M", "F", "UN"), código local ("H", "V") o palabra
+pub fn from_text(text: &str) -> &'static str {
+    match normalize(Some(ADMINISTRATIVE_GENDER), text) {
+        UNKNOWN => normalize(None, text),
+        normalized => normalized,
+    }
+}
+
+impl GenderCoding {
+    // Usa el código en su sistema (sin codeSystem, AdministrativeGender o código local) y, si no
+    // se reconoce, el displayName como texto libre
+    pub fn new(code: Option<String>, code_system: Option<String>, display_name: Option<String>) -> Self {
+        let normalized = code
+            .as_deref()
+            .map(|c| match code_system.as_deref() {
+                Some(system) => normalize(Some(system), c),
+                None => from_text(c),
+            })
+            .filter(|n| *n != UNKNOWN)
+            .or_else(|| display_name.as_deref().map(|d| normalize(None, d)))
+            .unwrap_or(UNKNOWN);
+
+        GenderCoding {
+            normalized: normalized.to_string(),
+            code,
+            code_system,
+            code_system_name: None,
+            display_name,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coding(code: Option<&str>, code_system: Option<&str>, display_name: Option<&str>) -> String {
+        GenderCoding::new(code.map(String::from), code_system.map(String::from), display_name.map(String::from)).normalized
+    }
+
+    #[test]
+    fn local_letters_without_code_system() {
+        assert_eq!(coding(Some("H"), None, None), MALE);
+        assert_eq!(coding(Some("V"), None, None), MALE);
+        assert_eq!(coding(Some("M"), None, None), MALE);
+        assert_eq!(coding(Some("F"), None, None), FEMALE);
+    }
+
+    #[test]
+    fn local_letters_need_no_code_system() {
+        assert_eq!(coding(Some("H"), Some(ADMINISTRATIVE_GENDER), None), UNKNOWN);
+        assert_eq!(coding(Some("H"), Some(ADMINISTRATIVE_GENDER), Some("Hombre")), MALE);
+    }
+
+    #[test]
+    fn spanish_words() {
+        assert_eq!(coding(Some("hombre"), None, None), MALE);
+        assert_eq!(coding(Some("mujer"), None, None), FEMALE);
+        assert_eq!(coding(Some("X"), None, Some("Mujer")), FEMALE);
+        assert_eq!(coding(None, None, Some("Varón")), MALE);
+    }
+
+    #[test]
+    fn undifferentiated() {
+        assert_eq!(coding(Some("UN"), Some(ADMINISTRATIVE_GENDER), None), UNDIFFERENTIATED);
+        assert_eq!(coding(Some("UN"), None, None), UNDIFFERENTIATED);
+    }
+
+    #[test]
+    fn gender_identity_snomed() {
+        assert_eq!(coding(Some("446141000124107"), Some(code_systems::SNOMED_CT), None), FEMALE);
+        assert_eq!(coding(Some("446151000124109"), Some(code_systems::SNOMED_CT), None), MALE);
+        assert_eq!(coding(Some("407377005"), Some(code_systems::SNOMED_CT), None), OTHER);
+        assert_eq!(coding(Some("33791000087105"), Some(code_systems::SNOMED_CT), None), OTHER);
+    }
+}
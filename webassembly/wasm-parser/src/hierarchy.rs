@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+
+use crate::code_systems;
+use crate::dictionary::Dictionaries;
+use crate::icd10;
+use crate::tables::normalize_header;
+use crate::terminology::Terminology;
+use crate::Diagnosis;
+
+// Concepto raíz de SNOMED CT y relación "es un"
+const SNOMED_ROOT: &str = "138875005";
+const IS_A: &str = "116680003";
+const DEFAULT_SNOMED_DEPTH: usize = 3;
+
+// Nivel al que se agrupan los diagnósticos en las estadísticas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollupLevel {
+    // Nombre del diagnóstico pasado por title_mappings ("diabético" -> "Diabetes"), sin distinguir
+    // mayúsculas, tildes ni espacios. Nombres distintos del mismo concepto ("Diabetes mellitus
+    // tipo 2") sólo se agrupan en los niveles codificados
+    Name,
+    Icd10Category,
+    Icd10Block,
+    Icd10Chapter,
+    // Antecesor SNOMED CT a esa profundidad desde la raíz
+    SnomedAncestor(usize),
+}
+
+impl RollupLevel {
+    // "name", "icd10_category", "icd10_block", "icd10_chapter", "snomed" o "snomed:4"
+    pub fn from_name(name: &str) -> Option<RollupLevel> {
+        let name = name.trim().to_lowercase();
+        match name.as_str() {
+            "name" => Some(RollupLevel::Name),
+            "icd10_category" => Some(RollupLevel::Icd10Category),
+            "icd10_block" => Some(RollupLevel::Icd10Block),
+            "icd10_chapter" => Some(RollupLevel::Icd10Chapter),
+            "snomed" => Some(RollupLevel::SnomedAncestor(DEFAULT_SNOMED_DEPTH)),
+            _ => {
+                let depth = name.strip_prefix("snomed:")?.trim().parse().ok()?;
+                Some(RollupLevel::SnomedAncestor(depth))
+            }
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            RollupLevel::Name => "name".to_string(),
+            RollupLevel::Icd10Category => "icd10_category".to_string(),
+            RollupLevel::Icd10Block => "icd10_block".to_string(),
+            RollupLevel::Icd10Chapter => "icd10_chapter".to_string(),
+            RollupLevel::SnomedAncestor(depth) => format!("snomed:{}", depth),
+        }
+    }
+}
+
+// Grupo de un diagnóstico: clave estable, código del grupo y nombre canónico si se conoce.
+// Sin nombre canónico se usa el nombre más frecuente entre los diagnósticos del grupo
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RollupGroup {
+    pub key: String,
+    pub code: Option<String>,
+    pub name: Option<String>,
+}
+
+// Relaciones "es un" de SNOMED CT: concepto -> padres
+#[derive(Debug, Clone, Default)]
+pub struct SnomedHierarchy {
+    parents: HashMap<String, Vec<String>>,
+}
+
+impl SnomedHierarchy {
+    // Fichero sct2_Relationship (o StatedRelationship) RF2, tabulado y con cabecera.
+    // Devuelve el número de relaciones "es un" activas cargadas
+    pub fn load(&mut self, content: &str, replace: bool) -> Result<usize, String> {
+        let mut lines = content.lines().filter(|l| !l.trim().is_empty());
+        let headers: Vec<String> = lines
+            .next()
+            .ok_or("empty relationship file")?
+            .split('\t')
+            .map(|h| h.trim().trim_start_matches('\u{FEFF}').to_lowercase())
+            .collect();
+        let column = |name: &str| headers.iter().position(|h| h == name);
+        let source = column("sourceid").ok_or("relationship file has no sourceId column")?;
+        let destination = column("destinationid").ok_or("relationship file has no destinationId column")?;
+        let (active, type_id) = (column("active"), column("typeid"));
+
+        let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+        let mut count = 0;
+        for line in lines {
+            let fields: Vec<&str> = line.split('\t').map(|f| f.trim()).collect();
+            let field = |index: Option<usize>| index.and_then(|i| fields.get(i).copied());
+            if field(active) == Some("0") || field(type_id).is_some_and(|t| t != IS_A) {
+                continue;
+            }
+            if let (Some(child), Some(parent)) = (field(Some(source)), field(Some(destination))) {
+                parents.entry(child.to_string()).or_default().push(parent.to_string());
+                count += 1;
+            }
+        }
+
+        if replace {
+            self.parents.clear();
+        }
+        for (child, mut new) in parents {
+            let existing = self.parents.entry(child).or_default();
+            existing.append(&mut new);
+            existing.sort();
+            existing.dedup();
+        }
+        Ok(count)
+    }
+
+    // Antecesor (o el propio concepto) a `depth` pasos de la raíz por el camino más corto.
+    // Con varios candidatos, el de menor SCTID, para que el grupo no dependa del orden de carga
+    pub fn ancestor_at_depth(&self, concept: &str, depth: usize) -> Option<String> {
+        if !self.parents.contains_key(concept) {
+            return None;
+        }
+        let mut depths: HashMap<String, usize> = HashMap::new();
+        let concept_depth = self.depth(concept, &mut depths, 0);
+        if concept_depth <= depth {
+            return Some(concept.to_string());
+        }
+
+        let mut candidates: Vec<&String> = depths.iter().filter(|(_, d)| **d == depth).map(|(c, _)| c).collect();
+        candidates.sort_by_key(|c| (c.len(), c.to_string()));
+        candidates.first().map(|c| c.to_string())
+    }
+
+    // El concepto es el antecesor o desciende de él por relaciones "es un"
+    pub fn is_descendant(&self, concept: &str, ancestor: &str) -> bool {
+        let mut pending = vec![concept];
+        let mut visited: Vec<&str> = Vec::new();
+        while let Some(current) = pending.pop() {
+            if current == ancestor {
+                return true;
+            }
+            if visited.contains(&current) {
+                continue;
+            }
+            visited.push(current);
+            if let Some(parents) = self.parents.get(current) {
+                pending.extend(parents.iter().map(|p| p.as_str()));
+            }
+        }
+        false
+    }
+
+    // Profundidad mínima desde la raíz; los conceptos sin padres cuentan como raíz
+    fn depth(&self, concept: &str, depths: &mut HashMap<String, usize>, guard: usize) -> usize {
+        if let Some(depth) = depths.get(concept) {
+            return *depth;
+        }
+        let depth = match self.parents.get(concept) {
+            // El límite evita recorrer ciclos de un fichero mal formado
+            Some(parents) if concept != SNOMED_ROOT && guard < 64 => {
+                parents.iter().map(|p| self.depth(p, depths, guard + 1)).min().unwrap_or(0) + 1
+            }
+            _ => 0,
+        };
+        depths.insert(concept.to_string(), depth);
+        depth
+    }
+
+    // Agrupa un diagnóstico al nivel pedido; sin código utilizable, por su nombre
+    pub fn rollup(
+        &self,
+        diagnosis: &Diagnosis,
+        level: RollupLevel,
+        terminology: &Terminology,
+        dictionaries: &Dictionaries,
+    ) -> RollupGroup {
+        let icd10 = diagnosis.icd10.first().map(|c| c.code.as_str());
+        let group = match level {
+            RollupLevel::Name => None,
+            RollupLevel::Icd10Category => icd10.and_then(icd10::category_of).map(|category| {
+                let name = [code_systems::ICD10, code_systems::ICD10_CM]
+                    .iter()
+                    .find_map(|system| terminology.lookup(system, &category))
+                    .and_then(|names| names.preferred())
+                    .map(|title| format!("{} {}", category, title));
+                RollupGroup { key: format!("icd10:{}", category), code: Some(category), name }
+            }),
+            RollupLevel::Icd10Block => icd10.and_then(icd10::block_of).map(|(range, title)| RollupGroup {
+                key: format!("icd10:{}", range),
+                name: Some(format!("{} {}", range, title)),
+                code: Some(range),
+            }),
+            RollupLevel::Icd10Chapter => icd10.and_then(icd10::chapter_of).and_then(|chapter| {
+                let (range, title) = icd10::chapter_info(chapter)?;
+                Some(RollupGroup {
+                    key: format!("icd10:{}", chapter),
+                    code: Some(range),
+                    name: Some(format!("{} {}", chapter, title)),
+                })
+            }),
+            RollupLevel::SnomedAncestor(depth) => diagnosis
+                .code_in(&[code_systems::SNOMED_CT])
+                .and_then(|code| self.ancestor_at_depth(code, depth).or_else(|| Some(code.to_string())))
+                .map(|ancestor| RollupGroup {
+                    key: format!("snomed:{}", ancestor),
+                    name: terminology
+                        .lookup(code_systems::SNOMED_CT, &ancestor)
+                        .and_then(|names| names.preferred()),
+                    code: Some(ancestor),
+                }),
+        };
+
+        group.unwrap_or_else(|| {
+            // El sinónimo del diccionario da nombre al grupo: "diabético" cuenta como "Diabetes"
+            let canonical = dictionaries.diagnosis_name(&diagnosis.name);
+            let key = name_key(&canonical);
+            RollupGroup {
+                name: Some(canonical).filter(|_| key != name_key(&diagnosis.name)),
+                key: format!("name:{}", key),
+                code: None,
+            }
+        })
+    }
+}
+
+// "  Diabetes  Mellitus" y "diabetes mellitus" son el mismo grupo
+pub fn name_key(name: &str) -> String {
+    normalize_header(name).split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[derive(Debug, Default)]
+struct Tally {
+    code: Option<String>,
+    name: Option<String>,
+    documents: u32,
+    last_document: Option<usize>,
+    // Nombres de los diagnósticos del grupo y sus apariciones
+    names: HashMap<String, u32>,
+}
+
+// Recuento de documentos por grupo: varios diagnósticos de un documento en el mismo grupo cuentan una vez
+#[derive(Debug, Default)]
+pub struct RollupCounter {
+    groups: HashMap<String, Tally>,
+}
+
+impl RollupCounter {
+    pub fn add(&mut self, document: usize, group: RollupGroup, diagnosis_name: &str) {
+        let tally = self.groups.entry(group.key).or_default();
+        tally.code = group.code;
+        tally.name = tally.name.take().or(group.name);
+        if tally.last_document != Some(document) {
+            tally.last_document = Some(document);
+            tally.documents += 1;
+        }
+        *tally.names.entry(diagnosis_name.trim().to_string()).or_insert(0) += 1;
+    }
+
+    // (código, nombre canónico, documentos), de más a menos frecuente y por nombre en caso de empate
+    pub fn top(self, limit: usize) -> Vec<(Option<String>, String, u32)> {
+        let mut groups: Vec<(Option<String>, String, u32)> = self
+            .groups
+            .into_values()
+            .map(|tally| {
+                let name = tally.name.unwrap_or_else(|| {
+                    let mut names: Vec<(String, u32)> = tally.names.into_iter().collect();
+                    names.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+                    names.into_iter().next().map(|(n, _)| n).unwrap_or_default()
+                });
+                (tally.code, name, tally.documents)
+            })
+            .collect();
+        groups.sort_by(|a, b| b.2.cmp(&a.2).then(a.1.cmp(&b.1)));
+        groups.truncate(limit);
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name_group(name: &str) -> RollupGroup {
+        let diagnosis = Diagnosis { name: name.to_string(), ..Default::default() };
+        SnomedHierarchy::default().rollup(&diagnosis, RollupLevel::Name, &Terminology::default(), &Dictionaries::defaults())
+    }
+
+    #[test]
+    fn name_rollup_merges_title_mapping_synonyms() {
+        assert_eq!(name_group("diabético").key, name_group("Diabetes").key);
+        assert_eq!(name_group("  DIABETES ").key, name_group("Diabetes").key);
+        assert_eq!(name_group("hipertenso").key, name_group("Hipertensión").key);
+        assert_eq!(name_group("hipertenso").name.as_deref(), Some("Hipertensión"));
+    }
+
+    #[test]
+    fn name_rollup_keeps_other_names_apart() {
+        assert_ne!(name_group("Diabetes mellitus tipo 2").key, name_group("Diabetes").key);
+    }
+
+    #[test]
+    fn counter_counts_documents_once_per_group() {
+        let mut counter = RollupCounter::default();
+        counter.add(0, name_group("Diabetes"), "Diabetes");
+        counter.add(0, name_group("diabético"), "diabético");
+        counter.add(1, name_group("diabético"), "diabético");
+        counter.add(1, name_group("Diabetes mellitus tipo 2"), "Diabetes mellitus tipo 2");
+        assert_eq!(
+            counter.top(10),
+            [
+                (None, "Diabetes".to_string(), 2),
+                (None, "Diabetes mellitus tipo 2".to_string(), 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+// Bloques de la CIE-10, embebidos en el WASM
+const BLOCKS_TSV: &str = include_str!("../resources/icd10_blocks.tsv");
+
+static BLOCKS: Lazy<Vec<(&'static str, &'static str, &'static str)>> = Lazy::new(|| {
+    BLOCKS_TSV
+        .lines()
+        .filter(|l| !l.starts_with('#') && !l.trim().is_empty())
+        .filter_map(|l| {
+            let mut fields = l.splitn(3, '\t');
+            Some((fields.next()?, fields.next()?, fields.next()?.trim()))
+        })
+        .collect()
+});
+
+// Capítulos de la CIE-10: número, primer y último código de tres caracteres, título
+const CHAPTERS: [(&str, &str, &str, &str); 22] = [
+    ("I", "A00", "B99", "Ciertas enfermedades infecciosas y parasitarias"),
+    ("II", "C00", "D49", "Neoplasias"),
+    ("III", "D50", "D89", "Enfermedades de la sangre y de los órganos hematopoyéticos y ciertos trastornos de la inmunidad"),
+    ("IV", "E00", "E90", "Enfermedades endocrinas, nutricionales y metabólicas"),
+    ("V", "F00", "F99", "Trastornos mentales y del comportamiento"),
+    ("VI", "G00", "G99", "Enfermedades del sistema nervioso"),
+    ("VII", "H00", "H59", "Enfermedades del ojo y sus anexos"),
+    ("VIII", "H60", "H95", "Enfermedades del oído y de la apófisis mastoides"),
+    ("IX", "I00", "I99", "Enfermedades del sistema circulatorio"),
+    ("X", "J00", "J99", "Enfermedades del sistema respiratorio"),
+    ("XI", "K00", "K95", "Enfermedades del sistema digestivo"),
+    ("XII", "L00", "L99", "Enfermedades de la piel y del tejido subcutáneo"),
+    ("XIII", "M00", "M99", "Enfermedades del sistema osteomuscular y del tejido conectivo"),
+    ("XIV", "N00", "N99", "Enfermedades del sistema genitourinario"),
+    ("XV", "O00", "O9A", "Embarazo, parto y puerperio"),
+    ("XVI", "P00", "P96", "Ciertas afecciones originadas en el periodo perinatal"),
+    ("XVII", "Q00", "Q99", "Malformaciones congénitas, deformidades y anomalías cromosómicas"),
+    ("XVIII", "R00", "R99", "Síntomas, signos y hallazgos anormales clínicos y de laboratorio"),
+    ("XIX", "S00", "T98", "Traumatismos, envenenamientos y otras consecuencias de causas externas"),
+    ("XX", "V00", "Y99", "Causas externas de morbilidad y mortalidad"),
+    ("XXI", "Z00", "Z99", "Factores que influyen en el estado de salud y contacto con los servicios sanitarios"),
+    ("XXII", "U00", "U99", "Códigos para propósitos especiales"),
+];
+
+// Código CIE-10 asociado a un diagnóstico, del propio documento o del mapeo SNOMED CT
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Icd10Code {
+    pub code: String,
+    // Capítulo en números romanos ("IX")
+    pub chapter: Option<String>,
+    // "document" o "crosswalk"
+    pub source: String,
+    pub map_group: Option<u32>,
+    pub map_rule: Option<String>,
+    pub map_advice: Option<String>,
+}
+
+impl Icd10Code {
+    pub fn from_document(code: &str) -> Self {
+        let code = normalize(code);
+        Icd10Code {
+            chapter: chapter_of(&code).map(|c| c.to_string()),
+            code,
+            source: "document".to_string(),
+            map_group: None,
+            map_rule: None,
+            map_advice: None,
+        }
+    }
+}
+
+// "e11.9" -> "E11.9"
+pub fn normalize(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+// Categoría de tres caracteres: "E11.9" -> "E11"
+pub fn category_of(code: &str) -> Option<String> {
+    let category: String = normalize(code).chars().filter(|c| *c != '.').take(3).collect();
+    if category.len() < 3 {
+        None
+    } else {
+        Some(category)
+    }
+}
+
+// Capítulo de un código por sus tres primeros caracteres
+pub fn chapter_of(code: &str) -> Option<&'static str> {
+    let category = category_of(code)?;
+    CHAPTERS
+        .iter()
+        .find(|(_, first, last, _)| category.as_str() >= *first && category.as_str() <= *last)
+        .map(|(chapter, _, _, _)| *chapter)
+}
+
+// Rango ("E10-E14") y título del bloque de un código
+pub fn block_of(code: &str) -> Option<(String, &'static str)> {
+    let category = category_of(code)?;
+    BLOCKS
+        .iter()
+        .find(|(first, last, _)| category.as_str() >= *first && category.as_str() <= *last)
+        .map(|(first, last, title)| (format!("{}-{}", first, last), *title))
+}
+
+// Rango ("I00-I99") y título de un capítulo
+pub fn chapter_info(chapter: &str) -> Option<(String, &'static str)> {
+    CHAPTERS
+        .iter()
+        .find(|(c, _, _, _)| *c == chapter)
+        .map(|(_, first, last, title)| (format!("{}-{}", first, last), *title))
+}
+
+// Orden de los capítulos en la clasificación (I, II, ... XXII)
+pub fn chapter_order(chapter: &str) -> usize {
+    CHAPTERS.iter().position(|(c, _, _, _)| *c == chapter).unwrap_or(CHAPTERS.len())
+}
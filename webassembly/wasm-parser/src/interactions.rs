@@ -0,0 +1,126 @@
+use once_cell::sync::Lazy;
+
+use crate::alerts::{self, Alert, DrugMatcher, RuleTable, Severity};
+use crate::Medication;
+
+// Tabla por defecto, embebida en el WASM
+const DEFAULT_TABLE: &str = include_str!("../resources/interactions.tsv");
+
+const DRUG_A_HEADERS: [&str; 3] = ["drug_a", "medicamento_a", "farmaco_a"];
+const DRUG_B_HEADERS: [&str; 3] = ["drug_b", "medicamento_b", "farmaco_b"];
+const SEVERITY_HEADERS: [&str; 2] = ["severity", "gravedad"];
+const DESCRIPTION_HEADERS: [&str; 2] = ["description", "descripcion"];
+const MANAGEMENT_HEADERS: [&str; 3] = ["management", "manejo", "recomendacion"];
+
+static DEFAULTS: Lazy<InteractionTable> = Lazy::new(|| {
+    let mut table = InteractionTable::default();
+    table.load(DEFAULT_TABLE, true).expect("resources/interactions.tsv no es válido");
+    table
+});
+
+#[derive(Debug, Clone)]
+struct Interaction {
+    drug_a: DrugMatcher,
+    drug_b: DrugMatcher,
+    rule: String,
+    severity: Severity,
+    description: String,
+    management: Option<String>,
+}
+
+// Interacciones entre pares de medicamentos
+#[derive(Debug, Clone, Default)]
+pub struct InteractionTable {
+    interactions: Vec<Interaction>,
+}
+
+impl InteractionTable {
+    pub fn defaults() -> InteractionTable {
+        DEFAULTS.clone()
+    }
+
+    // CSV o TSV con cabecera: drug_a, drug_b (prefijo ATC o principio activo), severity
+    // (minor, moderate, major, contraindicated), description y management opcional.
+    // Devuelve el número de interacciones cargadas
+    pub fn load(&mut self, content: &str, replace: bool) -> Result<usize, String> {
+        let table = RuleTable::parse(content)?;
+        let drug_a = table.required(&DRUG_A_HEADERS)?;
+        let drug_b = table.required(&DRUG_B_HEADERS)?;
+        let severity = table.required(&SEVERITY_HEADERS)?;
+        let description = table.required(&DESCRIPTION_HEADERS)?;
+        let management = table.column(&MANAGEMENT_HEADERS);
+
+        let mut interactions = Vec::new();
+        for (number, fields) in &table.rows {
+            let drug = |index: usize| -> Result<(String, DrugMatcher), String> {
+                let text = alerts::field(fields, Some(index)).ok_or_else(|| format!("line {}: missing drug", number))?;
+                let matcher = DrugMatcher::parse(text).ok_or_else(|| format!("line {}: invalid drug '{}'", number, text))?;
+                Ok((text.to_string(), matcher))
+            };
+            let (name_a, drug_a) = drug(drug_a)?;
+            let (name_b, drug_b) = drug(drug_b)?;
+            let severity_name = alerts::field(fields, Some(severity)).unwrap_or_default();
+            let severity = Severity::from_name(severity_name)
+                .ok_or_else(|| format!("line {}: unknown severity '{}'", number, severity_name))?;
+            let description = alerts::field(fields, Some(description))
+                .ok_or_else(|| format!("line {}: missing description", number))?;
+
+            interactions.push(Interaction {
+                drug_a,
+                drug_b,
+                rule: format!("{} + {}", name_a, name_b),
+                severity,
+                description: description.to_string(),
+                management: alerts::field(fields, management).map(|m| m.to_string()),
+            });
+        }
+
+        let count = interactions.len();
+        if replace {
+            self.interactions.clear();
+        }
+        self.interactions.append(&mut interactions);
+        Ok(count)
+    }
+
+    // Cada par de medicamentos distintos contra todas las interacciones; si un par cumple
+    // varias, la alerta es la más grave
+    pub fn check(&self, medications: &[Medication]) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for (index, first) in medications.iter().enumerate() {
+            for second in &medications[index + 1..] {
+                let mut best: Option<Alert> = None;
+                for interaction in &self.interactions {
+                    let alert = match self.alert(interaction, first, second) {
+                        Some(alert) => alert,
+                        None => continue,
+                    };
+                    if best.as_ref().is_none_or(|b| alert.severity > b.severity) {
+                        best = Some(alert);
+                    }
+                }
+                alerts.extend(best);
+            }
+        }
+        alerts
+    }
+
+    fn alert(&self, interaction: &Interaction, first: &Medication, second: &Medication) -> Option<Alert> {
+        let matches = |a: &Medication, b: &Medication| {
+            Some((
+                interaction.drug_a.matches("medication", &a.name, &a.atc)?,
+                interaction.drug_b.matches("medication", &b.name, &b.atc)?,
+            ))
+        };
+        let (trigger_a, trigger_b) = matches(first, second).or_else(|| matches(second, first))?;
+        Some(Alert {
+            alert_type: "drug_interaction".to_string(),
+            severity: interaction.severity,
+            rule: interaction.rule.clone(),
+            description: interaction.description.clone(),
+            management: interaction.management.clone(),
+            triggers: vec![trigger_a, trigger_b],
+            confidence: first.confidence.min(second.confidence),
+        })
+    }
+}
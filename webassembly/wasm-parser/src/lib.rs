@@ -6,6 +6,67 @@ use std::collections::HashMap;
 use once_cell::sync::Lazy;
 use regex::Regex;
 
+mod alerts;
+mod atc;
+mod attachments;
+mod code_systems;
+mod contraindications;
+mod crosswalk;
+mod datatypes;
+mod derived;
+mod dictionary;
+mod error;
+mod gender;
+mod hierarchy;
+mod icd10;
+mod interactions;
+mod narrative;
+mod null_flavor;
+mod person;
+mod provenance;
+mod reference_ranges;
+mod strength;
+mod tables;
+mod terminology;
+mod ucum;
+
+use alerts::Alert;
+use atc::{AtcCode, AtcTable};
+use attachments::Attachment;
+use code_systems::{CodeSystemReport, UnknownCodeSystem};
+use contraindications::ContraindicationTable;
+use crosswalk::{Crosswalk, MapContext};
+use datatypes::{CodedValue, ElementBuilder, PhysicalQuantity, RawElement, Value};
+use derived::Derivation;
+use dictionary::Dictionaries;
+use error::{CdaError, ParseMode, ParseWarning, Position};
+use gender::GenderCoding;
+use hierarchy::{RollupCounter, RollupLevel, SnomedHierarchy};
+use icd10::Icd10Code;
+use interactions::InteractionTable;
+use narrative::{NarrativeBuilder, NarrativeReference, Section};
+use null_flavor::{NullFlavor, NullFlavors};
+use person::{PersonName, RelatedPerson};
+use reference_ranges::{AbnormalFlag, ReferenceRangeTable};
+use provenance::{Evidence, ExtractionMethod, DISPLAY_NAME_CONFIDENCE, DISPLAY_NAME_OUTSIDE_PROBLEMS_CONFIDENCE};
+use strength::{Ingredient, MedicationGrouping};
+use tables::{NarrativeTable, TableKind};
+use terminology::{TermNames, Terminology};
+use ucum::{CanonicalUnits, NormalizedValue};
+
+// Límites de entrada: tamaño del documento y profundidad de anidamiento
+const MAX_DOCUMENT_BYTES: usize = 64 * 1024 * 1024;
+const MAX_DEPTH: usize = 256;
+
+static VALUE_WITH_UNIT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^([<>]?=?\s*-?\d+(?:[.,]\d+)?)\s*(.*)$").unwrap()
+});
+
+// "alergia a penicilina", "alérgico al ibuprofeno", "allergic to aspirin"
+static ALLERGY_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(?:alergia|al[eé]rgic[oa]|allergy|allergic)\s+(?:a|al|to)\s+(?:la\s+|las\s+|los\s+|el\s+)?([a-záéíóúñü]{4,})").unwrap()
+});
+
 static MED_PATTERNS: Lazy<Vec<Regex>> = Lazy::new(|| {
     vec![
         Regex::new(r"(?i)\b\w+(?:cillin|mycin|prazole|statin|tide|pine|zole|pril|sartan)\b").unwrap(),
@@ -28,51 +89,252 @@ macro_rules! console_log {
 }
 
 // Estructuras de datos para CDA
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Patient {
     pub id: Option<String>,
     pub name: Option<String>,
+    pub names: Vec<PersonName>,
+    // M, F, Undifferentiated, Other o Unknown
     pub gender: Option<String>,
+    pub gender_coding: Option<GenderCoding>,
+    pub gender_identity: Option<GenderCoding>,
     pub birth_date: Option<String>,
     pub age: Option<u32>,
+    pub deceased: Option<bool>,
+    pub deceased_time: Option<String>,
+    pub guardians: Vec<RelatedPerson>,
+    // Participantes de cabecera: contacto de emergencia, cuidador, familiar...
+    pub contacts: Vec<RelatedPerson>,
+    pub null_flavors: NullFlavors,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Diagnosis {
     pub code: Option<String>,
     pub name: String,
     pub code_system: Option<String>,
+    // Nombre del sistema según el registro de OIDs ("SNOMED CT", "ICD-10"...)
+    pub code_system_name: Option<String>,
+    // Nombres preferidos según las tablas de terminología cargadas
+    pub display_name_es: Option<String>,
+    pub display_name_en: Option<String>,
+    // Códigos CIE-10 del documento o del mapa SNOMED CT cargado
+    pub icd10: Vec<Icd10Code>,
+    pub original_text: Option<String>,
+    pub value: Option<Value>,
+    pub null_flavors: NullFlavors,
+    pub evidence: Vec<Evidence>,
+    // Confianza combinada de todas las evidencias (0-1)
+    pub confidence: f64,
 }
 
+impl Diagnosis {
+    // Códigos de esos sistemas: el del diagnóstico, el del valor y sus traducciones
+    pub fn codes_in(&self, oids: &[&str]) -> Vec<&str> {
+        let mut coded: Vec<(Option<&str>, Option<&str>)> = vec![(self.code_system.as_deref(), self.code.as_deref())];
+        if let Some(value) = self.value.as_ref().and_then(|v| v.coded()) {
+            for c in std::iter::once(value).chain(value.translations.iter()) {
+                coded.push((c.code_system.as_deref(), c.code.as_deref()));
+            }
+        }
+
+        let mut codes: Vec<&str> = Vec::new();
+        for (code_system, code) in coded {
+            let in_system = code_system
+                .and_then(code_systems::resolve)
+                .map(|oid| oids.contains(&oid.as_str()))
+                .unwrap_or(false);
+            if let Some(code) = code.filter(|_| in_system) {
+                if !codes.contains(&code) {
+                    codes.push(code);
+                }
+            }
+        }
+        codes
+    }
+
+    pub fn code_in(&self, oids: &[&str]) -> Option<&str> {
+        self.codes_in(oids).first().copied()
+    }
+}
+
+// Condición deducida de la medicación; no es un diagnóstico del documento
 #[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InferredCondition {
+    pub name: String,
+    pub medications: Vec<String>,
+    // Hay un diagnóstico del documento que la respalda; si no, posible indicación no documentada
+    pub documented: bool,
+    pub rationale: String,
+    pub evidence: Vec<Evidence>,
+    pub confidence: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Medication {
     pub name: String,
     pub medication_type: String,
+    // Código de <manufacturedMaterial> (RxNorm, ATC, nomenclátor nacional...)
+    pub code: Option<String>,
+    pub code_system: Option<String>,
+    pub code_system_name: Option<String>,
+    // displayName del código de <manufacturedMaterial>: "Warfarin 5mg" junto a <name>Warfarina</name>
+    pub display_name: Option<String>,
+    // Clasificación ATC: del documento, por código de producto o por principio activo
+    pub atc: Vec<AtcCode>,
+    // Del displayName o del nombre: "Enalapril/Hidroclorotiazida 20/12,5 mg comprimidos" son dos principios
+    // activos con su concentración y la forma "tablet"
+    pub ingredients: Vec<Ingredient>,
+    pub dose_form: Option<String>,
+    pub dose: Option<String>,
+    pub frequency: Option<String>,
+    pub route: Option<String>,
+    pub null_flavors: NullFlavors,
+    pub evidence: Vec<Evidence>,
+    pub confidence: f64,
+}
+
+// Alergia o intolerancia: sustancia de <playingEntity> en la sección de alergias o mención en el texto
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Allergy {
+    pub name: String,
+    pub code: Option<String>,
+    pub code_system: Option<String>,
+    pub code_system_name: Option<String>,
+    // Clasificación ATC de la sustancia, si es un medicamento
+    pub atc: Vec<AtcCode>,
+    pub evidence: Vec<Evidence>,
+    pub confidence: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Observation {
+    pub code: Option<String>,
+    pub code_system: Option<String>,
+    pub code_system_name: Option<String>,
+    pub name: String,
+    pub display_name_es: Option<String>,
+    pub display_name_en: Option<String>,
+    pub category: String,
+    pub value: Option<Value>,
+    // Valor numérico en la unidad canónica de su código LOINC
+    pub normalized_value: Option<NormalizedValue>,
+    pub effective_time: Option<String>,
+    pub reference_range: Option<Value>,
+    // interpretationCode del documento ("H", "N"...) y la marca calculada a partir de él,
+    // del rango de referencia o del rango por defecto del código LOINC
+    pub interpretation_code: Option<String>,
+    pub abnormal_flag: Option<AbnormalFlag>,
+    // "structured", "narrative_table" o "calculated" (IMC, eGFR, PAM a partir de otras observaciones)
+    pub observation_type: String,
+    // Fórmula y entradas de las observaciones calculadas
+    pub derivation: Option<Derivation>,
+}
+
+// <manufacturedMaterial> abierto: nullFlavors de code/name hasta saber si hay medicamento
+struct PendingMaterial {
+    // Nombre normalizado de <name>: clave del medicamento, nuevo o fusionado con uno anterior
+    name: Option<String>,
+    display_name: Option<String>,
+    // <doseQuantity> de la <substanceAdministration> que lo contiene
+    dose: Option<String>,
+    code: Option<String>,
+    code_system: Option<String>,
+    code_system_name: Option<String>,
+    null_flavors: NullFlavors,
+}
+
+// <playingEntity> abierto en la sección de alergias: código y nombre de la sustancia
+#[derive(Default)]
+struct PendingAllergen {
+    display_name: Option<String>,
+    name: Option<String>,
+    code: Option<String>,
+    code_system: Option<String>,
+    code_system_name: Option<String>,
+}
+
+// Observación abierta durante el parseo y diagnóstico creado por su <code>
+struct PendingObservation {
+    observation: Observation,
+    diagnosis: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CDADocument {
     pub file_name: String,
     pub patient: Patient,
+    // Todos los recordTarget; `patient` es el primero
+    pub patients: Vec<Patient>,
     pub diagnoses: Vec<Diagnosis>,
+    // Sólo con la inferencia activada (set_infer_conditions)
+    pub inferred_conditions: Vec<InferredCondition>,
     pub medications: Vec<Medication>,
+    pub allergies: Vec<Allergy>,
+    pub observations: Vec<Observation>,
+    // Interacciones, contraindicaciones y conflictos con alergias
+    pub alerts: Vec<Alert>,
     pub document_date: Option<String>,
     pub author: Option<String>,
+    // <name> de cada <author><assignedAuthor><assignedPerson> de la cabecera; `author` es el preferido
+    #[serde(skip)]
+    author_names: Vec<PersonName>,
+    pub sections: Vec<Section>,
+    // Índices de las secciones abiertas durante el parseo; la última es la actual
+    #[serde(skip)]
+    open_sections: Vec<usize>,
+    pub attachments: Vec<Attachment>,
+    pub null_flavors: NullFlavors,
+    pub warnings: Vec<ParseWarning>,
+    // OIDs de codeSystem que no están en el registro
+    pub unknown_code_systems: Vec<UnknownCodeSystem>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Statistics {
     pub total_documents: u32,
     pub total_patients: u32,
+    pub active_patients: u32,
+    // Los documentos en que todos los pacientes han fallecido sólo cuentan en total_documents,
+    // total_patients y deceased_patients; el resto de agregados describe la población activa
+    pub deceased_patients: u32,
     pub average_age: f64,
     pub gender_distribution: HashMap<String, u32>,
+    // Agrupados al nivel `rollup_level`
     pub top_diagnoses: Vec<DiagnosisCount>,
+    pub rollup_level: String,
+    // Condiciones deducidas de la medicación, separadas de los diagnósticos
+    pub top_inferred_conditions: Vec<DiagnosisCount>,
+    pub undocumented_conditions: u32,
+    // Agrupados por nombre o por principio activo según `medication_grouping`
     pub top_medications: Vec<MedicationCount>,
+    pub medication_grouping: String,
+    // Medicamentos agrupados por clase ATC al nivel `atc_level`
+    pub top_therapeutic_classes: Vec<TherapeuticClassCount>,
+    pub atc_level: u8,
+    pub medications_without_atc: u32,
+    // Alertas por tipo y gravedad, documentos con alguna y reglas más frecuentes
+    pub alerts_by_type: HashMap<String, u32>,
+    pub alerts_by_severity: HashMap<String, u32>,
+    pub documents_with_alerts: u32,
+    pub top_alerts: Vec<AlertCount>,
+    pub attachment_media_types: HashMap<String, u32>,
+    pub missing_data: Vec<MissingDataCount>,
+    // Diagnósticos agrupados por capítulo CIE-10 y los que no tienen código CIE-10
+    pub icd10_chapters: Vec<Icd10ChapterCount>,
+    pub diagnoses_without_icd10: u32,
+    // Resultados numéricos por código LOINC en su unidad canónica y los que no se pudieron normalizar
+    pub lab_results: Vec<LabResultSummary>,
+    pub observations_without_canonical_unit: u32,
+    // Umbral aplicado a top_diagnoses y top_medications
+    pub min_confidence: f64,
     pub processing_time_ms: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DiagnosisCount {
+    // Código del grupo al agrupar por CIE-10 o SNOMED CT ("E10-E14", "73211009")
+    pub code: Option<String>,
     pub name: String,
     pub count: u32,
 }
@@ -83,9 +345,70 @@ pub struct MedicationCount {
     pub count: u32,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TherapeuticClassCount {
+    pub code: String,
+    pub name: Option<String>,
+    pub count: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AlertCount {
+    pub alert_type: String,
+    pub rule: String,
+    pub severity: String,
+    pub count: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LabResultSummary {
+    pub code: String,
+    pub name: String,
+    pub unit: String,
+    pub count: u32,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    // Resultados con marca H/L/HH/LL/N, los anormales y su porcentaje sobre los marcados
+    pub flagged: u32,
+    pub abnormal: u32,
+    pub abnormal_percent: Option<f64>,
+}
+
+// Datos ausentes por campo y motivo ("UNK", "ASKU"... o "missing" si no hay elemento)
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MissingDataCount {
+    pub field: String,
+    pub reason: String,
+    pub count: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Icd10ChapterCount {
+    pub chapter: String,
+    pub range: String,
+    pub title: String,
+    pub count: u32,
+}
+
 #[wasm_bindgen]
 pub struct CDAParser {
     documents: Vec<CDADocument>,
+    mode: ParseMode,
+    infer_conditions: bool,
+    dictionaries: Dictionaries,
+    terminology: Terminology,
+    crosswalk: Crosswalk,
+    snomed_hierarchy: SnomedHierarchy,
+    rollup_level: RollupLevel,
+    medication_grouping: MedicationGrouping,
+    atc_table: AtcTable,
+    // Nivel ATC de top_therapeutic_classes (1-5)
+    atc_level: u8,
+    interactions: InteractionTable,
+    contraindications: ContraindicationTable,
+    canonical_units: CanonicalUnits,
+    reference_ranges: ReferenceRangeTable,
 }
 
 #[wasm_bindgen]
@@ -95,6 +418,232 @@ impl CDAParser {
         //console_log!("CDA Parser WebAssembly inicializado");
         CDAParser {
             documents: Vec::new(),
+            mode: ParseMode::Standard,
+            infer_conditions: false,
+            dictionaries: Dictionaries::defaults(),
+            terminology: Terminology::default(),
+            crosswalk: Crosswalk::default(),
+            snomed_hierarchy: SnomedHierarchy::default(),
+            rollup_level: RollupLevel::Name,
+            medication_grouping: MedicationGrouping::Name,
+            atc_table: AtcTable::defaults(),
+            atc_level: 4,
+            interactions: InteractionTable::defaults(),
+            contraindications: ContraindicationTable::defaults(),
+            canonical_units: CanonicalUnits::defaults(),
+            reference_ranges: ReferenceRangeTable::defaults(),
+        }
+    }
+
+    // kind: "medication_names", "title_mappings", "diagnosis_keywords", "med_to_diagnosis" o "all".
+    // Sin replace=true las entradas amplían la tabla actual.
+    #[wasm_bindgen]
+    pub fn load_dictionary(&mut self, kind: &str, json: &str, replace: Option<bool>) -> Result<u32, JsValue> {
+        self.dictionaries
+            .load(kind, json, replace.unwrap_or(false))
+            .map(|count| count as u32)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Vuelve a las tablas embebidas por defecto
+    #[wasm_bindgen]
+    pub fn reset_dictionaries(&mut self) {
+        self.dictionaries = Dictionaries::defaults();
+    }
+
+    #[wasm_bindgen]
+    pub fn get_dictionaries(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.dictionaries)
+            .map_err(|e| JsValue::from_str(&format!("Error serializing dictionaries: {:?}", e)))
+    }
+
+    // Carga un índice compacto de terminología (ver build_terminology_index).
+    // Devuelve el número de códigos leídos.
+    #[wasm_bindgen]
+    pub fn load_terminology(&mut self, index: &str, replace: Option<bool>) -> Result<u32, JsValue> {
+        let count = if replace.unwrap_or(false) {
+            let mut terminology = Terminology::default();
+            let count = terminology.load_index(index);
+            if count.is_ok() {
+                self.terminology = terminology;
+            }
+            count
+        } else {
+            self.terminology.load_index(index)
+        };
+        count.map(|c| c as u32).map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Genera el índice compacto desde un extracto licenciado:
+    // format "csv" (code_system obligatorio) o "rf2" (descripciones de SNOMED CT,
+    // con el refset de idioma opcional para elegir el término preferido)
+    #[wasm_bindgen]
+    pub fn build_terminology_index(
+        &self,
+        format: &str,
+        content: &str,
+        code_system: Option<String>,
+        language_refset: Option<String>,
+    ) -> Result<String, JsValue> {
+        let terminology = match format.trim().to_lowercase().as_str() {
+            "csv" => {
+                let code_system = code_system.ok_or_else(|| JsValue::from_str("CSV terminology needs a code system"))?;
+                Terminology::from_csv(&code_system, content)
+            }
+            "rf2" => Terminology::from_rf2(content, language_refset.as_deref()),
+            other => Err(format!("Unknown terminology format: {}", other)),
+        };
+        terminology.map(|t| t.to_index()).map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Mapa SNOMED CT -> CIE-10: refset de mapa extendido RF2 (mapGroup, mapPriority, mapRule, mapTarget).
+    // Devuelve el número de filas activas cargadas.
+    #[wasm_bindgen]
+    pub fn load_icd10_map(&mut self, content: &str, replace: Option<bool>) -> Result<u32, JsValue> {
+        self.crosswalk
+            .load(content, replace.unwrap_or(false))
+            .map(|count| count as u32)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Relaciones "es un" de SNOMED CT (sct2_Relationship RF2) para agrupar por antecesores
+    #[wasm_bindgen]
+    pub fn load_snomed_relationships(&mut self, content: &str, replace: Option<bool>) -> Result<u32, JsValue> {
+        self.snomed_hierarchy
+            .load(content, replace.unwrap_or(false))
+            .map(|count| count as u32)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Nivel de top_diagnoses: "name", "icd10_category", "icd10_block", "icd10_chapter",
+    // "snomed" o "snomed:N" (antecesor a N niveles de la raíz)
+    #[wasm_bindgen]
+    pub fn set_rollup_level(&mut self, level: &str) -> Result<(), JsValue> {
+        match RollupLevel::from_name(level) {
+            Some(level) => {
+                self.rollup_level = level;
+                Ok(())
+            }
+            None => Err(JsValue::from_str(&format!("Unknown rollup level: {}", level))),
+        }
+    }
+
+    // Agrupación de top_medications: "name" o "ingredient" (principios activos, sin
+    // concentración ni forma farmacéutica)
+    #[wasm_bindgen]
+    pub fn set_medication_grouping(&mut self, grouping: &str) -> Result<(), JsValue> {
+        match MedicationGrouping::from_name(grouping) {
+            Some(grouping) => {
+                self.medication_grouping = grouping;
+                Ok(())
+            }
+            None => Err(JsValue::from_str(&format!("Unknown medication grouping: {}", grouping))),
+        }
+    }
+
+    // Tabla ATC en CSV/TSV: atc, name y opcionalmente code + code_system de producto.
+    // Amplía la tabla embebida salvo con replace=true. Devuelve el número de filas cargadas.
+    #[wasm_bindgen]
+    pub fn load_atc_table(&mut self, content: &str, replace: Option<bool>) -> Result<u32, JsValue> {
+        self.atc_table
+            .load(content, replace.unwrap_or(false))
+            .map(|count| count as u32)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Tabla de interacciones en CSV/TSV: drug_a, drug_b (prefijo ATC o principio activo),
+    // severity, description y management. Amplía la tabla embebida salvo con replace=true.
+    #[wasm_bindgen]
+    pub fn load_interactions(&mut self, content: &str, replace: Option<bool>) -> Result<u32, JsValue> {
+        self.interactions
+            .load(content, replace.unwrap_or(false))
+            .map(|count| count as u32)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Contraindicaciones en CSV/TSV: drug, condition (CIE-10, SNOMED CT o nombre) o allergy,
+    // severity, description y management. Amplía la tabla embebida salvo con replace=true.
+    #[wasm_bindgen]
+    pub fn load_contraindications(&mut self, content: &str, replace: Option<bool>) -> Result<u32, JsValue> {
+        self.contraindications
+            .load(content, replace.unwrap_or(false))
+            .map(|count| count as u32)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Unidad canónica por código LOINC en CSV/TSV: loinc, unit (UCUM) y molar_mass opcional.
+    // Amplía la tabla embebida salvo con replace=true.
+    #[wasm_bindgen]
+    pub fn load_canonical_units(&mut self, content: &str, replace: Option<bool>) -> Result<u32, JsValue> {
+        self.canonical_units
+            .load(content, replace.unwrap_or(false))
+            .map(|count| count as u32)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Rangos de referencia por defecto en CSV/TSV: loinc, sex, age_min, age_max, low, high,
+    // critical_low, critical_high y unit. Amplía la tabla embebida salvo con replace=true.
+    #[wasm_bindgen]
+    pub fn load_reference_ranges(&mut self, content: &str, replace: Option<bool>) -> Result<u32, JsValue> {
+        self.reference_ranges
+            .load(content, replace.unwrap_or(false))
+            .map(|count| count as u32)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Conversión UCUM ("mg/dL" a "mmol/L" necesita la masa molar en g/mol)
+    #[wasm_bindgen]
+    pub fn convert_units(&self, value: f64, from: &str, to: &str, molar_mass: Option<f64>) -> Result<f64, JsValue> {
+        let from = ucum::Unit::parse(from).map_err(|e| JsValue::from_str(&e))?;
+        let to = ucum::Unit::parse(to).map_err(|e| JsValue::from_str(&e))?;
+        ucum::convert(value, &from, &to, molar_mass).map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Nivel ATC de las clases terapéuticas en las estadísticas: 1 (grupo anatómico) a
+    // 5 (principio activo); por defecto 4, subgrupo químico ("C10AA")
+    #[wasm_bindgen]
+    pub fn set_atc_level(&mut self, level: u8) -> Result<(), JsValue> {
+        if !(1..=5).contains(&level) {
+            return Err(JsValue::from_str(&format!("ATC level must be between 1 and 5, got {}", level)));
+        }
+        self.atc_level = level;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_terminology(&mut self) {
+        self.terminology.clear();
+    }
+
+    // Códigos cargados por sistema
+    #[wasm_bindgen]
+    pub fn get_terminology_summary(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.terminology.summary())
+            .map_err(|e| JsValue::from_str(&format!("Error serializing terminology summary: {:?}", e)))
+    }
+
+    // Registro de sistemas de codificación: OID, nombre y URI
+    #[wasm_bindgen]
+    pub fn get_code_systems(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(code_systems::registry())
+            .map_err(|e| JsValue::from_str(&format!("Error serializing code systems: {:?}", e)))
+    }
+
+    // Deducir condiciones a partir de la medicación, en `inferred_conditions`
+    #[wasm_bindgen]
+    pub fn set_infer_conditions(&mut self, enabled: bool) {
+        self.infer_conditions = enabled;
+    }
+
+    // "standard", "lenient" (recupera XML dañado) o "strict" (los avisos son errores)
+    #[wasm_bindgen]
+    pub fn set_mode(&mut self, mode: &str) -> Result<(), JsValue> {
+        match ParseMode::from_name(mode) {
+            Some(mode) => {
+                self.mode = mode;
+                Ok(())
+            }
+            None => Err(JsValue::from_str(&format!("Unknown parse mode: {}", mode))),
         }
     }
 
@@ -107,14 +656,15 @@ impl CDAParser {
             }
             Err(e) => {
                 //console_log!("Error parseando {}: {}", file_name, e);
-                Err(JsValue::from_str(&format!("Error parsing {}: {}", file_name, e)))
+                Err(e.to_js(file_name))
             }
         }
     }
 
+    // min_confidence: sólo cuenta diagnósticos y medicamentos con al menos esa confianza
     #[wasm_bindgen]
-    pub fn get_statistics(&self) -> Result<JsValue, JsValue> {      
-        let stats = self.calculate_statistics();
+    pub fn get_statistics(&self, min_confidence: Option<f64>) -> Result<JsValue, JsValue> {      
+        let stats = self.calculate_statistics(min_confidence.unwrap_or(0.0));
         match serde_wasm_bindgen::to_value(&stats) {
             Ok(js_value) => Ok(js_value),
             Err(e) => Err(JsValue::from_str(&format!("Error serializing statistics: {:?}", e)))
@@ -136,7 +686,7 @@ impl CDAParser {
     }
 
     #[wasm_bindgen]
-    pub fn parse_files_batch(&mut self, files_data: JsValue) -> Result<JsValue, JsValue> {
+    pub fn parse_files_batch(&mut self, files_data: JsValue, min_confidence: Option<f64>) -> Result<JsValue, JsValue> {
         use std::time::Instant;
         let start_time = Instant::now();
 
@@ -170,7 +720,7 @@ impl CDAParser {
         let duration = start_time.elapsed();
         let processing_time_ms = duration.as_millis() as u64;
 
-        let mut stats = self.calculate_statistics();
+        let mut stats = self.calculate_statistics(min_confidence.unwrap_or(0.0));
         stats.processing_time_ms = processing_time_ms;
 
         serde_wasm_bindgen::to_value(&stats)
@@ -180,62 +730,308 @@ impl CDAParser {
 }
 
 impl CDAParser {
-    fn parse_cda_document(&self, file_name: &str, xml_content: &str) -> Result<CDADocument, String> {
+    fn parse_cda_document(&self, file_name: &str, xml_content: &str) -> Result<CDADocument, CdaError> {
+        if xml_content.len() > MAX_DOCUMENT_BYTES {
+            return Err(CdaError::LimitExceeded {
+                limit: "document_size".to_string(),
+                max: MAX_DOCUMENT_BYTES,
+                actual: xml_content.len(),
+                position: Position::new(xml_content, MAX_DOCUMENT_BYTES),
+            });
+        }
+        let lenient = self.mode == ParseMode::Lenient;
         let mut reader = Reader::from_str(xml_content);
         reader.trim_text(true);
+        reader.expand_empty_elements(true);
+        // En modo tolerante las etiquetas de cierre se emparejan a mano
+        reader.check_end_names(!lenient);
 
         let mut document = CDADocument {
             file_name: file_name.to_string(),
-            patient: Patient {
-                id: None,
-                name: None,
-                gender: None,
-                birth_date: None,
-                age: None,
-            },
+            patient: Patient::default(),
+            patients: Vec::new(),
             diagnoses: Vec::new(),
+            inferred_conditions: Vec::new(),
             medications: Vec::new(),
+            allergies: Vec::new(),
+            observations: Vec::new(),
+            alerts: Vec::new(),
             document_date: None,
             author: None,
+            author_names: Vec::new(),
+            sections: Vec::new(),
+            open_sections: Vec::new(),
+            attachments: Vec::new(),
+            null_flavors: NullFlavors::new(),
+            warnings: Vec::new(),
+            unknown_code_systems: Vec::new(),
         };
 
+        // U+FFFD: el archivo se leyó con una codificación distinta de la suya
+        if let Some(offset) = xml_content.find('\u{FFFD}') {
+            let message = "replacement character found, the file was decoded with the wrong encoding".to_string();
+            let position = Position::new(xml_content, offset);
+            if !lenient {
+                return Err(CdaError::Encoding { message, position });
+            }
+            document.warnings.push(ParseWarning::new("encoding", message, Some(position)));
+        }
+
         let mut buf = Vec::new();
         let mut current_path = Vec::new();
         let mut current_text = String::new();
+        // Árbol narrativo en construcción mientras estamos dentro de <section><text>
+        let mut narrative: Option<NarrativeBuilder> = None;
+        // Diagnóstico creado por el <code> abierto, para etiquetarlo con su originalText
+        let mut coded_entry: Option<usize> = None;
+        // Subárbol de <value> en lectura y pila de observaciones abiertas
+        let mut value_element: Option<ElementBuilder> = None;
+        let mut observations: Vec<PendingObservation> = Vec::new();
+        let mut material: Option<PendingMaterial> = None;
+        let mut dose_quantity: Option<String> = None;
+        let mut allergen: Option<PendingAllergen> = None;
+        let mut root_found = false;
 
         loop {
+            let event_start = reader.buffer_position();
+            // Elementos a cerrar tras este evento y si la lectura termina
+            let mut closing: Vec<String> = Vec::new();
+            let mut finished = false;
+
             match reader.read_event_into(&mut buf) {
                 Ok(Event::Start(ref e)) => {
                     let tag_name = String::from_utf8_lossy(e.name().as_ref()).to_lowercase();
+
+                    if !root_found {
+                        let local_name = tag_name.rsplit(':').next().unwrap_or(&tag_name);
+                        if local_name != "clinicaldocument" {
+                            return Err(CdaError::UnexpectedRoot {
+                                expected: "ClinicalDocument".to_string(),
+                                found: Some(String::from_utf8_lossy(e.name().as_ref()).to_string()),
+                                position: Position::new(xml_content, event_start),
+                            });
+                        }
+                        root_found = true;
+                    }
+                    if current_path.len() >= MAX_DEPTH {
+                        return Err(CdaError::LimitExceeded {
+                            limit: "nesting_depth".to_string(),
+                            max: MAX_DEPTH,
+                            actual: current_path.len() + 1,
+                            position: Position::new(xml_content, event_start),
+                        });
+                    }
                     current_path.push(tag_name.clone());
-                    
-                    // Extraer atributos importantes
-                    self.extract_attributes(&mut document, &tag_name, e, &current_path);
-                    //self.extract_attributes(&mut document, &tag_name, e, &current_path, &reader);
+
+                    if let Some(builder) = narrative.as_mut() {
+                        builder.start(
+                            &tag_name,
+                            self.attribute_value(e, "id"),
+                            self.attribute_value(e, "stylecode"),
+                        );
+                    } else if let Some(builder) = value_element.as_mut() {
+                        builder.start(&tag_name, self.attributes(e));
+                    } else if tag_name == "text" && self.parent_is(&current_path, "section") {
+                        narrative = Some(NarrativeBuilder::new());
+                    } else if tag_name == "value"
+                        && !observations.is_empty()
+                        && (self.parent_is(&current_path, "observation")
+                            || self.parent_is(&current_path, "observationrange"))
+                    {
+                        value_element = Some(ElementBuilder::new(&tag_name, self.attributes(e)));
+                    } else {
+                        if tag_name == "section" {
+                            document.open_sections.push(document.sections.len());
+                            document.sections.push(Section::default());
+                        }
+                        if tag_name == "recordtarget" {
+                            document.patients.push(Patient::default());
+                        }
+                        if tag_name == "substanceadministration" {
+                            dose_quantity = None;
+                        }
+                        if tag_name == "dosequantity" && self.parent_is(&current_path, "substanceadministration") {
+                            dose_quantity = self.attribute_value(e, "value").map(|value| {
+                                match self.attribute_value(e, "unit").filter(|u| u != "1") {
+                                    Some(unit) => format!("{} {}", value, unit),
+                                    None => value,
+                                }
+                            });
+                        }
+                        if tag_name == "manufacturedmaterial" {
+                            material = Some(PendingMaterial {
+                                name: None,
+                                display_name: None,
+                                dose: dose_quantity.clone(),
+                                code: None,
+                                code_system: None,
+                                code_system_name: None,
+                                null_flavors: NullFlavors::new(),
+                            });
+                        }
+                        if tag_name == "playingentity" && self.section_category(&document) == "allergies" {
+                            allergen = Some(PendingAllergen::default());
+                        }
+                        if tag_name == "observation" {
+                            observations.push(PendingObservation {
+                                observation: self.new_observation(&document),
+                                diagnosis: None,
+                            });
+                        }
+
+                        // Extraer atributos importantes
+                        let diagnoses_before = document.diagnoses.len();
+                        let warnings_before = document.warnings.len();
+                        self.extract_attributes(&mut document, &tag_name, e, &current_path);
+                        //self.extract_attributes(&mut document, &tag_name, e, &current_path, &reader);
+                        for warning in document.warnings[warnings_before..].iter_mut() {
+                            warning.position.get_or_insert_with(|| Position::new(xml_content, event_start));
+                        }
+
+                        if tag_name == "code" && document.diagnoses.len() > diagnoses_before {
+                            coded_entry = Some(document.diagnoses.len() - 1);
+                        }
+                        if let Some(pending) = observations.last_mut() {
+                            self.extract_observation_attributes(pending, &tag_name, e, &current_path, coded_entry);
+                        }
+                        if let Some(pending) = material.as_mut() {
+                            self.extract_material_attributes(pending, &tag_name, e, &current_path);
+                        }
+                        if let Some(pending) = allergen.as_mut() {
+                            if tag_name == "code" && self.parent_is(&current_path, "playingentity") {
+                                pending.code = self.attribute_value(e, "code");
+                                pending.code_system = self.attribute_value(e, "codesystem");
+                                pending.code_system_name = self.attribute_value(e, "codesystemname");
+                                pending.display_name = self.attribute_value(e, "displayname");
+                            }
+                        }
+                        if tag_name == "reference" && self.parent_is(&current_path, "originaltext") {
+                            self.extract_reference(&mut document, e, &current_path, coded_entry);
+                        }
+                    }
                 }
                 Ok(Event::End(ref e)) => {
                     let tag_name = String::from_utf8_lossy(e.name().as_ref()).to_lowercase();
-                    
-                    // Procesar texto acumulado
-                    if !current_text.trim().is_empty() {
-                        self.process_text_content(&mut document, &tag_name, &current_text.trim(), &current_path);
-                        current_text.clear();
-                    }
-                    
-                    current_path.pop();
+                    closing = self.elements_to_close(&mut document, &current_path, &tag_name, xml_content, event_start);
                 }
                 Ok(Event::Text(e)) => {
-                    current_text.push_str(&e.unescape().unwrap_or_default());
+                    let text = e.unescape().unwrap_or_default();
+                    if let Some(builder) = narrative.as_mut() {
+                        builder.text(&text);
+                    } else if let Some(builder) = value_element.as_mut() {
+                        builder.text(&text);
+                    } else {
+                        current_text.push_str(&text);
+                    }
+                }
+                Ok(Event::Eof) => {
+                    let position = Position::new(xml_content, xml_content.len());
+                    if let Some(open) = current_path.last() {
+                        let message = format!("unexpected end of document, <{}> is not closed", open);
+                        if !lenient {
+                            return Err(CdaError::MalformedXml { message, position });
+                        }
+                        document.warnings.push(ParseWarning::new("unclosed_element", message, Some(position)));
+                    }
+                    closing = current_path.iter().rev().cloned().collect();
+                    finished = true;
+                }
+                Err(e) => {
+                    let error = CdaError::from_xml(e, Position::new(xml_content, event_start));
+                    if !lenient {
+                        return Err(error);
+                    }
+                    // Se conserva lo extraído hasta el error y se cierra lo que quedó abierto
+                    document.warnings.push(ParseWarning::new("malformed_xml", error.to_string(), error.position()));
+                    closing = current_path.iter().rev().cloned().collect();
+                    finished = true;
                 }
-                Ok(Event::Eof) => break,
-                Err(e) => return Err(format!("Error reading XML: {:?}", e)),
                 _ => {}
             }
+
+            for tag_name in closing {
+                if let Some(builder) = narrative.as_mut() {
+                    if let Some(root) = builder.end() {
+                        narrative = None;
+                        self.process_narrative(&mut document, root, &current_path);
+                    }
+                    current_path.pop();
+                    continue;
+                }
+
+                if let Some(builder) = value_element.as_mut() {
+                    if let Some(root) = builder.end() {
+                        value_element = None;
+                        if let Some(pending) = observations.last_mut() {
+                            self.assign_observation_value(&document, pending, &root, &current_path);
+                        }
+                    }
+                    current_path.pop();
+                    continue;
+                }
+
+                if tag_name == "code" {
+                    coded_entry = None;
+                }
+                if tag_name == "section" {
+                    document.open_sections.pop();
+                }
+                if tag_name == "observation" {
+                    if let Some(pending) = observations.pop() {
+                        self.finish_observation(&mut document, pending, &current_path);
+                    }
+                }
+
+                if let Some(pending) = allergen.as_mut() {
+                    if tag_name == "name" && self.parent_is(&current_path, "playingentity") && !current_text.trim().is_empty() {
+                        pending.name = Some(current_text.trim().to_string());
+                    }
+                }
+                if let Some(pending) = material.as_mut() {
+                    if tag_name == "name" && self.parent_is(&current_path, "manufacturedmaterial") {
+                        pending.name = Some(self.normalize_medication_name(current_text.trim())).filter(|n| !n.is_empty());
+                    }
+                }
+
+                // Procesar texto acumulado
+                if !current_text.trim().is_empty() {
+                    self.process_text_content(&mut document, &tag_name, &current_text.trim(), &current_path);
+                    current_text.clear();
+                }
+
+                if tag_name == "manufacturedmaterial" {
+                    if let Some(pending) = material.take() {
+                        self.finish_material(&mut document, pending, &current_path);
+                    }
+                }
+                if tag_name == "playingentity" {
+                    if let Some(pending) = allergen.take() {
+                        self.finish_allergen(&mut document, pending, &current_path);
+                    }
+                }
+
+                current_path.pop();
+            }
+            if finished {
+                break;
+            }
             buf.clear();
         }
 
+        if !root_found {
+            return Err(CdaError::UnexpectedRoot {
+                expected: "ClinicalDocument".to_string(),
+                found: None,
+                position: Position::new(xml_content, xml_content.len()),
+            });
+        }
+
         // Post-procesamiento
         self.post_process_document(&mut document);
+
+        if let Some(warning) = document.warnings.iter().find(|w| self.mode.rejects(w)) {
+            return Err(CdaError::InvalidData(warning.clone()));
+        }
         
         //console_log!("Documento parseado: {} diagnósticos, {} medicamentos", document.diagnoses.len(), document.medications.len());
         
@@ -249,40 +1045,99 @@ impl CDAParser {
         element: &quick_xml::events::BytesStart, 
         path: &[String]
     ) {
+        if let Some(code) = self.attribute_value(element, "nullflavor") {
+            self.record_null_flavor(document, tag_name, path, NullFlavor::from_code(&code));
+        }
+
+        if tag_name == "name" && self.parent_is(path, "patient") {
+            let name_use = self.attribute_value(element, "use");
+            self.current_patient(document).names.push(PersonName::new(name_use));
+        }
+        if tag_name == "name" && self.parent_is(path, "assignedperson") && self.is_document_author(path) {
+            let name_use = self.attribute_value(element, "use");
+            document.author_names.push(PersonName::new(name_use));
+        }
+
+        if self.parent_is(path, "patient") {
+            match tag_name {
+                "administrativegendercode" => {
+                    if let Some(coding) = self.extract_gender(element) {
+                        let patient = self.current_patient(document);
+                        patient.gender = Some(coding.normalized.clone());
+                        patient.gender_coding = Some(coding);
+                    }
+                }
+                "sdtc:genderidentity" | "genderidentity" => {
+                    if let Some(coding) = self.extract_gender(element) {
+                        self.current_patient(document).gender_identity = Some(coding);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if path.iter().any(|p| p == "guardian" || p == "associatedentity") {
+            self.extract_related_person_attributes(document, tag_name, element, path);
+        }
+
+        // Los códigos de resultados y signos vitales son observaciones, no diagnósticos, y los
+        // de la sección de alergias describen la reacción o la sustancia
+        if tag_name == "code"
+            && self.is_diagnosis_context(path)
+            && !self.is_supporting_observation(path)
+            && !self.is_measurement_section(document)
+            && self.section_category(document) != "allergies"
+        {
+            self.extract_coded_diagnosis(document, element, path);
+        }
+
+        // Sin mediaType el adjunto es text/plain, pero un contenido B64 se decodifica igual
+        if tag_name == "text" && self.parent_is(path, "nonxmlbody") {
+            document.attachments.push(Attachment::new(
+                self.attribute_value(element, "mediatype"),
+                self.attribute_value(element, "representation"),
+                self.attribute_value(element, "compression"),
+            ));
+        }
+
         for attr in element.attributes() {
             if let Ok(attr) = attr {
                 let attr_name = String::from_utf8_lossy(attr.key.as_ref()).to_lowercase();
                 let attr_value = String::from_utf8_lossy(&attr.value);
 
                 match (tag_name, attr_name.as_str()) {
-                    ("administrativegendercode", "code") => {
-                        document.patient.gender = Some(self.normalize_gender(&attr_value));
+                    ("sdtc:deceasedind", "value") | ("deceasedind", "value") if path.contains(&"patient".to_string()) => {
+                        self.current_patient(document).deceased = Some(attr_value.eq_ignore_ascii_case("true"));
                     }
-                    ("birthtime", "value") => {
-                        document.patient.birth_date = Some(attr_value.to_string());
-                        document.patient.age = self.calculate_age_from_hl7_date(&attr_value);
+                    ("sdtc:deceasedtime", "value") | ("deceasedtime", "value") if path.contains(&"patient".to_string()) => {
+                        self.check_date(document, "deceased_time", &attr_value);
+                        let patient = self.current_patient(document);
+                        patient.deceased_time = Some(attr_value.to_string());
+                        patient.deceased.get_or_insert(true);
+                    }
+                    ("birthtime", "value") if !path.iter().any(|p| p == "guardian") => {
+                        self.check_date(document, "birth_date", &attr_value);
+                        let patient = self.current_patient(document);
+                        patient.birth_date = Some(attr_value.to_string());
+                        patient.age = self.calculate_age_from_hl7_date(&attr_value);
                     }
                     ("effectivetime", "value") if path.len() <= 3 => {
+                        self.check_date(document, "document_date", &attr_value);
                         document.document_date = Some(attr_value.to_string());
                     }
-                    ("code", "displayname") => {
-                        if self.is_diagnosis_context(path) {
-                            document.diagnoses.push(Diagnosis {
-                                code: None,
-                                name: attr_value.to_string(),
-                                code_system: None,
-                            });
+                    ("templateid", "root") if self.parent_is(path, "section") => {
+                        if let Some(section) = self.current_section_mut(document) {
+                            section.template_ids.push(attr_value.to_string());
                         }
                     }
-                    ("code", "code") => {
-                        if self.is_diagnosis_context(path) && !document.diagnoses.is_empty() {
-                            if let Some(last_diagnosis) = document.diagnoses.last_mut() {
-                                last_diagnosis.code = Some(attr_value.to_string());
-                            }
+                    ("code", "code") if self.parent_is(path, "section") => {
+                        if let Some(section) = self.current_section_mut(document) {
+                            section.code = Some(attr_value.to_string());
                         }
                     }
-                    ("id", "extension") if path.contains(&"patient".to_string()) => {
-                        document.patient.id = Some(attr_value.to_string());
+                    ("id", "extension") if (path.contains(&"patient".to_string()) && !path.contains(&"guardian".to_string()))
+                        || self.parent_is(path, "patientrole") => {
+                        self.current_patient(document).id = Some(attr_value.to_string());
                     }
                     _ => {}
                 }
@@ -290,6 +1145,619 @@ impl CDAParser {
         }
     }
 
+    fn extract_coded_diagnosis(&self, document: &mut CDADocument, element: &quick_xml::events::BytesStart, path: &[String]) {
+        let code = self.attribute_value(element, "code");
+        let display_name = self.attribute_value(element, "displayname");
+        let null_flavor = self.attribute_value(element, "nullflavor").map(|c| NullFlavor::from_code(&c));
+
+        // Un código sin displayName (o con nullFlavor) se etiqueta después con su originalText
+        if code.is_none() && display_name.is_none() && null_flavor.is_none() {
+            return;
+        }
+
+        let mut null_flavors = NullFlavors::new();
+        if let Some(null_flavor) = null_flavor {
+            null_flavors.insert("code".to_string(), null_flavor);
+        }
+
+        let evidence = Evidence::new(ExtractionMethod::CodedEntry, "entry code")
+            .at(path)
+            .with_confidence(self.coded_confidence(document, code.is_some()));
+
+        document.diagnoses.push(Diagnosis {
+            code,
+            name: display_name.unwrap_or_default(),
+            code_system: self.attribute_value(element, "codesystem"),
+            code_system_name: self.attribute_value(element, "codesystemname"),
+            null_flavors,
+            evidence: vec![evidence],
+            ..Default::default()
+        });
+    }
+
+    // Paciente del recordTarget abierto (o el único, si el documento no lo declara)
+    fn current_patient<'a>(&self, document: &'a mut CDADocument) -> &'a mut Patient {
+        if document.patients.is_empty() {
+            document.patients.push(Patient::default());
+        }
+        document.patients.last_mut().unwrap()
+    }
+
+    // Primer recordTarget: destinatario de los participantes de cabecera
+    fn primary_patient<'a>(&self, document: &'a mut CDADocument) -> &'a mut Patient {
+        if document.patients.is_empty() {
+            document.patients.push(Patient::default());
+        }
+        &mut document.patients[0]
+    }
+
+    // Tutor (patient/guardian) o participante de cabecera (participant/associatedEntity) abierto
+    fn current_related_person<'a>(&self, document: &'a mut CDADocument, path: &[String]) -> Option<&'a mut RelatedPerson> {
+        if path.iter().any(|p| p == "guardian") {
+            self.current_patient(document).guardians.last_mut()
+        } else {
+            self.primary_patient(document).contacts.last_mut()
+        }
+    }
+
+    fn extract_related_person_attributes(
+        &self,
+        document: &mut CDADocument,
+        tag_name: &str,
+        element: &quick_xml::events::BytesStart,
+        path: &[String],
+    ) {
+        match tag_name {
+            "guardian" if self.parent_is(path, "patient") => {
+                self.current_patient(document).guardians.push(RelatedPerson::new("guardian"));
+                return;
+            }
+            "associatedentity" if self.parent_is(path, "participant") && path.len() == 3 => {
+                let class_code = self.attribute_value(element, "classcode");
+                let role = RelatedPerson::role_from_class_code(class_code.as_deref());
+                self.primary_patient(document).contacts.push(RelatedPerson::new(role));
+                return;
+            }
+            _ => {}
+        }
+
+        // Participantes de cabecera: sólo /ClinicalDocument/participant
+        let in_guardian = path.iter().any(|p| p == "guardian");
+        let in_header_participant = path.len() > 3 && path[1] == "participant";
+        if !in_guardian && !in_header_participant {
+            return;
+        }
+        let person = match self.current_related_person(document, path) {
+            Some(person) => person,
+            None => return,
+        };
+
+        match tag_name {
+            "code" if self.parent_is(path, "guardian") || self.parent_is(path, "associatedentity") => {
+                person.relationship_code = self.attribute_value(element, "code");
+                person.relationship = self.attribute_value(element, "displayname");
+            }
+            "telecom" => {
+                if let Some(value) = self.attribute_value(element, "value") {
+                    person.telecoms.push(value);
+                }
+            }
+            "name" if self.parent_is(path, "guardianperson") || self.parent_is(path, "associatedperson") => {
+                person.name = Some(PersonName::new(self.attribute_value(element, "use")));
+            }
+            _ => {}
+        }
+    }
+
+    fn extract_gender(&self, element: &quick_xml::events::BytesStart) -> Option<GenderCoding> {
+        let code = self.attribute_value(element, "code");
+        let code_system = self.attribute_value(element, "codesystem");
+        let display_name = self.attribute_value(element, "displayname");
+
+        if code.is_none() && display_name.is_none() {
+            // nullFlavor="OTH": sexo fuera del vocabulario, no desconocido
+            let null_flavor = self.attribute_value(element, "nullflavor")?;
+            if NullFlavor::from_code(&null_flavor) != NullFlavor::Other {
+                return None;
+            }
+            return Some(GenderCoding {
+                normalized: gender::OTHER.to_string(),
+                code: None,
+                code_system,
+                code_system_name: None,
+                display_name: None,
+            });
+        }
+        let mut coding = GenderCoding::new(code, code_system, display_name);
+        coding.code_system_name = self.attribute_value(element, "codesystemname");
+        Some(coding)
+    }
+
+    fn record_null_flavor(&self, document: &mut CDADocument, tag_name: &str, path: &[String], null_flavor: NullFlavor) {
+        let in_patient = path.contains(&"patient".to_string());
+
+        let (patient_field, header_field) = match tag_name {
+            "administrativegendercode" if in_patient => (Some("gender"), None),
+            "birthtime" if in_patient => (Some("birth_date"), None),
+            "name" if self.parent_is(path, "patient") => (Some("name"), None),
+            "id" if self.parent_is(path, "patientrole") => (Some("id"), None),
+            "effectivetime" if path.len() <= 2 => (None, Some("document_date")),
+            "assignedauthor" | "assignedperson" => (None, Some("author")),
+            "name" if self.parent_is(path, "assignedperson") => (None, Some("author")),
+            _ => (None, None),
+        };
+
+        if let Some(field) = patient_field {
+            self.current_patient(document).null_flavors.insert(field.to_string(), null_flavor);
+        }
+        if let Some(field) = header_field {
+            document.null_flavors.insert(field.to_string(), null_flavor);
+        }
+    }
+
+    fn extract_material_attributes(
+        &self,
+        pending: &mut PendingMaterial,
+        tag_name: &str,
+        element: &quick_xml::events::BytesStart,
+        path: &[String],
+    ) {
+        if !matches!(tag_name, "code" | "name") || !self.parent_is(path, "manufacturedmaterial") {
+            return;
+        }
+        if tag_name == "code" {
+            pending.display_name = self.attribute_value(element, "displayname");
+            pending.code = self.attribute_value(element, "code");
+            pending.code_system = self.attribute_value(element, "codesystem");
+            pending.code_system_name = self.attribute_value(element, "codesystemname");
+        }
+        if let Some(code) = self.attribute_value(element, "nullflavor") {
+            pending.null_flavors.insert(tag_name.to_string(), NullFlavor::from_code(&code));
+        }
+    }
+
+    fn finish_material(&self, document: &mut CDADocument, pending: PendingMaterial, path: &[String]) {
+        let key = pending.name.as_deref().map(hierarchy::name_key);
+        if let Some(medication) = document.medications.iter_mut().find(|m| Some(hierarchy::name_key(&m.name)) == key) {
+            if medication.code.is_none() {
+                medication.code = pending.code;
+                medication.code_system = pending.code_system;
+                medication.code_system_name = pending.code_system_name;
+            }
+            medication.display_name = medication.display_name.take().or(pending.display_name);
+            medication.dose = medication.dose.take().or(pending.dose);
+            medication.null_flavors.extend(pending.null_flavors);
+            return;
+        }
+        if pending.null_flavors.is_empty() {
+            return;
+        }
+
+        // <name nullFlavor="..."/>: el displayName del código identifica el medicamento
+        if let Some(display_name) = pending.display_name {
+            let med_name = self.normalize_medication_name(&display_name);
+            self.add_medication(document, Medication {
+                name: med_name,
+                medication_type: "structured".to_string(),
+                code: pending.code,
+                code_system: pending.code_system,
+                code_system_name: pending.code_system_name,
+                display_name: Some(display_name.clone()),
+                dose: pending.dose,
+                null_flavors: pending.null_flavors,
+                evidence: vec![Evidence::new(ExtractionMethod::CodedEntry, "manufacturedMaterial code displayName")
+                    .at(path)
+                    .with_text(&display_name)],
+                ..Default::default()
+            });
+        }
+    }
+
+    fn finish_allergen(&self, document: &mut CDADocument, pending: PendingAllergen, path: &[String]) {
+        let name = match pending.display_name.clone().or(pending.name.clone()).or(pending.code.clone()) {
+            Some(name) => name,
+            None => return,
+        };
+        let text = pending.name.unwrap_or_else(|| name.clone());
+        self.add_allergy(document, Allergy {
+            name,
+            code: pending.code,
+            code_system: pending.code_system,
+            code_system_name: pending.code_system_name,
+            evidence: vec![Evidence::new(ExtractionMethod::CodedEntry, "allergy playingEntity").at(path).with_text(&text)],
+            ..Default::default()
+        });
+    }
+
+    fn extract_reference(
+        &self,
+        document: &mut CDADocument,
+        element: &quick_xml::events::BytesStart,
+        path: &[String],
+        coded_entry: Option<usize>,
+    ) {
+        let reference = match self.attribute_value(element, "value") {
+            Some(value) => value,
+            None => return,
+        };
+
+        // Elemento dueño del originalText: code, value, manufacturedmaterial...
+        let source = path.len().checked_sub(3).map(|i| path[i].clone()).unwrap_or_default();
+        let text = self.current_section(document).and_then(|s| s.resolve(&reference));
+
+        if source == "code" {
+            if let Some(diagnosis) = coded_entry.and_then(|i| document.diagnoses.get_mut(i)) {
+                diagnosis.original_text = text.clone();
+                if diagnosis.name.is_empty() {
+                    if let Some(ref text) = text {
+                        diagnosis.name = text.clone();
+                    }
+                }
+            }
+        }
+
+        if let Some(section) = self.current_section_mut(document) {
+            section.references.push(NarrativeReference { reference, source, text });
+        }
+    }
+
+    fn new_observation(&self, document: &CDADocument) -> Observation {
+        Observation {
+            code: None,
+            code_system: None,
+            code_system_name: None,
+            name: String::new(),
+            display_name_es: None,
+            display_name_en: None,
+            category: self.section_category(document).to_string(),
+            value: None,
+            normalized_value: None,
+            effective_time: None,
+            reference_range: None,
+            interpretation_code: None,
+            abnormal_flag: None,
+            observation_type: "structured".to_string(),
+            derivation: None,
+        }
+    }
+
+    fn extract_observation_attributes(
+        &self,
+        pending: &mut PendingObservation,
+        tag_name: &str,
+        element: &quick_xml::events::BytesStart,
+        path: &[String],
+        coded_entry: Option<usize>,
+    ) {
+        let observation = &mut pending.observation;
+
+        match tag_name {
+            "code" if self.parent_is(path, "observation") => {
+                observation.code = self.attribute_value(element, "code");
+                observation.code_system = self.attribute_value(element, "codesystem");
+                observation.code_system_name = self.attribute_value(element, "codesystemname");
+                observation.name = self.attribute_value(element, "displayname").unwrap_or_default();
+                pending.diagnosis = coded_entry;
+            }
+            "effectivetime" if self.parent_is(path, "observation") => {
+                if let Some(value) = self.attribute_value(element, "value") {
+                    observation.effective_time = Some(value);
+                }
+            }
+            "interpretationcode" if self.parent_is(path, "observation") => {
+                observation.interpretation_code = self.attribute_value(element, "code");
+            }
+            "low" if self.parent_is(path, "effectivetime")
+                && path.len() >= 3 && path[path.len() - 3] == "observation"
+                && observation.effective_time.is_none() =>
+            {
+                observation.effective_time = self.attribute_value(element, "value");
+            }
+            _ => {}
+        }
+    }
+
+    fn assign_observation_value(
+        &self,
+        document: &CDADocument,
+        pending: &mut PendingObservation,
+        element: &RawElement,
+        path: &[String],
+    ) {
+        let mut value = Value::from_element(element);
+
+        // originalText/reference del valor codificado apunta al bloque narrativo
+        if let Some(coded) = value.coded_mut() {
+            if coded.original_text.is_none() {
+                if let Some(reference) = coded.original_text_reference.as_deref() {
+                    coded.original_text = narrative::resolve_reference(&document.sections, reference);
+                }
+            }
+        }
+
+        if self.parent_is(path, "observationrange") {
+            pending.observation.reference_range = Some(value);
+        } else if pending.observation.value.is_none() {
+            pending.observation.value = Some(value);
+        }
+    }
+
+    fn finish_observation(&self, document: &mut CDADocument, pending: PendingObservation, path: &[String]) {
+        let observation = pending.observation;
+
+        // Identidad de género registrada como observación social (LOINC 76691-5)
+        if observation.code.as_deref() == Some("76691-5") {
+            if let Some(coded) = observation.value.as_ref().and_then(|v| v.coded()) {
+                let mut coding = GenderCoding::new(coded.code.clone(), coded.code_system.clone(), coded.label());
+                coding.code_system_name = coded.code_system_name.clone();
+                self.primary_patient(document).gender_identity = Some(coding);
+            }
+        }
+
+        // La sustancia de la alergia sale de <playingEntity>; la observación sólo dice el tipo
+        if observation.category == "allergies" {
+            return;
+        }
+
+        if observation.category != "problems" {
+            // Fuera de resultados y constantes, un valor medido hace del <code> una prueba, no un diagnóstico
+            let measured = matches!(
+                observation.value,
+                Some(Value::Pq(_)) | Some(Value::IvlPq { .. }) | Some(Value::RtoPqPq { .. }) | Some(Value::Int { .. }) | Some(Value::Real { .. })
+            );
+            if measured && pending.diagnosis.is_some() && pending.diagnosis == document.diagnoses.len().checked_sub(1) {
+                document.diagnoses.pop();
+            }
+            if !observation.name.is_empty() || observation.code.is_some() || observation.value.is_some() {
+                document.observations.push(observation);
+            }
+            return;
+        }
+
+        // Estado ("Active"), gravedad... del problema: no son diagnósticos
+        if self.is_supporting_observation(path) {
+            return;
+        }
+
+        // En problemas el trastorno va en el valor (CD); el <code> sólo dice "Problem"
+        let value = match observation.value {
+            Some(value) => value,
+            None => return,
+        };
+        let coded = value.coded().cloned();
+        let null_flavor = value.null_flavor().map(NullFlavor::from_code);
+        let has_code = coded.as_ref().map(|c| c.code.is_some()).unwrap_or(false);
+        let evidence = Evidence::new(ExtractionMethod::CodedEntry, "problem observation value")
+            .at(path)
+            .with_confidence(self.coded_confidence(document, has_code));
+
+        if let Some(index) = pending.diagnosis {
+            if let Some(diagnosis) = document.diagnoses.get_mut(index) {
+                if let Some(null_flavor) = null_flavor {
+                    diagnosis.null_flavors.insert("value".to_string(), null_flavor);
+                }
+                if let Some(coded) = coded {
+                    // El <code> de la observación sólo decía "Problem": la evidencia es el valor
+                    diagnosis.evidence = vec![evidence];
+                    if let Some(label) = coded.label() {
+                        diagnosis.name = label;
+                    }
+                    if coded.code.is_some() {
+                        diagnosis.code = coded.code;
+                        diagnosis.code_system = coded.code_system;
+                        diagnosis.code_system_name = coded.code_system_name;
+                    }
+                    diagnosis.original_text = coded.original_text.or(diagnosis.original_text.take());
+                }
+                diagnosis.value = Some(value);
+                return;
+            }
+        }
+
+        if let Some(coded) = coded {
+            let name = coded.label().or_else(|| coded.code.clone()).unwrap_or_default();
+            if !name.is_empty() {
+                self.add_diagnosis(document, Diagnosis {
+                    code: coded.code.clone(),
+                    name,
+                    code_system: coded.code_system.clone(),
+                    code_system_name: coded.code_system_name.clone(),
+                    original_text: coded.original_text.clone(),
+                    value: Some(value),
+                    evidence: vec![evidence],
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    fn current_section<'a>(&self, document: &'a CDADocument) -> Option<&'a Section> {
+        document.open_sections.last().and_then(|&index| document.sections.get(index))
+    }
+
+    fn current_section_mut<'a>(&self, document: &'a mut CDADocument) -> Option<&'a mut Section> {
+        let index = *document.open_sections.last()?;
+        document.sections.get_mut(index)
+    }
+
+    // Categoría de las observaciones según el código LOINC de la sección abierta o, si no es
+    // un código de sección conocido, según su templateId
+    fn section_category(&self, document: &CDADocument) -> &'static str {
+        let section = match self.current_section(document) {
+            Some(section) => section,
+            None => return "other",
+        };
+        let by_code = match section.code.as_deref() {
+            Some("30954-2") | Some("11502-2") | Some("26436-6") => Some("results"),
+            Some("8716-3") => Some("vital_signs"),
+            Some("11450-4") | Some("11348-0") | Some("29548-5") => Some("problems"),
+            Some("48765-2") => Some("allergies"),
+            // Medicación, procedimientos, plan, inmunizaciones, antecedentes sociales, encuentros
+            Some("10160-0") | Some("29549-3") | Some("47519-4") | Some("18776-5") | Some("11369-6")
+            | Some("29762-2") | Some("46240-8") => Some("other"),
+            _ => None,
+        };
+        let by_template = section.template_ids.iter().find_map(|root| match root.as_str() {
+            "2.16.840.1.113883.10.20.22.2.3" | "2.16.840.1.113883.10.20.22.2.3.1" | "2.16.840.1.113883.10.20.1.14" => {
+                Some("results")
+            }
+            "2.16.840.1.113883.10.20.22.2.4" | "2.16.840.1.113883.10.20.22.2.4.1" | "2.16.840.1.113883.10.20.1.16" => {
+                Some("vital_signs")
+            }
+            "2.16.840.1.113883.10.20.22.2.5" | "2.16.840.1.113883.10.20.22.2.5.1" | "2.16.840.1.113883.10.20.1.11" => {
+                Some("problems")
+            }
+            "2.16.840.1.113883.10.20.22.2.6" | "2.16.840.1.113883.10.20.22.2.6.1" | "2.16.840.1.113883.10.20.1.2" => {
+                Some("allergies")
+            }
+            _ => None,
+        });
+        by_code.or(by_template).unwrap_or("other")
+    }
+
+    // Código estructurado, o sólo displayName/originalText dentro o fuera de una sección de problemas
+    fn coded_confidence(&self, document: &CDADocument, has_code: bool) -> f64 {
+        if has_code {
+            ExtractionMethod::CodedEntry.confidence()
+        } else if self.section_category(document) == "problems" {
+            DISPLAY_NAME_CONFIDENCE
+        } else {
+            DISPLAY_NAME_OUTSIDE_PROBLEMS_CONFIDENCE
+        }
+    }
+
+    fn is_measurement_section(&self, document: &CDADocument) -> bool {
+        matches!(self.section_category(document), "results" | "vital_signs")
+    }
+
+    fn process_narrative(&self, document: &mut CDADocument, root: narrative::NarrativeNode, path: &[String]) {
+        // Tablas con cabecera: medicamentos y resultados que no vienen codificados. Van antes
+        // que el texto para que sus filas conserven la procedencia "narrative_table"
+        let tables = tables::extract_tables(&root);
+        for table in &tables {
+            self.extract_table_records(document, table, path);
+        }
+
+        // Extraer diagnósticos y medicamentos fragmento a fragmento, sin las celdas ya extraídas
+        for fragment in root.fragments(&tables::is_record_table) {
+            self.extract_from_text(document, &fragment, path);
+        }
+
+        if let Some(section) = self.current_section_mut(document) {
+            section.narrative = Some(root);
+            section.tables = tables;
+        }
+    }
+
+    fn extract_non_xml_body(&self, document: &mut CDADocument, content: &str, path: &[String]) {
+        let loaded = match document.attachments.last_mut() {
+            Some(attachment) => attachment.load(content).map(|()| attachment.fragments()),
+            None => return,
+        };
+        let fragments = match loaded {
+            Ok(fragments) => fragments,
+            Err(reason) => {
+                document.warnings.push(ParseWarning::new(
+                    "invalid_attachment",
+                    format!("nonXMLBody content could not be decoded: {}", reason),
+                    None,
+                ));
+                Vec::new()
+            }
+        };
+
+        // Informes en texto plano o RTF pasan por los mismos extractores narrativos
+        for fragment in fragments {
+            self.extract_from_text(document, &fragment, path);
+        }
+    }
+
+    fn extract_table_records(&self, document: &mut CDADocument, table: &NarrativeTable, path: &[String]) {
+        match table.kind() {
+            TableKind::Medications => {
+                for row in table.medication_rows() {
+                    let med_name = self.normalize_medication_name(row.name);
+                    if med_name.is_empty() {
+                        continue;
+                    }
+                    self.add_medication(document, Medication {
+                        name: med_name,
+                        medication_type: "narrative_table".to_string(),
+                        dose: row.dose.map(|v| v.to_string()),
+                        frequency: row.frequency.map(|v| v.to_string()),
+                        route: row.route.map(|v| v.to_string()),
+                        evidence: vec![Evidence::new(ExtractionMethod::NarrativeTable, "medication table row")
+                            .at(path)
+                            .with_text(row.name)],
+                        ..Default::default()
+                    });
+                }
+            }
+            TableKind::Observations => {
+                for row in table.observation_rows() {
+                    // "148 mg/dL" en una sola celda cuando la tabla no tiene columna de unidad
+                    let (value, unit) = match (row.value, row.unit) {
+                        (Some(value), None) => self.split_value_unit(value),
+                        (value, unit) => (value.map(|v| v.to_string()), unit.map(|u| u.to_string())),
+                    };
+                    let mut observation = self.new_observation(document);
+                    observation.name = row.name.to_string();
+                    observation.category = "results".to_string();
+                    observation.value = value.map(|v| Value::from_text(&v, unit.as_deref()));
+                    observation.effective_time = row.date.map(|v| v.to_string());
+                    observation.reference_range = row.reference_range.map(|r| Value::range_from_text(r, unit.as_deref()));
+                    observation.observation_type = "narrative_table".to_string();
+                    document.observations.push(observation);
+                }
+            }
+            TableKind::Unknown => {}
+        }
+    }
+
+    fn split_value_unit(&self, text: &str) -> (Option<String>, Option<String>) {
+        match VALUE_WITH_UNIT.captures(text.trim()) {
+            Some(caps) => {
+                let unit = caps[2].trim();
+                (
+                    Some(caps[1].replace(' ', "")),
+                    if unit.is_empty() { None } else { Some(unit.to_string()) },
+                )
+            }
+            None => (Some(text.trim().to_string()), None),
+        }
+    }
+
+    fn attributes(&self, element: &quick_xml::events::BytesStart) -> Vec<(String, String)> {
+        element
+            .attributes()
+            .flatten()
+            .map(|attr| {
+                let name = String::from_utf8_lossy(attr.key.local_name().as_ref()).to_lowercase();
+                let value = attr.unescape_value()
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|_| String::from_utf8_lossy(&attr.value).to_string());
+                (name, value)
+            })
+            .collect()
+    }
+
+    fn attribute_value(&self, element: &quick_xml::events::BytesStart, name: &str) -> Option<String> {
+        self.attributes(element)
+            .into_iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+            .filter(|v| !v.trim().is_empty())
+    }
+
+    fn parent_is(&self, path: &[String], parent: &str) -> bool {
+        path.len() >= 2 && path[path.len() - 2] == parent
+    }
+
+    // <author> de la cabecera, no el de una entrada
+    fn is_document_author(&self, path: &[String]) -> bool {
+        path.get(1).is_some_and(|p| p == "author")
+    }
+
     /* fn extract_attributes(
         &self,
         document: &mut CDADocument,
@@ -372,154 +1840,214 @@ impl CDAParser {
 
     fn process_text_content(&self, document: &mut CDADocument, tag_name: &str, text: &str, path: &[String]) {
         match tag_name {
-            "given" | "family" if path.contains(&"patient".to_string()) => {
-                let current_name = document.patient.name.clone().unwrap_or_default();
-                document.patient.name = Some(format!("{} {}", current_name, text).trim().to_string());
+            "given" | "family" | "prefix" | "suffix"
+                if path.len() >= 3 && path[path.len() - 3] == "patient" && self.parent_is(path, "name") =>
+            {
+                if let Some(name) = self.current_patient(document).names.last_mut() {
+                    name.add_part(tag_name, text);
+                }
+            }
+            "name" if self.parent_is(path, "patient") => {
+                if let Some(name) = self.current_patient(document).names.last_mut() {
+                    name.text = Some(text.split_whitespace().collect::<Vec<&str>>().join(" "));
+                }
+            }
+            "given" | "family" | "prefix" | "suffix" | "name"
+                if path.iter().any(|p| p == "guardianperson" || p == "associatedperson") =>
+            {
+                if let Some(name) = self.current_related_person(document, path).and_then(|p| p.name.as_mut()) {
+                    if tag_name == "name" {
+                        name.text = Some(text.split_whitespace().collect::<Vec<&str>>().join(" "));
+                    } else {
+                        name.add_part(tag_name, text);
+                    }
+                }
             }
-            "name" if path.contains(&"assignedperson".to_string()) => {
-                document.author = Some(text.to_string());
+            "given" | "family" | "prefix" | "suffix" | "name"
+                if path.iter().any(|p| p == "assignedperson") && self.is_document_author(path) =>
+            {
+                if let Some(name) = document.author_names.last_mut() {
+                    if tag_name == "name" {
+                        name.text = Some(text.split_whitespace().collect::<Vec<&str>>().join(" "));
+                    } else {
+                        name.add_part(tag_name, text);
+                    }
+                }
             }
             "name" if path.contains(&"manufacturedmaterial".to_string()) || 
                      path.contains(&"medication".to_string()) => {
                 // Extraer medicamentos específicos de tus archivos
                 let med_name = self.normalize_medication_name(text);
-                if !med_name.is_empty() && !self.is_duplicate_medication(document, &med_name) {
-                    document.medications.push(Medication {
+                if !med_name.is_empty() {
+                    self.add_medication(document, Medication {
                         name: med_name,
                         medication_type: "structured".to_string(),
+                        evidence: vec![Evidence::new(ExtractionMethod::CodedEntry, "manufacturedMaterial name")
+                            .at(path)
+                            .with_text(text)],
+                        ..Default::default()
                     });
                 }
             }
             "title" => {
+                if self.parent_is(path, "section") {
+                    if let Some(section) = self.current_section_mut(document) {
+                        section.title = Some(text.to_string());
+                    }
+                }
+
                 // Extraer diagnósticos del título del documento
-                self.extract_diagnoses_from_title(document, text);
+                self.extract_diagnoses_from_title(document, text, path);
                 
                 // También buscar medicamentos y diagnósticos en el texto
-                self.extract_from_text(document, text);
+                self.extract_from_text(document, text, path);
+            }
+            "text" if self.parent_is(path, "nonxmlbody") => {
+                self.extract_non_xml_body(document, text, path);
             }
             "text" => {
                 // Extraer diagnósticos y medicamentos del texto libre
-                self.extract_from_text(document, text);
+                self.extract_from_text(document, text, path);
             }
             _ => {}
         }
     }
     
     fn normalize_medication_name(&self, name: &str) -> String {
-        // Normalizar nombres de medicamentos comunes en tus archivos
-        let name_lower = name.to_lowercase().trim().to_string();
-        
-        match name_lower.as_str() {
-            "metformina" | "metformin" => "Metformina".to_string(),
-            "enalapril" => "Enalapril".to_string(),
-            "atorvastatina" | "atorvastatin" => "Atorvastatina".to_string(),
-            "salbutamol" => "Salbutamol".to_string(),
-            "budesonida" | "budesonide" => "Budesonida".to_string(),
-            "warfarina" | "warfarin" => "Warfarina".to_string(),
-            _ => {
-                // Si no está en la lista, devolver el nombre original limpio
-                name.trim().to_string()
-            }
-        }
+        self.dictionaries.medication_name(name)
     }
 
-    fn extract_from_text(&self, document: &mut CDADocument, text: &str) {
+    fn extract_from_text(&self, document: &mut CDADocument, text: &str, path: &[String]) {
         let text_lower = text.to_lowercase();
-        
-        // Extraer diagnósticos del título del documento
-        self.extract_diagnoses_from_title(document, text);            
 
-        for re in MED_PATTERNS.iter() {
+        // "alergia a amoxicilina": la sustancia es una alergia, no un medicamento que toma
+        let mut allergy_spans = Vec::new();
+        for captures in ALLERGY_PATTERN.captures_iter(&text_lower) {
+            let (mention, substance) = (captures.get(0).unwrap(), captures.get(1).unwrap());
+            allergy_spans.push((mention.start(), mention.end()));
+            self.add_allergy(document, Allergy {
+                name: self.normalize_medication_name(substance.as_str()),
+                evidence: vec![Evidence::new(ExtractionMethod::NarrativeRegex, "ALLERGY_PATTERN")
+                    .at(path)
+                    .with_text(text)
+                    .with_span(&text_lower, substance.start(), substance.end())],
+                ..Default::default()
+            });
+        }
+
+        for (index, re) in MED_PATTERNS.iter().enumerate() {
             for mat in re.find_iter(&text_lower) {
                 let med_name = mat.as_str().to_string();
-                if med_name.len() > 3 && !self.is_duplicate_medication(document, &med_name) {
-                    document.medications.push(Medication {
+                let in_allergy = allergy_spans.iter().any(|(start, end)| mat.start() < *end && mat.end() > *start);
+                if med_name.len() > 3 && !in_allergy {
+                    self.add_medication(document, Medication {
                         name: med_name,
                         medication_type: "text_extracted".to_string(),
+                        evidence: vec![Evidence::new(ExtractionMethod::NarrativeRegex, &format!("MED_PATTERNS[{}]", index))
+                            .at(path)
+                            .with_text(text)
+                            .with_span(&text_lower, mat.start(), mat.end())],
+                        ..Default::default()
                     });
                 }
             }
         }
         
         // Buscar diagnósticos por palabras clave médicas específicas
-        let diagnosis_keywords = [
-            "diabetes", "diabético", "hipertension", "hipertenso", "asma", "pneumonia", "infection",
-            "fracture", "cancer", "depression", "anxiety", "arthritis", "hipercolesterolemia",
-            "bronchitis", "gastritis", "dermatitis", "nephritis", "controlada", "crónico",
-            "agudo", "estable", "compensado"
-        ];
-        
-        for keyword in &diagnosis_keywords {
-            if text_lower.contains(keyword) {
-                let diagnosis_name = keyword.to_string();
-                if !self.is_duplicate_diagnosis(document, &diagnosis_name) {
-                    document.diagnoses.push(Diagnosis {
-                        code: None,
-                        name: diagnosis_name,
-                        code_system: Some("text_extracted".to_string()),
-                    });
-                }
+        for keyword in &self.dictionaries.diagnosis_keywords {
+            if let Some(start) = text_lower.find(keyword.as_str()) {
+                self.add_diagnosis(document, Diagnosis {
+                    name: self.dictionaries.diagnosis_name(keyword),
+                    code_system: Some("text_extracted".to_string()),
+                    evidence: vec![Evidence::new(ExtractionMethod::NarrativeRegex, &format!("diagnosis_keywords: {}", keyword))
+                        .at(path)
+                        .with_text(text)
+                        .with_span(&text_lower, start, start + keyword.len())],
+                    ..Default::default()
+                });
             }
         }
     }
     
-    fn extract_diagnoses_from_title(&self, document: &mut CDADocument, text: &str) {
+    fn extract_diagnoses_from_title(&self, document: &mut CDADocument, text: &str, path: &[String]) {
         let text_lower = text.to_lowercase();
         
         // Mapear títulos específicos a diagnósticos
-        let title_mappings = [
-            ("hipertenso", "Hipertensión"),
-            ("diabético", "Diabetes"),
-            ("hipercolesterolemia", "Hipercolesterolemia"),
-            ("asma", "Asma"),
-            ("múltiples condiciones", "Múltiples patologías"),
-            ("anticoagulación", "Trastorno de coagulación"),
-            ("control anticoagulación", "Anticoagulación"),
-        ];
-        
-        for (keyword, diagnosis) in &title_mappings {
-            if text_lower.contains(keyword) {
-                if !self.is_duplicate_diagnosis(document, diagnosis) {
-                    document.diagnoses.push(Diagnosis {
-                        code: None,
-                        name: diagnosis.to_string(),
-                        code_system: Some("title_inferred".to_string()),
-                    });
-                }
+        for mapping in &self.dictionaries.title_mappings {
+            let (keyword, diagnosis) = (&mapping.keyword, &mapping.diagnosis);
+            if let Some(start) = text_lower.find(keyword.as_str()) {
+                self.add_diagnosis(document, Diagnosis {
+                    name: diagnosis.clone(),
+                    code_system: Some("title_inferred".to_string()),
+                    evidence: vec![Evidence::new(ExtractionMethod::TitleMapping, &format!("title_mappings: {} -> {}", keyword, diagnosis))
+                        .at(path)
+                        .with_text(text)
+                        .with_span(&text_lower, start, start + keyword.len())],
+                    ..Default::default()
+                });
             }
         }
     }
     
     fn infer_diagnoses_from_medications(&self, document: &mut CDADocument) {
         // Mapear medicamentos a diagnósticos probables
-        let med_to_diagnosis = [
-            ("metformina", "Diabetes mellitus tipo 2"),
-            ("metformin", "Diabetes mellitus tipo 2"),
-            ("enalapril", "Hipertensión arterial"),
-            ("atorvastatina", "Hipercolesterolemia"),
-            ("atorvastatin", "Hipercolesterolemia"),
-            ("salbutamol", "Asma bronquial"),
-            ("budesonida", "Asma bronquial"),
-            ("budesonide", "Asma bronquial"),
-            ("warfarina", "Trastorno de coagulación"),
-            ("warfarin", "Trastorno de coagulación"),
-        ];
-        
+        let mut inferred: Vec<InferredCondition> = Vec::new();
         for medication in &document.medications {
             let med_name_lower = medication.name.to_lowercase();
             
-            for (med_keyword, diagnosis) in &med_to_diagnosis {
-                if med_name_lower.contains(med_keyword) {
-                    if !self.is_duplicate_diagnosis(document, diagnosis) {
-                        document.diagnoses.push(Diagnosis {
-                            code: None,
-                            name: diagnosis.to_string(),
-                            code_system: Some("medication_inferred".to_string()),
-                        });
+            for mapping in &self.dictionaries.med_to_diagnosis {
+                let (med_keyword, diagnosis) = (&mapping.keyword, &mapping.diagnosis);
+                if let Some(start) = med_name_lower.find(med_keyword.as_str()) {
+                    // El rastro apunta al medicamento que disparó la regla
+                    let mut evidence = Evidence::new(ExtractionMethod::MedicationInference, &format!("med_to_diagnosis: {} -> {}", med_keyword, diagnosis))
+                        .with_text(&medication.name)
+                        .with_span(&med_name_lower, start, start + med_keyword.len());
+                    evidence.path = medication.evidence.first().and_then(|e| e.path.clone());
+
+                    match inferred.iter_mut().find(|c| c.name == *diagnosis) {
+                        // "metformin" y "metformina" sobre el mismo medicamento cuentan una sola vez
+                        Some(condition) => {
+                            if !condition.medications.contains(&medication.name) {
+                                condition.medications.push(medication.name.clone());
+                                condition.evidence.push(evidence);
+                            }
+                        }
+                        None => inferred.push(InferredCondition {
+                            name: diagnosis.clone(),
+                            medications: vec![medication.name.clone()],
+                            documented: false,
+                            rationale: String::new(),
+                            evidence: vec![evidence],
+                            confidence: 0.0,
+                        }),
                     }
                 }
             }
         }
+
+        for condition in inferred.iter_mut() {
+            condition.documented = self.is_documented_condition(document, &condition.name);
+            condition.confidence = provenance::combined_confidence(&condition.evidence);
+            condition.rationale = if condition.documented {
+                format!("{} se indica habitualmente para {}, que consta en el documento", condition.medications.join(", "), condition.name)
+            } else {
+                format!("{} se indica habitualmente para {}, sin diagnóstico que lo respalde en el documento", condition.medications.join(", "), condition.name)
+            };
+        }
+        document.inferred_conditions = inferred;
+    }
+
+    // Coincidencia laxa por la raíz de la primera palabra: "Diabetes mellitus tipo 2" ~ "diabético"
+    fn is_documented_condition(&self, document: &CDADocument, condition: &str) -> bool {
+        let stem = |name: &str| -> String {
+            tables::normalize_header(name)
+                .split_whitespace()
+                .next()
+                .map(|w| w.chars().take(6).collect())
+                .unwrap_or_default()
+        };
+        let key = stem(condition);
+        !key.is_empty() && document.diagnoses.iter().any(|d| stem(&d.name) == key)
     }
     
     fn extract_diagnoses_from_observations(&self, _document: &mut CDADocument) {
@@ -531,12 +2059,60 @@ impl CDAParser {
         // pero por simplicidad, los inferimos del contexto
     }
 
-    fn is_duplicate_medication(&self, document: &CDADocument, med_name: &str) -> bool {
-        document.medications.iter().any(|m| m.name.to_lowercase() == med_name.to_lowercase())
+    // Un medicamento ya extraído por otra vía suma la nueva evidencia en vez de duplicarse
+    fn add_medication(&self, document: &mut CDADocument, medication: Medication) {
+        match document.medications.iter_mut().find(|m| hierarchy::name_key(&m.name) == hierarchy::name_key(&medication.name)) {
+            Some(existing) => self.merge_medication(existing, medication),
+            None => document.medications.push(medication),
+        }
+    }
+
+    fn add_allergy(&self, document: &mut CDADocument, allergy: Allergy) {
+        match document.allergies.iter_mut().find(|a| hierarchy::name_key(&a.name) == hierarchy::name_key(&allergy.name)) {
+            Some(existing) => {
+                if existing.code.is_none() && allergy.code.is_some() {
+                    existing.code = allergy.code;
+                    existing.code_system = allergy.code_system;
+                    existing.code_system_name = allergy.code_system_name;
+                }
+                provenance::merge_evidence(&mut existing.evidence, allergy.evidence);
+            }
+            None => document.allergies.push(allergy),
+        }
+    }
+
+    fn add_diagnosis(&self, document: &mut CDADocument, diagnosis: Diagnosis) {
+        match document.diagnoses.iter_mut().find(|d| hierarchy::name_key(&d.name) == hierarchy::name_key(&diagnosis.name)) {
+            Some(existing) => self.merge_diagnosis(existing, diagnosis),
+            None => document.diagnoses.push(diagnosis),
+        }
+    }
+
+    fn merge_medication(&self, target: &mut Medication, other: Medication) {
+        if target.code.is_none() && other.code.is_some() {
+            target.name = other.name;
+            target.code = other.code;
+            target.code_system = other.code_system;
+            target.code_system_name = other.code_system_name;
+        }
+        target.display_name = target.display_name.take().or(other.display_name);
+        target.dose = target.dose.take().or(other.dose);
+        target.frequency = target.frequency.take().or(other.frequency);
+        target.route = target.route.take().or(other.route);
+        provenance::merge_evidence(&mut target.evidence, other.evidence);
     }
 
-    fn is_duplicate_diagnosis(&self, document: &CDADocument, diag_name: &str) -> bool {
-        document.diagnoses.iter().any(|d| d.name.to_lowercase() == diag_name.to_lowercase())
+    fn merge_diagnosis(&self, target: &mut Diagnosis, other: Diagnosis) {
+        // Variantes del mismo nombre ("hipertension", "Hipertensión"): se queda el del registro codificado
+        if target.code.is_none() && other.code.is_some() {
+            target.name = other.name;
+            target.code = other.code;
+            target.code_system = other.code_system;
+            target.code_system_name = other.code_system_name;
+        }
+        target.original_text = target.original_text.take().or(other.original_text);
+        target.value = target.value.take().or(other.value);
+        provenance::merge_evidence(&mut target.evidence, other.evidence);
     }
 
     fn is_diagnosis_context(&self, path: &[String]) -> bool {
@@ -546,19 +2122,9 @@ impl CDAParser {
         })
     }
 
-    fn normalize_gender(&self, gender_code: &str) -> String {
-        //console_log!("RUST normalize_gender: input='{}' len={}", gender_code, gender_code.len());
-        let result = match gender_code.to_uppercase().as_str() {
-            "M" | "MALE" => "M".to_string(),
-            "F" | "FEMALE" => "F".to_string(),
-            _ => {
-                //console_log!("RUST: Género no reconocido: '{}'", gender_code);
-                "Unknown".to_string()
-            }
-        };
-        
-        //console_log!("RUST normalize_gender: output='{}'", result);
-        result
+    // Observación dentro del entryRelationship de otra: estado, gravedad u otro dato de apoyo
+    fn is_supporting_observation(&self, path: &[String]) -> bool {
+        path.iter().filter(|p| p.as_str() == "observation").count() >= 2
     }
 
     fn calculate_age_from_hl7_date(&self, hl7_date: &str) -> Option<u32> {
@@ -576,94 +2142,767 @@ impl CDAParser {
         None
     }
 
+    // Fecha HL7 TS: AAAA[MM[DD[HH[MM[SS]]]]] seguida de fracción o zona horaria opcionales
+    fn is_valid_hl7_date(&self, value: &str) -> bool {
+        let digits: String = value.trim().chars().take_while(|c| c.is_ascii_digit()).collect();
+        if ![4, 6, 8, 10, 12, 14].contains(&digits.len()) {
+            return false;
+        }
+        let part = |range: std::ops::Range<usize>| digits.get(range).map(|p| p.parse::<u32>().unwrap_or(0));
+        let year = digits[0..4].parse::<i32>().unwrap_or(0);
+        let month = part(4..6).unwrap_or(1);
+        let day = part(6..8).unwrap_or(1);
+        let time_ok = part(8..10).unwrap_or(0) < 24
+            && part(10..12).unwrap_or(0) < 60
+            && part(12..14).unwrap_or(0) < 60;
+        time_ok && chrono::NaiveDate::from_ymd_opt(year, month, day).is_some()
+    }
+
+    fn check_date(&self, document: &mut CDADocument, field: &str, value: &str) {
+        if !self.is_valid_hl7_date(value) {
+            document.warnings.push(ParseWarning::new(
+                "invalid_date",
+                format!("{} '{}' is not a valid HL7 date", field, value),
+                None,
+            ));
+        }
+    }
+
+    // Elementos que cierra </tag_name>; en modo tolerante cierra también los que quedaron abiertos
+    fn elements_to_close(
+        &self,
+        document: &mut CDADocument,
+        path: &[String],
+        tag_name: &str,
+        xml: &str,
+        offset: usize,
+    ) -> Vec<String> {
+        if path.last().map(|t| t == tag_name).unwrap_or(false) {
+            return vec![tag_name.to_string()];
+        }
+        // Línea y columna sólo cuando hay aviso: calcularlas recorre el XML desde el principio
+        let position = Position::new(xml, offset);
+        match path.iter().rposition(|t| t == tag_name) {
+            Some(index) => {
+                for open in path[index + 1..].iter().rev() {
+                    document.warnings.push(ParseWarning::new(
+                        "unclosed_element",
+                        format!("<{}> closed implicitly by </{}>", open, tag_name),
+                        Some(position),
+                    ));
+                }
+                path[index..].iter().rev().cloned().collect()
+            }
+            None => {
+                document.warnings.push(ParseWarning::new(
+                    "mismatched_end_tag",
+                    format!("unexpected </{}> ignored", tag_name),
+                    Some(position),
+                ));
+                Vec::new()
+            }
+        }
+    }
+
     fn post_process_document(&self, document: &mut CDADocument) {
-        // Limpiar nombre del paciente
-        if let Some(ref mut name) = document.patient.name {
-            *name = name.split_whitespace().collect::<Vec<&str>>().join(" ");
-            if name.is_empty() {
-                document.patient.name = None;
+        // Nombre para mostrar de cada paciente: el legal o el primero disponible
+        for patient in document.patients.iter_mut() {
+            patient.name = person::preferred_name(&patient.names).and_then(|n| n.display());
+        }
+        document.author = person::preferred_name(&document.author_names).and_then(|n| n.display());
+        for (index, patient) in document.patients.iter().enumerate() {
+            if patient.id.is_none() {
+                document.warnings.push(ParseWarning::new(
+                    "missing_patient_id",
+                    format!("recordTarget {} has no patient id", index + 1),
+                    None,
+                ));
+            }
+        }
+        // Resolver referencias que apuntan a otras secciones del documento
+        let sections = document.sections.clone();
+        for section in document.sections.iter_mut() {
+            for reference in section.references.iter_mut().filter(|r| r.text.is_none()) {
+                reference.text = narrative::resolve_reference(&sections, &reference.reference);
             }
         }
 
-        // Inferir diagnósticos de medicamentos si no hay diagnósticos
-        if document.diagnoses.is_empty() {
-            self.infer_diagnoses_from_medications(document);
+        // Nombres de las tablas de terminología; también etiquetan los códigos sin displayName
+        self.apply_terminology(document);
+
+        // Códigos sin displayName ni texto narrativo: usar el propio código
+        for diagnosis in document.diagnoses.iter_mut().filter(|d| d.name.is_empty()) {
+            diagnosis.name = diagnosis.original_text.clone()
+                .or_else(|| diagnosis.code.clone())
+                .unwrap_or_default();
         }
-        
+        // Un código con nullFlavor y sin texto no identifica ningún diagnóstico
+        document.diagnoses.retain(|d| !d.name.is_empty());
+
         // Extraer diagnósticos de observaciones clínicas
         self.extract_diagnoses_from_observations(document);
 
-        // Remover duplicados de diagnósticos
-        document.diagnoses.sort_by(|a, b| a.name.cmp(&b.name));
-        document.diagnoses.dedup_by(|a, b| a.name.to_lowercase() == b.name.to_lowercase());
+        // Fusionar duplicados de diagnósticos conservando la evidencia de ambos; "Diabetes" y
+        // "diabetes", o "Hipertensión" e "hipertension", son el mismo
+        document.diagnoses.sort_by_cached_key(|d| hierarchy::name_key(&d.name));
+        document.diagnoses.dedup_by(|a, b| {
+            let duplicate = hierarchy::name_key(&a.name) == hierarchy::name_key(&b.name);
+            if duplicate {
+                self.merge_diagnosis(b, std::mem::take(a));
+            }
+            duplicate
+        });
+
+        // Fusionar duplicados de medicamentos
+        document.medications.sort_by_cached_key(|m| hierarchy::name_key(&m.name));
+        document.medications.dedup_by(|a, b| {
+            let duplicate = hierarchy::name_key(&a.name) == hierarchy::name_key(&b.name);
+            if duplicate {
+                self.merge_medication(b, a.clone());
+            }
+            duplicate
+        });
+
+        for diagnosis in document.diagnoses.iter_mut() {
+            diagnosis.confidence = provenance::combined_confidence(&diagnosis.evidence);
+        }
+        for medication in document.medications.iter_mut() {
+            medication.confidence = provenance::combined_confidence(&medication.evidence);
+            // El displayName codificado ("Warfarin 5mg") suele traer la concentración que falta
+            // en <name>; la dosis administrada completa la de un único principio activo
+            let normalize = |name: &str| self.normalize_medication_name(name);
+            let from_name = strength::parse(&medication.name, normalize);
+            let mut product = match medication.display_name.as_deref().map(|n| strength::parse(n, normalize)) {
+                Some(coded) if !coded.ingredients.is_empty() => coded,
+                _ => from_name.clone(),
+            };
+            product.dose_form = product.dose_form.or(from_name.dose_form);
+            if let [ingredient] = product.ingredients.as_mut_slice() {
+                if ingredient.strength.is_none() {
+                    ingredient.strength = medication.dose.as_deref().and_then(strength::parse_strength);
+                }
+            }
+            medication.ingredients = product.ingredients;
+            medication.dose_form = product.dose_form;
+        }
+        for allergy in document.allergies.iter_mut() {
+            allergy.confidence = provenance::combined_confidence(&allergy.evidence);
+        }
+
+        // Nombre del sistema de cada código, validación sintáctica y OIDs desconocidos
+        self.annotate_code_systems(document);
+        self.assign_icd10(document);
+        self.normalize_observation_units(document);
+        // Antes de marcar anormales, para que los valores calculados también se marquen
+        let derived = derived::derive(&document.observations, document.patients.first());
+        document.observations.extend(derived);
+        self.flag_abnormal_results(document);
+        for medication in document.medications.iter_mut() {
+            medication.atc =
+                self.atc_table.classify(medication.code_system.as_deref(), medication.code.as_deref(), &medication.name);
+        }
+        for allergy in document.allergies.iter_mut() {
+            allergy.atc = self.atc_table.classify(allergy.code_system.as_deref(), allergy.code.as_deref(), &allergy.name);
+        }
+        document.alerts = self.interactions.check(&document.medications);
+        document.alerts.extend(self.contraindications.check(
+            &document.medications,
+            &document.diagnoses,
+            &document.allergies,
+            &self.snomed_hierarchy,
+        ));
+        document.alerts.sort_by(|a, b| b.severity.cmp(&a.severity).then(a.rule.cmp(&b.rule)));
+        document.patient = document.patients.first().cloned().unwrap_or_default();
 
-        // Remover duplicados de medicamentos
-        document.medications.sort_by(|a, b| a.name.cmp(&b.name));
-        document.medications.dedup_by(|a, b| a.name.to_lowercase() == b.name.to_lowercase());
+        // Inferencia opcional desde la medicación, aparte de los diagnósticos del documento
+        if self.infer_conditions {
+            self.infer_diagnoses_from_medications(document);
+        }
         
         //console_log!("Post-procesamiento completado: {} diagnósticos, {} medicamentos", document.diagnoses.len(), document.medications.len());
     }
 
-    fn calculate_statistics(&self) -> Statistics {
+    fn apply_terminology(&self, document: &mut CDADocument) {
+        if self.terminology.is_empty() {
+            return;
+        }
+
+        for diagnosis in document.diagnoses.iter_mut() {
+            let names = self.term_names(diagnosis.code_system.as_deref(), diagnosis.code.as_deref()).or_else(|| {
+                // Código local en el diagnóstico: probar las traducciones del valor
+                let coded = diagnosis.value.as_ref()?.coded()?;
+                coded.translations.iter().find_map(|t| self.term_names(t.code_system.as_deref(), t.code.as_deref()))
+            });
+            if let Some(names) = names {
+                if diagnosis.name.is_empty() || diagnosis.code.as_ref() == Some(&diagnosis.name) {
+                    diagnosis.name = names.preferred().unwrap_or_default();
+                }
+                diagnosis.display_name_es = names.es;
+                diagnosis.display_name_en = names.en;
+            }
+        }
+
+        for observation in document.observations.iter_mut() {
+            if let Some(names) = self.term_names(observation.code_system.as_deref(), observation.code.as_deref()) {
+                if observation.name.is_empty() || observation.code.as_ref() == Some(&observation.name) {
+                    observation.name = names.preferred().unwrap_or_default();
+                }
+                observation.display_name_es = names.es;
+                observation.display_name_en = names.en;
+            }
+        }
+    }
+
+    fn term_names(&self, code_system: Option<&str>, code: Option<&str>) -> Option<TermNames> {
+        self.terminology.lookup(code_system?, code?).cloned()
+    }
+
+    fn assign_icd10(&self, document: &mut CDADocument) {
+        // Reglas de edad y sexo: paciente principal; reglas "IFA hallazgo": diagnósticos SNOMED CT del documento
+        let patient = document.patients.first();
+        let context = MapContext {
+            sex: patient.and_then(|p| p.gender.clone()),
+            age_years: patient.and_then(|p| p.age).map(f64::from),
+            findings: document
+                .diagnoses
+                .iter()
+                .filter_map(|d| d.code_in(&[code_systems::SNOMED_CT]))
+                .map(|code| code.to_string())
+                .collect(),
+        };
+
+        for diagnosis in document.diagnoses.iter_mut() {
+            let mut codes: Vec<Icd10Code> = diagnosis
+                .codes_in(&[code_systems::ICD10, code_systems::ICD10_CM])
+                .into_iter()
+                .map(Icd10Code::from_document)
+                .collect();
+            for code in diagnosis.codes_in(&[code_systems::SNOMED_CT]) {
+                codes.extend(self.crosswalk.map(code, &context));
+            }
+            let mut seen = Vec::new();
+            codes.retain(|c| {
+                let new = !seen.contains(&c.code);
+                seen.push(c.code.clone());
+                new
+            });
+            diagnosis.icd10 = codes;
+        }
+    }
+
+    // Valores PQ a la unidad canónica de su código LOINC; sin código, sólo la unidad en UCUM
+    fn normalize_observation_units(&self, document: &mut CDADocument) {
+        for observation in document.observations.iter_mut() {
+            let (value, unit) = match &observation.value {
+                Some(Value::Pq(PhysicalQuantity { value: Some(value), unit, .. })) => (*value, unit.as_deref()),
+                _ => continue,
+            };
+            let code = observation.code.as_deref().filter(|_| observation.code_system.as_deref() == Some(code_systems::LOINC));
+            match self.canonical_units.normalize(code, value, unit) {
+                Ok(normalized) => observation.normalized_value = Some(normalized),
+                Err(reason) => document.warnings.push(ParseWarning::new(
+                    "unit_conversion",
+                    format!("observation '{}': {}", observation.name, reason),
+                    None,
+                )),
+            }
+        }
+    }
+
+    // H/L/HH/LL/N: el interpretationCode del documento y, si no hay, el valor normalizado contra
+    // el referenceRange o contra el rango por defecto del código LOINC para el sexo y la edad
+    // del paciente. Los límites críticos sólo vienen de la tabla por defecto
+    fn flag_abnormal_results(&self, document: &mut CDADocument) {
+        let patient = document.patients.first();
+        let sex = patient.and_then(|p| p.gender.clone());
+        let age = patient.and_then(|p| p.age);
+
+        for observation in document.observations.iter_mut() {
+            if let Some(flag) = observation.interpretation_code.as_deref().and_then(reference_ranges::interpretation_flag) {
+                observation.abnormal_flag = Some(AbnormalFlag {
+                    flag: flag.to_string(),
+                    source: "interpretation_code".to_string(),
+                    low: None,
+                    high: None,
+                    critical_low: None,
+                    critical_high: None,
+                });
+                continue;
+            }
+            let value = match &observation.normalized_value {
+                Some(value) => value,
+                None => continue,
+            };
+            let code = observation.code.as_deref().filter(|_| observation.code_system.as_deref() == Some(code_systems::LOINC));
+            let value_unit = match &observation.value {
+                Some(Value::Pq(pq)) => pq.unit.as_deref(),
+                _ => None,
+            };
+            let bound = |limit: Option<f64>, unit: Option<&str>| limit.and_then(|l| self.bound_in(code, l, unit, value));
+            let default = code.and_then(|c| self.reference_ranges.lookup(c, sex.as_deref(), age));
+
+            let (mut source, mut low, mut high) = ("reference_range", None, None);
+            if let Some(Value::IvlPq { low: range_low, high: range_high, .. }) = &observation.reference_range {
+                let limit = |pq: &Option<PhysicalQuantity>| {
+                    pq.as_ref().and_then(|pq| bound(pq.value, pq.unit.as_deref().or(value_unit)))
+                };
+                (low, high) = (limit(range_low), limit(range_high));
+            }
+            if low.is_none() && high.is_none() {
+                match default {
+                    Some(range) => {
+                        source = "default_range";
+                        low = bound(range.low, Some(&range.unit));
+                        high = bound(range.high, Some(&range.unit));
+                    }
+                    None => continue,
+                }
+            }
+            let critical_low = default.and_then(|r| bound(r.critical_low, Some(&r.unit)));
+            let critical_high = default.and_then(|r| bound(r.critical_high, Some(&r.unit)));
+            if low.is_none() && high.is_none() && critical_low.is_none() && critical_high.is_none() {
+                continue;
+            }
+
+            observation.abnormal_flag = Some(AbnormalFlag {
+                flag: reference_ranges::flag(value.value, low, high, critical_low, critical_high).to_string(),
+                source: source.to_string(),
+                low,
+                high,
+                critical_low,
+                critical_high,
+            });
+        }
+    }
+
+    // Límite de un rango en la unidad del valor normalizado
+    fn bound_in(&self, code: Option<&str>, limit: f64, unit: Option<&str>, target: &NormalizedValue) -> Option<f64> {
+        let normalized = self.canonical_units.normalize(code, limit, unit).ok()?;
+        if normalized.unit == target.unit {
+            return Some(normalized.value);
+        }
+        let from = ucum::Unit::parse(&normalized.unit).ok()?;
+        let to = ucum::Unit::parse(&target.unit).ok()?;
+        ucum::convert(normalized.value, &from, &to, None).ok()
+    }
+
+    fn annotate_code_systems(&self, document: &mut CDADocument) {
+        let mut report = CodeSystemReport::default();
+
+        for (index, patient) in document.patients.iter_mut().enumerate() {
+            let location = format!("recordTarget {}", index + 1);
+            for coding in patient.gender_coding.iter_mut().chain(patient.gender_identity.iter_mut()) {
+                coding.code_system_name = report.annotate(
+                    coding.code_system.as_deref(),
+                    coding.code.as_deref(),
+                    coding.code_system_name.as_deref(),
+                    &location,
+                );
+            }
+        }
+
+        for diagnosis in document.diagnoses.iter_mut() {
+            let location = format!("diagnosis '{}'", diagnosis.name);
+            if let Some(coded) = diagnosis.value.as_mut().and_then(|v| v.coded_mut()) {
+                self.annotate_coded_value(&mut report, coded, &location);
+            }
+            // El código del diagnóstico suele ser el del valor: no contarlo dos veces
+            let same_as_value = diagnosis.value.as_ref().and_then(|v| v.coded()).map(|c| {
+                (c.code.as_deref(), c.code_system.as_deref()) == (diagnosis.code.as_deref(), diagnosis.code_system.as_deref())
+            });
+            diagnosis.code_system_name = match same_as_value {
+                Some(true) => diagnosis.value.as_ref().and_then(|v| v.coded()).and_then(|c| c.code_system_name.clone()),
+                _ => report.annotate(
+                    diagnosis.code_system.as_deref(),
+                    diagnosis.code.as_deref(),
+                    diagnosis.code_system_name.as_deref(),
+                    &location,
+                ),
+            };
+        }
+
+        for medication in document.medications.iter_mut() {
+            let location = format!("medication '{}'", medication.name);
+            medication.code_system_name = report.annotate(
+                medication.code_system.as_deref(),
+                medication.code.as_deref(),
+                medication.code_system_name.as_deref(),
+                &location,
+            );
+        }
+
+        for allergy in document.allergies.iter_mut() {
+            let location = format!("allergy '{}'", allergy.name);
+            allergy.code_system_name = report.annotate(
+                allergy.code_system.as_deref(),
+                allergy.code.as_deref(),
+                allergy.code_system_name.as_deref(),
+                &location,
+            );
+        }
+
+        for observation in document.observations.iter_mut() {
+            let location = format!("observation '{}'", observation.name);
+            observation.code_system_name = report.annotate(
+                observation.code_system.as_deref(),
+                observation.code.as_deref(),
+                observation.code_system_name.as_deref(),
+                &location,
+            );
+            for value in observation.value.iter_mut().chain(observation.reference_range.iter_mut()) {
+                if let Some(coded) = value.coded_mut() {
+                    self.annotate_coded_value(&mut report, coded, &location);
+                }
+            }
+        }
+
+        for invalid in report.invalid {
+            document.warnings.push(ParseWarning::new(
+                "invalid_code",
+                format!("{} code '{}' in {}: {}", invalid.system, invalid.code, invalid.location, invalid.reason),
+                None,
+            ));
+        }
+        document.unknown_code_systems = report.unknown;
+    }
+
+    fn annotate_coded_value(&self, report: &mut CodeSystemReport, coded: &mut CodedValue, location: &str) {
+        coded.code_system_name = report.annotate(
+            coded.code_system.as_deref(),
+            coded.code.as_deref(),
+            coded.code_system_name.as_deref(),
+            location,
+        );
+        for translation in coded.translations.iter_mut() {
+            self.annotate_coded_value(report, translation, location);
+        }
+    }
+
+    // Grupo de top_medications: el nombre, o los principios activos ("Enalapril + Hidroclorotiazida")
+    fn medication_group(&self, medication: &Medication) -> (String, String) {
+        let name = match self.medication_grouping {
+            MedicationGrouping::Ingredient if !medication.ingredients.is_empty() => medication
+                .ingredients
+                .iter()
+                .map(|i| i.name.as_str())
+                .collect::<Vec<&str>>()
+                .join(" + "),
+            _ => return (medication.name.clone(), medication.name.clone()),
+        };
+        (tables::normalize_header(&name), name)
+    }
+
+    // Pares (campo, motivo) de los datos ausentes de un documento
+    fn missing_data(&self, doc: &CDADocument) -> Vec<(String, String)> {
+        let mut missing = Vec::new();
+        let mut check = |field: &str, present: bool, null_flavors: &NullFlavors, key: &str| {
+            if let Some(null_flavor) = null_flavors.get(key) {
+                missing.push((field.to_string(), null_flavor.code().to_string()));
+            } else if !present {
+                missing.push((field.to_string(), "missing".to_string()));
+            }
+        };
+
+        // Cada recordTarget cuenta; sin ninguno, el paciente vacío del documento
+        let patients = if doc.patients.is_empty() { std::slice::from_ref(&doc.patient) } else { &doc.patients[..] };
+        for patient in patients {
+            check("patient.id", patient.id.is_some(), &patient.null_flavors, "id");
+            check("patient.name", patient.name.is_some(), &patient.null_flavors, "name");
+            check("patient.gender", patient.gender.is_some(), &patient.null_flavors, "gender");
+            check("patient.birth_date", patient.birth_date.is_some(), &patient.null_flavors, "birth_date");
+        }
+        check("document_date", doc.document_date.is_some(), &doc.null_flavors, "document_date");
+        check("author", doc.author.is_some(), &doc.null_flavors, "author");
+
+        for diagnosis in &doc.diagnoses {
+            for (key, null_flavor) in &diagnosis.null_flavors {
+                missing.push((format!("diagnosis.{}", key), null_flavor.code().to_string()));
+            }
+        }
+        for medication in &doc.medications {
+            for (key, null_flavor) in &medication.null_flavors {
+                missing.push((format!("medication.{}", key), null_flavor.code().to_string()));
+            }
+        }
+
+        missing
+    }
+
+    fn calculate_statistics(&self, min_confidence: f64) -> Statistics {
         let mut gender_distribution = HashMap::new();
-        let mut diagnosis_counts: HashMap<String, u32> = HashMap::new();
-        let mut medication_counts: HashMap<String, u32> = HashMap::new();
+        let mut diagnosis_counts = RollupCounter::default();
+        let mut inferred_counts: HashMap<String, u32> = HashMap::new();
+        let mut undocumented_conditions = 0u32;
+        let mut medication_counts: HashMap<String, (String, u32)> = HashMap::new();
+        let mut class_counts: HashMap<String, (Option<String>, u32)> = HashMap::new();
+        let mut medications_without_atc = 0u32;
+        let mut alerts_by_type: HashMap<String, u32> = HashMap::new();
+        let mut alerts_by_severity: HashMap<String, u32> = HashMap::new();
+        let mut alert_counts: HashMap<(String, String, String), u32> = HashMap::new();
+        let mut documents_with_alerts = 0u32;
+        let mut attachment_media_types: HashMap<String, u32> = HashMap::new();
+        let mut missing_counts: HashMap<(String, String), u32> = HashMap::new();
+        let mut chapter_counts: HashMap<String, u32> = HashMap::new();
+        let mut diagnoses_without_icd10 = 0u32;
+        let mut lab_results: Vec<LabResultSummary> = Vec::new();
+        let mut observations_without_canonical_unit = 0u32;
         let mut total_age = 0u32;
         let mut age_count = 0u32;
 
         use std::time::Instant;
         let start_time = Instant::now();
 
-        for doc in &self.documents {
-            // Contar géneros
-            let gender = doc.patient.gender.as_deref().unwrap_or("Unknown");
-            *gender_distribution.entry(gender.to_string()).or_insert(0) += 1;
+        let mut total_patients = 0u32;
+        let mut deceased_patients = 0u32;
+
+        for (doc_index, doc) in self.documents.iter().enumerate() {
+            // Un documento sin recordTarget cuenta como un paciente desconocido
+            let patients = if doc.patients.is_empty() {
+                std::slice::from_ref(&doc.patient)
+            } else {
+                &doc.patients[..]
+            };
+            total_patients += patients.len() as u32;
+
+            if patients.iter().all(|p| p.deceased == Some(true)) {
+                deceased_patients += patients.len() as u32;
+                continue;
+            }
+
+            for patient in patients {
+                // Los fallecidos no forman parte de la población activa
+                if patient.deceased == Some(true) {
+                    deceased_patients += 1;
+                    continue;
+                }
+
+                // Contar géneros
+                let gender = patient.gender.as_deref().unwrap_or("Unknown");
+                *gender_distribution.entry(gender.to_string()).or_insert(0) += 1;
 
-            // Calcular edad promedio
-            if let Some(age) = doc.patient.age {
-                total_age += age;
-                age_count += 1;
+                // Calcular edad promedio
+                if let Some(age) = patient.age {
+                    total_age += age;
+                    age_count += 1;
+                }
             }
 
             // Contar diagnósticos
-            for diagnosis in &doc.diagnoses {
-                *diagnosis_counts.entry(diagnosis.name.clone()).or_insert(0) += 1;
+            for diagnosis in doc.diagnoses.iter().filter(|d| d.confidence >= min_confidence) {
+                let group = self.snomed_hierarchy.rollup(diagnosis, self.rollup_level, &self.terminology, &self.dictionaries);
+                diagnosis_counts.add(doc_index, group, &diagnosis.name);
+
+                // Cada capítulo una vez por diagnóstico, aunque tenga varios códigos en él
+                let mut chapters: Vec<&String> = diagnosis.icd10.iter().filter_map(|c| c.chapter.as_ref()).collect();
+                chapters.sort();
+                chapters.dedup();
+                if diagnosis.icd10.is_empty() {
+                    diagnoses_without_icd10 += 1;
+                }
+                for chapter in chapters {
+                    *chapter_counts.entry(chapter.clone()).or_insert(0) += 1;
+                }
+            }
+
+            // Contar condiciones inferidas sin mezclarlas con los diagnósticos
+            for condition in doc.inferred_conditions.iter().filter(|c| c.confidence >= min_confidence) {
+                *inferred_counts.entry(condition.name.clone()).or_insert(0) += 1;
+                if !condition.documented {
+                    undocumented_conditions += 1;
+                }
             }
 
             // Contar medicamentos
-            for medication in &doc.medications {
-                *medication_counts.entry(medication.name.clone()).or_insert(0) += 1;
+            // Cada clase terapéutica una vez por documento, aunque haya varios medicamentos de ella
+            let mut classes: Vec<&atc::AtcLevel> = Vec::new();
+            let mut medication_groups: Vec<(String, String)> = Vec::new();
+            for medication in doc.medications.iter().filter(|m| m.confidence >= min_confidence) {
+                let group = self.medication_group(medication);
+                if !medication_groups.iter().any(|g| g.0 == group.0) {
+                    medication_groups.push(group);
+                }
+                if medication.atc.is_empty() {
+                    medications_without_atc += 1;
+                }
+                for class in medication.atc.iter().filter_map(|c| c.level(self.atc_level)) {
+                    if !classes.iter().any(|c| c.code == class.code) {
+                        classes.push(class);
+                    }
+                }
+            }
+            // Como las clases, cada grupo una vez por documento
+            for (key, name) in medication_groups {
+                medication_counts.entry(key).or_insert((name, 0)).1 += 1;
+            }
+            for class in classes {
+                let entry = class_counts.entry(class.code.clone()).or_insert((class.name.clone(), 0));
+                entry.1 += 1;
+            }
+
+            // Contar alertas
+            let alerts: Vec<&Alert> = doc.alerts.iter().filter(|a| a.confidence >= min_confidence).collect();
+            if !alerts.is_empty() {
+                documents_with_alerts += 1;
+            }
+            for alert in alerts {
+                let severity = alert.severity.name().to_string();
+                *alerts_by_type.entry(alert.alert_type.clone()).or_insert(0) += 1;
+                *alerts_by_severity.entry(severity.clone()).or_insert(0) += 1;
+                *alert_counts.entry((alert.alert_type.clone(), alert.rule.clone(), severity)).or_insert(0) += 1;
+            }
+
+            // Resultados por código LOINC y unidad canónica
+            for observation in &doc.observations {
+                if !matches!(observation.value, Some(Value::Pq(PhysicalQuantity { value: Some(_), .. }))) {
+                    continue;
+                }
+                let normalized = match &observation.normalized_value {
+                    Some(normalized) => normalized,
+                    None => {
+                        observations_without_canonical_unit += 1;
+                        continue;
+                    }
+                };
+                let code = match observation.code.as_ref() {
+                    Some(code) if observation.code_system.as_deref() == Some(code_systems::LOINC) => code,
+                    _ => continue,
+                };
+                let index = match lab_results.iter().position(|r| &r.code == code && r.unit == normalized.unit) {
+                    Some(index) => index,
+                    None => {
+                        lab_results.push(LabResultSummary {
+                            code: code.clone(),
+                            name: observation.display_name_es.clone().unwrap_or_else(|| observation.name.clone()),
+                            unit: normalized.unit.clone(),
+                            count: 0,
+                            min: normalized.value,
+                            max: normalized.value,
+                            mean: 0.0,
+                            flagged: 0,
+                            abnormal: 0,
+                            abnormal_percent: None,
+                        });
+                        lab_results.len() - 1
+                    }
+                };
+                let result = &mut lab_results[index];
+                result.count += 1;
+                result.min = result.min.min(normalized.value);
+                result.max = result.max.max(normalized.value);
+                result.mean += normalized.value;
+                if let Some(flag) = &observation.abnormal_flag {
+                    result.flagged += 1;
+                    if flag.is_abnormal() {
+                        result.abnormal += 1;
+                    }
+                }
+            }
+
+            // Contar documentos no estructurados (nonXMLBody) por tipo
+            for attachment in &doc.attachments {
+                *attachment_media_types.entry(attachment.media_type.clone()).or_insert(0) += 1;
+            }
+
+            // Contar datos ausentes por campo y motivo
+            for (field, reason) in self.missing_data(doc) {
+                *missing_counts.entry((field, reason)).or_insert(0) += 1;
             }
         }
 
-        // Top diagnósticos
-        let mut diagnosis_vec: Vec<_> = diagnosis_counts.into_iter().collect();
-        diagnosis_vec.sort_by(|a, b| b.1.cmp(&a.1));
-        let top_diagnoses: Vec<DiagnosisCount> = diagnosis_vec
+        let mut missing_data: Vec<MissingDataCount> = missing_counts
+            .into_iter()
+            .map(|((field, reason), count)| MissingDataCount { field, reason, count })
+            .collect();
+        missing_data.sort_by(|a, b| a.field.cmp(&b.field).then(a.reason.cmp(&b.reason)));
+
+        let mut icd10_chapters: Vec<Icd10ChapterCount> = chapter_counts
+            .into_iter()
+            .map(|(chapter, count)| {
+                let (range, title) = icd10::chapter_info(&chapter).unwrap_or_default();
+                Icd10ChapterCount { chapter, range, title: title.to_string(), count }
+            })
+            .collect();
+        icd10_chapters.sort_by_key(|c| icd10::chapter_order(&c.chapter));
+
+        // Top diagnósticos al nivel de agrupación elegido
+        let top_diagnoses: Vec<DiagnosisCount> = diagnosis_counts
+            .top(5)
+            .into_iter()
+            .map(|(code, name, count)| DiagnosisCount { code, name, count })
+            .collect();
+
+        let mut inferred_vec: Vec<_> = inferred_counts.into_iter().collect();
+        inferred_vec.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let top_inferred_conditions: Vec<DiagnosisCount> = inferred_vec
             .into_iter()
             .take(5)
-            .map(|(name, count)| DiagnosisCount { name, count })
+            .map(|(name, count)| DiagnosisCount { code: None, name, count })
             .collect();
 
         // Top medicamentos
-        let mut medication_vec: Vec<_> = medication_counts.into_iter().collect();
-        medication_vec.sort_by(|a, b| b.1.cmp(&a.1));
+        let mut medication_vec: Vec<_> = medication_counts.into_values().collect();
+        medication_vec.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
         let top_medications: Vec<MedicationCount> = medication_vec
             .into_iter()
             .take(5)
             .map(|(name, count)| MedicationCount { name, count })
             .collect();
+
+        // Top clases terapéuticas
+        let mut top_therapeutic_classes: Vec<TherapeuticClassCount> = class_counts
+            .into_iter()
+            .map(|(code, (name, count))| TherapeuticClassCount { code, name, count })
+            .collect();
+        top_therapeutic_classes.sort_by(|a, b| b.count.cmp(&a.count).then(a.code.cmp(&b.code)));
+        top_therapeutic_classes.truncate(5);
+
+        // Top alertas por regla
+        let mut top_alerts: Vec<AlertCount> = alert_counts
+            .into_iter()
+            .map(|((alert_type, rule, severity), count)| AlertCount { alert_type, rule, severity, count })
+            .collect();
+        top_alerts.sort_by(|a, b| b.count.cmp(&a.count).then(a.rule.cmp(&b.rule)));
+        top_alerts.truncate(5);
+
+        // Hasta aquí `mean` acumulaba la suma; porcentaje de anormales con un decimal
+        for result in lab_results.iter_mut() {
+            result.mean = ucum::round(result.mean / result.count as f64);
+            if result.flagged > 0 {
+                result.abnormal_percent = Some((result.abnormal as f64 * 1000.0 / result.flagged as f64).round() / 10.0);
+            }
+        }
+        lab_results.sort_by(|a, b| a.code.cmp(&b.code).then(a.unit.cmp(&b.unit)));
         
         let duration = start_time.elapsed();
         let processing_time_ms = duration.as_millis() as u64;
 
         Statistics {
             total_documents: self.documents.len() as u32,
-            total_patients: self.documents.len() as u32,
+            total_patients,
+            active_patients: total_patients - deceased_patients,
+            deceased_patients,
             average_age: if age_count > 0 { total_age as f64 / age_count as f64 } else { 0.0 },
             gender_distribution,
             top_diagnoses,
+            rollup_level: self.rollup_level.name(),
+            top_inferred_conditions,
+            undocumented_conditions,
             top_medications,
+            medication_grouping: self.medication_grouping.name(),
+            top_therapeutic_classes,
+            atc_level: self.atc_level,
+            medications_without_atc,
+            alerts_by_type,
+            alerts_by_severity,
+            documents_with_alerts,
+            top_alerts,
+            attachment_media_types,
+            missing_data,
+            icd10_chapters,
+            diagnoses_without_icd10,
+            lab_results,
+            observations_without_canonical_unit,
+            min_confidence,
             processing_time_ms, // Usar el tiempo pasado como parámetro
         }
     }
@@ -673,4 +2912,212 @@ impl CDAParser {
 #[wasm_bindgen(start)]
 pub fn main() {
     //console_log!("CDA Parser WebAssembly cargado correctamente");
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(xml: &str) -> CDADocument {
+        CDAParser::new().parse_cda_document("test.xml", xml).expect("documento válido")
+    }
+
+    #[test]
+    fn gender_codes_without_code_system() {
+        let document = parse(
+            r#"<ClinicalDocument xmlns="urn:hl7-org:v3" xmlns:sdtc="urn:hl7-org:sdtc">
+              <recordTarget><patientRole><id extension="P1"/><patient>
+                <administrativeGenderCode code="H"/>
+                <sdtc:genderIdentity code="446141000124107" codeSystem="2.16.840.1.113883.6.96"/>
+              </patient></patientRole></recordTarget>
+              <recordTarget><patientRole><id extension="P2"/><patient>
+                <administrativeGenderCode code="mujer"/>
+              </patient></patientRole></recordTarget>
+              <recordTarget><patientRole><id extension="P3"/><patient>
+                <administrativeGenderCode code="UN" codeSystem="2.16.840.1.113883.5.1"/>
+              </patient></patientRole></recordTarget>
+            </ClinicalDocument>"#,
+        );
+        let genders: Vec<_> = document.patients.iter().map(|p| p.gender.as_deref()).collect();
+        assert_eq!(genders, [Some(gender::MALE), Some(gender::FEMALE), Some(gender::UNDIFFERENTIATED)]);
+        let identity = document.patients[0].gender_identity.as_ref().unwrap();
+        assert_eq!(identity.normalized, gender::FEMALE);
+        assert_eq!(identity.code_system.as_deref(), Some(code_systems::SNOMED_CT));
+    }
+
+    #[test]
+    fn coded_material_merges_into_existing_medication() {
+        let document = parse(
+            r#"<ClinicalDocument xmlns="urn:hl7-org:v3"><component><structuredBody><component><section>
+              <entry><substanceAdministration>
+                <consumable><manufacturedProduct><manufacturedMaterial>
+                  <name>Metformina</name>
+                </manufacturedMaterial></manufacturedProduct></consumable>
+              </substanceAdministration></entry>
+              <entry><substanceAdministration>
+                <doseQuantity value="850" unit="mg"/>
+                <consumable><manufacturedProduct><manufacturedMaterial>
+                  <code code="860975" codeSystem="2.16.840.1.113883.6.88" displayName="Metformin 850 MG Oral Tablet"/>
+                  <name>metformina</name>
+                </manufacturedMaterial></manufacturedProduct></consumable>
+              </substanceAdministration></entry>
+            </section></component></structuredBody></component></ClinicalDocument>"#,
+        );
+        assert_eq!(document.medications.len(), 1);
+        let medication = &document.medications[0];
+        assert_eq!(medication.code.as_deref(), Some("860975"));
+        assert_eq!(medication.display_name.as_deref(), Some("Metformin 850 MG Oral Tablet"));
+        assert_eq!(medication.dose.as_deref(), Some("850 mg"));
+    }
+
+    #[test]
+    fn strict_mode_rejects_only_invalid_data() {
+        let mut parser = CDAParser::new();
+        parser.mode = ParseMode::Strict;
+
+        let invalid_code = r#"<ClinicalDocument xmlns="urn:hl7-org:v3">
+          <recordTarget><patientRole><id extension="P1"/></patientRole></recordTarget>
+          <component><structuredBody><component><section>
+            <entry><observation><code code="2345-8" codeSystem="2.16.840.1.113883.6.1" displayName="Glucose"/></observation></entry>
+          </section></component></structuredBody></component>
+        </ClinicalDocument>"#;
+        let document = parser.parse_cda_document("test.xml", invalid_code).expect("invalid_code es informativo");
+        assert!(document.warnings.iter().any(|w| w.code == "invalid_code"));
+
+        let missing_id = r#"<ClinicalDocument xmlns="urn:hl7-org:v3">
+          <recordTarget><patientRole><patient/></patientRole></recordTarget>
+        </ClinicalDocument>"#;
+        match parser.parse_cda_document("test.xml", missing_id) {
+            Err(CdaError::InvalidData(warning)) => assert_eq!(warning.code, "missing_patient_id"),
+            other => panic!("expected missing_patient_id, got {:?}", other.map(|d| d.warnings)),
+        }
+    }
+
+    #[test]
+    fn lenient_mode_keeps_truncated_document() {
+        let truncated = r#"<ClinicalDocument xmlns="urn:hl7-org:v3">
+          <recordTarget><patientRole><id extension="P1"/><patient>
+            <administrativeGenderCode code="F" codeSystem="2.16.840.1.113883.5.1"/>
+          </patient></patientRole></recordTarget>
+          <component><structuredBody><component><section>
+            <title>Paciente hipertenso</title>
+            <entry><substanceAdministration><consumable><manufacturedProduct><manufacturedMaterial>
+              <name>Enalapril</name>
+            </manufacturedMaterial></manufacturedProduct></consumable></substanceAdministration></entry>
+            <entry><observation><code code="2345-7" codeSystem="2.16.840.1.113883.6.1"#;
+
+        assert!(matches!(CDAParser::new().parse_cda_document("test.xml", truncated), Err(CdaError::MalformedXml { .. })));
+
+        let mut parser = CDAParser::new();
+        parser.mode = ParseMode::Lenient;
+        let document = parser.parse_cda_document("test.xml", truncated).expect("documento parcial");
+        assert_eq!(document.patient.id.as_deref(), Some("P1"));
+        assert_eq!(document.patient.gender.as_deref(), Some(gender::FEMALE));
+        assert!(document.diagnoses.iter().any(|d| d.name == "Hipertensión"));
+        assert!(document.medications.iter().any(|m| m.name == "Enalapril"));
+        assert!(!document.warnings.is_empty());
+        assert!(document.warnings.iter().all(|w| w.position.is_some()));
+    }
+
+    #[test]
+    fn keyword_and_title_hits_share_one_diagnosis() {
+        let document = parse(
+            r#"<ClinicalDocument xmlns="urn:hl7-org:v3">
+              <title>Historia Clínica - Paciente Hipertenso Diabético</title>
+            </ClinicalDocument>"#,
+        );
+        let names: Vec<&str> = document.diagnoses.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["Diabetes", "Hipertensión"]);
+        for diagnosis in &document.diagnoses {
+            let methods: Vec<ExtractionMethod> = diagnosis.evidence.iter().map(|e| e.method).collect();
+            assert_eq!(methods, [ExtractionMethod::TitleMapping, ExtractionMethod::NarrativeRegex]);
+        }
+    }
+
+    #[test]
+    fn non_xml_body_without_media_type() {
+        let document = parse(
+            r#"<ClinicalDocument xmlns="urn:hl7-org:v3"><component><nonXMLBody>
+              <text representation="B64">UGFjaWVudGUgaGlwZXJ0ZW5zbw==</text>
+            </nonXMLBody></component></ClinicalDocument>"#,
+        );
+        let attachment = &document.attachments[0];
+        assert_eq!(attachment.media_type, "text/plain");
+        assert_eq!(attachment.text.as_deref(), Some("Paciente hipertenso"));
+        assert!(document.diagnoses.iter().any(|d| d.name == "Hipertensión"));
+    }
+
+    #[test]
+    fn invalid_attachment_is_reported() {
+        let document = parse(
+            r#"<ClinicalDocument xmlns="urn:hl7-org:v3"><component><nonXMLBody>
+              <text mediaType="text/plain" representation="B64">no es base64!</text>
+            </nonXMLBody></component></ClinicalDocument>"#,
+        );
+        assert_eq!(document.attachments.len(), 1);
+        assert!(document.warnings.iter().any(|w| w.code == "invalid_attachment"));
+    }
+
+    #[test]
+    fn statistics_skip_documents_of_deceased_patients() {
+        let document = |deceased: &str, title: &str| {
+            format!(
+                r#"<ClinicalDocument xmlns="urn:hl7-org:v3" xmlns:sdtc="urn:hl7-org:sdtc">
+                  <title>{}</title>
+                  <recordTarget><patientRole><id extension="P1"/><patient>
+                    <administrativeGenderCode code="F"/>
+                    <sdtc:deceasedInd value="{}"/>
+                  </patient></patientRole></recordTarget>
+                </ClinicalDocument>"#,
+                title, deceased
+            )
+        };
+        let mut parser = CDAParser::new();
+        for xml in [document("false", "Paciente asmático: asma"), document("true", "Paciente hipertenso")] {
+            let parsed = parser.parse_cda_document("test.xml", &xml).unwrap();
+            parser.documents.push(parsed);
+        }
+
+        let statistics = parser.calculate_statistics(0.0);
+        assert_eq!((statistics.total_patients, statistics.deceased_patients), (2, 1));
+        assert_eq!(statistics.gender_distribution.get(gender::FEMALE), Some(&1));
+        let names: Vec<&str> = statistics.top_diagnoses.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["Asma"]);
+        assert!(statistics.missing_data.iter().all(|m| m.count == 1));
+    }
+
+    #[test]
+    fn author_name_parts_and_missing_data_per_record_target() {
+        let document = parse(
+            r#"<ClinicalDocument xmlns="urn:hl7-org:v3">
+              <recordTarget><patientRole><id extension="P1"/><patient>
+                <name><given>Ana</given></name>
+                <administrativeGenderCode code="F"/><birthTime value="19800101"/>
+              </patient></patientRole></recordTarget>
+              <recordTarget><patientRole><patient>
+                <administrativeGenderCode nullFlavor="UNK"/>
+              </patient></patientRole></recordTarget>
+              <author><assignedAuthor><assignedPerson>
+                <name><prefix>Dra.</prefix><given>Elena</given><family>García</family></name>
+              </assignedPerson></assignedAuthor></author>
+              <legalAuthenticator><assignedEntity><assignedPerson>
+                <name><given>Luis</given></name>
+              </assignedPerson></assignedEntity></legalAuthenticator>
+            </ClinicalDocument>"#,
+        );
+        assert_eq!(document.author.as_deref(), Some("Elena García"));
+
+        let mut missing = CDAParser::new().missing_data(&document);
+        missing.sort();
+        let expected: Vec<(String, String)> = [
+            ("document_date", "missing"),
+            ("patient.birth_date", "missing"),
+            ("patient.gender", "UNK"),
+            ("patient.id", "missing"),
+            ("patient.name", "missing"),
+        ]
+        .iter()
+        .map(|(field, reason)| (field.to_string(), reason.to_string()))
+        .collect();
+        assert_eq!(missing, expected);
+    }
+}
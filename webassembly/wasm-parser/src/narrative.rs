@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+
+use crate::tables::NarrativeTable;
+
+// Elementos del bloque narrativo que delimitan un fragmento de texto propio
+const FRAGMENT_TAGS: [&str; 6] = ["paragraph", "item", "td", "th", "caption", "footnote"];
+
+// Nodo del árbol narrativo de <section><text>
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NarrativeNode {
+    Element {
+        tag: String,
+        id: Option<String>,
+        style_code: Option<String>,
+        children: Vec<NarrativeNode>,
+    },
+    Text {
+        text: String,
+    },
+}
+
+impl NarrativeNode {
+    pub fn element(tag: &str, id: Option<String>, style_code: Option<String>) -> Self {
+        NarrativeNode::Element {
+            tag: tag.to_string(),
+            id,
+            style_code,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        match self {
+            NarrativeNode::Element { tag, .. } => Some(tag),
+            NarrativeNode::Text { .. } => None,
+        }
+    }
+
+    pub fn children(&self) -> &[NarrativeNode] {
+        match self {
+            NarrativeNode::Element { children, .. } => children,
+            NarrativeNode::Text { .. } => &[],
+        }
+    }
+
+    // Texto plano del nodo y sus descendientes, con espacios normalizados
+    pub fn text_content(&self) -> String {
+        let mut out = String::new();
+        self.collect_text(&mut out);
+        out.split_whitespace().collect::<Vec<&str>>().join(" ")
+    }
+
+    fn collect_text(&self, out: &mut String) {
+        match self {
+            NarrativeNode::Text { text } => {
+                out.push_str(text);
+                out.push(' ');
+            }
+            NarrativeNode::Element { tag, children, .. } => {
+                for child in children {
+                    child.collect_text(out);
+                }
+                if tag == "br" {
+                    out.push(' ');
+                }
+            }
+        }
+    }
+
+    // Busca un nodo por su atributo ID (sin el '#' de la referencia)
+    pub fn find_by_id(&self, target: &str) -> Option<&NarrativeNode> {
+        match self {
+            NarrativeNode::Element { id, children, .. } => {
+                if id.as_deref() == Some(target) {
+                    return Some(self);
+                }
+                children.iter().find_map(|c| c.find_by_id(target))
+            }
+            NarrativeNode::Text { .. } => None,
+        }
+    }
+
+    // Fragmentos de texto independientes (párrafos, celdas, ítems de lista...)
+    // `skip` descarta elementos enteros (tablas ya extraídas fila a fila)
+    pub fn fragments(&self, skip: &dyn Fn(&NarrativeNode) -> bool) -> Vec<String> {
+        let mut fragments = Vec::new();
+        let mut loose = String::new();
+
+        for child in self.children() {
+            match child {
+                NarrativeNode::Text { text } => {
+                    loose.push_str(text);
+                    loose.push(' ');
+                }
+                NarrativeNode::Element { .. } => child.collect_fragments(&mut fragments, &mut loose, skip),
+            }
+        }
+
+        let loose = loose.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if !loose.is_empty() {
+            fragments.insert(0, loose);
+        }
+        fragments
+    }
+
+    fn collect_fragments(&self, fragments: &mut Vec<String>, loose: &mut String, skip: &dyn Fn(&NarrativeNode) -> bool) {
+        if skip(self) {
+            return;
+        }
+        match self {
+            NarrativeNode::Text { text } => {
+                loose.push_str(text);
+                loose.push(' ');
+            }
+            NarrativeNode::Element { tag, children, .. } => {
+                if FRAGMENT_TAGS.contains(&tag.as_str()) {
+                    let text = self.text_content();
+                    if !text.is_empty() {
+                        fragments.push(text);
+                    }
+                } else {
+                    for child in children {
+                        child.collect_fragments(fragments, loose, skip);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Construye el árbol a partir de los eventos SAX del lector principal
+pub struct NarrativeBuilder {
+    stack: Vec<NarrativeNode>,
+}
+
+impl Default for NarrativeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NarrativeBuilder {
+    pub fn new() -> Self {
+        NarrativeBuilder {
+            stack: vec![NarrativeNode::element("text", None, None)],
+        }
+    }
+
+    pub fn start(&mut self, tag: &str, id: Option<String>, style_code: Option<String>) {
+        self.stack.push(NarrativeNode::element(tag, id, style_code));
+    }
+
+    pub fn text(&mut self, text: &str) {
+        if let Some(NarrativeNode::Element { children, .. }) = self.stack.last_mut() {
+            children.push(NarrativeNode::Text { text: text.to_string() });
+        }
+    }
+
+    // Cierra el elemento actual; devuelve la raíz cuando se cierra <text>
+    pub fn end(&mut self) -> Option<NarrativeNode> {
+        let node = self.stack.pop()?;
+        match self.stack.last_mut() {
+            Some(NarrativeNode::Element { children, .. }) => {
+                children.push(node);
+                None
+            }
+            _ => Some(node),
+        }
+    }
+}
+
+// Referencia de una entrada (originalText/reference) hacia el bloque narrativo
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NarrativeReference {
+    pub reference: String,
+    pub source: String,
+    pub text: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Section {
+    pub code: Option<String>,
+    // templateId de la sección (C-CDA 2.16.840.1.113883.10.20.22.2.*, CCD 2.16.840.1.113883.10.20.1.*)
+    pub template_ids: Vec<String>,
+    pub title: Option<String>,
+    pub narrative: Option<NarrativeNode>,
+    pub references: Vec<NarrativeReference>,
+    pub tables: Vec<NarrativeTable>,
+}
+
+impl Section {
+    pub fn resolve(&self, reference: &str) -> Option<String> {
+        let id = reference.trim().trim_start_matches('#');
+        self.narrative
+            .as_ref()
+            .and_then(|n| n.find_by_id(id))
+            .map(|n| n.text_content())
+            .filter(|t| !t.is_empty())
+    }
+}
+
+// Resuelve una referencia buscando en todas las secciones del documento
+pub fn resolve_reference(sections: &[Section], reference: &str) -> Option<String> {
+    sections.iter().find_map(|s| s.resolve(reference))
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Motivo por el que falta un valor (vocabulario HL7 v3 NullFlavor)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NullFlavor {
+    #[serde(rename = "NI")]
+    NoInformation,
+    #[serde(rename = "INV")]
+    Invalid,
+    #[serde(rename = "DER")]
+    Derived,
+    #[serde(rename = "OTH")]
+    Other,
+    #[serde(rename = "NINF")]
+    NegativeInfinity,
+    #[serde(rename = "PINF")]
+    PositiveInfinity,
+    #[serde(rename = "UNC")]
+    Unencoded,
+    #[serde(rename = "MSK")]
+    Masked,
+    #[serde(rename = "NA")]
+    NotApplicable,
+    #[serde(rename = "UNK")]
+    Unknown,
+    #[serde(rename = "ASKU")]
+    AskedButUnknown,
+    #[serde(rename = "NAV")]
+    TemporarilyUnavailable,
+    #[serde(rename = "NASK")]
+    NotAsked,
+    #[serde(rename = "QS")]
+    SufficientQuantity,
+    #[serde(rename = "TRC")]
+    Trace,
+    #[serde(rename = "NP")]
+    NotPresent,
+}
+
+impl NullFlavor {
+    // Códigos desconocidos caen en NI, la raíz de la jerarquía
+    pub fn from_code(code: &str) -> NullFlavor {
+        match code.trim().to_uppercase().as_str() {
+            "INV" => NullFlavor::Invalid,
+            "DER" => NullFlavor::Derived,
+            "OTH" => NullFlavor::Other,
+            "NINF" => NullFlavor::NegativeInfinity,
+            "PINF" => NullFlavor::PositiveInfinity,
+            "UNC" => NullFlavor::Unencoded,
+            "MSK" => NullFlavor::Masked,
+            "NA" => NullFlavor::NotApplicable,
+            "UNK" => NullFlavor::Unknown,
+            "ASKU" => NullFlavor::AskedButUnknown,
+            "NAV" => NullFlavor::TemporarilyUnavailable,
+            "NASK" => NullFlavor::NotAsked,
+            "QS" => NullFlavor::SufficientQuantity,
+            "TRC" => NullFlavor::Trace,
+            "NP" => NullFlavor::NotPresent,
+            _ => NullFlavor::NoInformation,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            NullFlavor::NoInformation => "NI",
+            NullFlavor::Invalid => "INV",
+            NullFlavor::Derived => "DER",
+            NullFlavor::Other => "OTH",
+            NullFlavor::NegativeInfinity => "NINF",
+            NullFlavor::PositiveInfinity => "PINF",
+            NullFlavor::Unencoded => "UNC",
+            NullFlavor::Masked => "MSK",
+            NullFlavor::NotApplicable => "NA",
+            NullFlavor::Unknown => "UNK",
+            NullFlavor::AskedButUnknown => "ASKU",
+            NullFlavor::TemporarilyUnavailable => "NAV",
+            NullFlavor::NotAsked => "NASK",
+            NullFlavor::SufficientQuantity => "QS",
+            NullFlavor::Trace => "TRC",
+            NullFlavor::NotPresent => "NP",
+        }
+    }
+}
+
+// Campos ausentes con motivo, indexados por nombre de campo ("gender", "code"...)
+pub type NullFlavors = HashMap<String, NullFlavor>;
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+// Nombre estructurado (PN) de una persona
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PersonName {
+    pub given: Vec<String>,
+    // Dos apellidos en nombres hispanos: cada <family> es una entrada
+    pub family: Vec<String>,
+    pub prefix: Vec<String>,
+    pub suffix: Vec<String>,
+    #[serde(rename = "use")]
+    pub name_use: Option<String>,
+    // Nombre sin partes: <name>Juan Pérez</name>
+    pub text: Option<String>,
+}
+
+impl PersonName {
+    pub fn new(name_use: Option<String>) -> Self {
+        PersonName { name_use, ..Default::default() }
+    }
+
+    pub fn add_part(&mut self, part: &str, text: &str) {
+        let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if text.is_empty() {
+            return;
+        }
+        match part {
+            "given" => self.given.push(text),
+            "family" => self.family.push(text),
+            "prefix" => self.prefix.push(text),
+            "suffix" => self.suffix.push(text),
+            _ => {}
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.given.is_empty() && self.family.is_empty() && self.text.is_none()
+    }
+
+    // Nombres y apellidos para mostrar, sin prefijos ni sufijos
+    pub fn display(&self) -> Option<String> {
+        let parts: Vec<&str> = self.given.iter().chain(self.family.iter()).map(|s| s.as_str()).collect();
+        if parts.is_empty() {
+            return self.text.clone();
+        }
+        Some(parts.join(" "))
+    }
+
+    pub fn has_use(&self, code: &str) -> bool {
+        self.name_use
+            .as_deref()
+            .map(|u| u.split_whitespace().any(|c| c.eq_ignore_ascii_case(code)))
+            .unwrap_or(false)
+    }
+}
+
+// Nombre preferido: el legal (use="L"), si no el primero con contenido
+pub fn preferred_name(names: &[PersonName]) -> Option<&PersonName> {
+    names
+        .iter()
+        .find(|n| n.has_use("L") && !n.is_empty())
+        .or_else(|| names.iter().find(|n| !n.is_empty()))
+}
+
+// Persona vinculada al paciente: tutor legal o participante de cabecera
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RelatedPerson {
+    // guardian, emergency_contact, next_of_kin, caregiver, personal_relationship...
+    pub role: String,
+    pub relationship_code: Option<String>,
+    pub relationship: Option<String>,
+    pub name: Option<PersonName>,
+    pub telecoms: Vec<String>,
+}
+
+impl RelatedPerson {
+    pub fn new(role: &str) -> Self {
+        RelatedPerson { role: role.to_string(), ..Default::default() }
+    }
+
+    // Rol según el classCode de <associatedEntity>
+    pub fn role_from_class_code(class_code: Option<&str>) -> &'static str {
+        match class_code.map(|c| c.to_uppercase()).as_deref() {
+            Some("ECON") => "emergency_contact",
+            Some("NOK") => "next_of_kin",
+            Some("CAREGIVER") => "caregiver",
+            Some("GUARD") => "guardian",
+            Some("PRS") => "personal_relationship",
+            Some("AGNT") => "agent",
+            _ => "participant",
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+
+// Vía por la que se obtuvo un diagnóstico o medicamento
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractionMethod {
+    CodedEntry,
+    NarrativeTable,
+    NarrativeRegex,
+    TitleMapping,
+    MedicationInference,
+}
+
+// Código sin displayName ni código: sólo el texto del elemento codificado
+pub const DISPLAY_NAME_CONFIDENCE: f64 = 0.8;
+pub const DISPLAY_NAME_OUTSIDE_PROBLEMS_CONFIDENCE: f64 = 0.6;
+
+impl ExtractionMethod {
+    // Confianza de partida de cada vía de extracción
+    pub fn confidence(&self) -> f64 {
+        match self {
+            ExtractionMethod::CodedEntry => 0.95,
+            ExtractionMethod::NarrativeTable => 0.75,
+            ExtractionMethod::TitleMapping => 0.5,
+            ExtractionMethod::NarrativeRegex => 0.4,
+            ExtractionMethod::MedicationInference => 0.3,
+        }
+    }
+}
+
+// Posición de la coincidencia en `Evidence.text`, en caracteres
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextSpan {
+    pub start: usize,
+    pub end: usize,
+}
+
+// Rastro de un elemento extraído: regla aplicada, elemento XML y texto de origen
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Evidence {
+    pub method: ExtractionMethod,
+    // "med_to_diagnosis: warfarina", "diagnosis_keywords: asma", "manufacturedMaterial/name"...
+    pub rule: String,
+    pub path: Option<String>,
+    pub text: Option<String>,
+    pub span: Option<TextSpan>,
+    pub confidence: f64,
+}
+
+impl Evidence {
+    pub fn new(method: ExtractionMethod, rule: &str) -> Self {
+        Evidence {
+            method,
+            rule: rule.to_string(),
+            path: None,
+            text: None,
+            span: None,
+            confidence: method.confidence(),
+        }
+    }
+
+    pub fn at(mut self, path: &[String]) -> Self {
+        if !path.is_empty() {
+            self.path = Some(format!("/{}", path.join("/")));
+        }
+        self
+    }
+
+    pub fn with_confidence(mut self, confidence: f64) -> Self {
+        self.confidence = confidence;
+        self
+    }
+
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.text = Some(text.to_string());
+        self
+    }
+
+    // `start` y `end`: offsets en bytes dentro de `searched` (el texto en minúsculas)
+    pub fn with_span(mut self, searched: &str, start: usize, end: usize) -> Self {
+        let chars = |offset: usize| searched.get(..offset).map(|t| t.chars().count()).unwrap_or(offset);
+        self.span = Some(TextSpan { start: chars(start), end: chars(end) });
+        self
+    }
+}
+
+// Añade evidencias sin repetir la misma regla sobre el mismo origen
+pub fn merge_evidence(target: &mut Vec<Evidence>, other: Vec<Evidence>) {
+    for evidence in other {
+        let repeated = target.iter().any(|e| {
+            e.rule == evidence.rule && e.path == evidence.path && e.text == evidence.text && e.span == evidence.span
+        });
+        if !repeated {
+            target.push(evidence);
+        }
+    }
+}
+
+// Vías independientes se refuerzan: 1 - Π(1 - c). Varias coincidencias de una misma vía
+// (la misma regex repetida en el texto) no son evidencia nueva: cuenta la mejor de cada vía
+pub fn combined_confidence(evidence: &[Evidence]) -> f64 {
+    let mut best: Vec<(ExtractionMethod, f64)> = Vec::new();
+    for e in evidence {
+        let confidence = e.confidence.clamp(0.0, 1.0);
+        match best.iter_mut().find(|(method, _)| *method == e.method) {
+            Some((_, c)) => *c = c.max(confidence),
+            None => best.push((e.method, confidence)),
+        }
+    }
+    let doubt: f64 = best.iter().map(|(_, c)| 1.0 - c).product();
+    ((1.0 - doubt) * 1000.0).round() / 1000.0
+}
@@ -0,0 +1,199 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::{self, RuleTable};
+use crate::code_systems;
+use crate::datatypes::parse_number;
+use crate::gender;
+use crate::ucum::Unit;
+
+// Tabla por defecto, embebida en el WASM
+const DEFAULT_TABLE: &str = include_str!("../resources/reference_ranges.tsv");
+
+const LOINC_HEADERS: [&str; 3] = ["loinc", "code", "codigo"];
+const SEX_HEADERS: [&str; 2] = ["sex", "sexo"];
+const AGE_MIN_HEADERS: [&str; 2] = ["age_min", "edad_min"];
+const AGE_MAX_HEADERS: [&str; 2] = ["age_max", "edad_max"];
+const LOW_HEADERS: [&str; 2] = ["low", "bajo"];
+const HIGH_HEADERS: [&str; 2] = ["high", "alto"];
+const CRITICAL_LOW_HEADERS: [&str; 2] = ["critical_low", "critico_bajo"];
+const CRITICAL_HIGH_HEADERS: [&str; 2] = ["critical_high", "critico_alto"];
+const UNIT_HEADERS: [&str; 3] = ["unit", "unidad", "ucum"];
+
+static DEFAULTS: Lazy<ReferenceRangeTable> = Lazy::new(|| {
+    let mut table = ReferenceRangeTable::default();
+    table.load(DEFAULT_TABLE, true).expect("resources/reference_ranges.tsv no es válido");
+    table
+});
+
+// Resultado anormal: "H", "L", "HH", "LL" (valores críticos) o "N"
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AbnormalFlag {
+    pub flag: String,
+    // "interpretation_code", "reference_range" o "default_range"
+    pub source: String,
+    // Límites aplicados, en la unidad de `normalized_value`
+    pub low: Option<f64>,
+    pub high: Option<f64>,
+    pub critical_low: Option<f64>,
+    pub critical_high: Option<f64>,
+}
+
+impl AbnormalFlag {
+    pub fn is_abnormal(&self) -> bool {
+        self.flag != "N"
+    }
+}
+
+// Código de HL7 ObservationInterpretation (2.16.840.1.113883.5.83) en la escala H/L/HH/LL/N.
+// "A" (anormal sin dirección) no se puede expresar y cae al rango de referencia. ">" y "<"
+// son valores fuera de la escala del instrumento, no críticos: cuentan como H y L
+pub fn interpretation_flag(code: &str) -> Option<&'static str> {
+    match code.trim().to_uppercase().as_str() {
+        "N" => Some("N"),
+        "H" | "HX" | ">" => Some("H"),
+        "L" | "LX" | "<" => Some("L"),
+        "HH" | "HU" | "H>" => Some("HH"),
+        "LL" | "LU" | "L<" => Some("LL"),
+        _ => None,
+    }
+}
+
+// Los límites críticos son inclusivos; los normales, no: en el límite el valor es normal
+pub fn flag(value: f64, low: Option<f64>, high: Option<f64>, critical_low: Option<f64>, critical_high: Option<f64>) -> &'static str {
+    if critical_high.is_some_and(|c| value >= c) {
+        "HH"
+    } else if critical_low.is_some_and(|c| value <= c) {
+        "LL"
+    } else if high.is_some_and(|h| value > h) {
+        "H"
+    } else if low.is_some_and(|l| value < l) {
+        "L"
+    } else {
+        "N"
+    }
+}
+
+// Rango por defecto de un código LOINC, para un sexo y un tramo de edad en años [min, max)
+#[derive(Debug, Clone)]
+pub struct DefaultRange {
+    pub code: String,
+    sex: Option<&'static str>,
+    age_min: Option<u32>,
+    age_max: Option<u32>,
+    pub low: Option<f64>,
+    pub high: Option<f64>,
+    pub critical_low: Option<f64>,
+    pub critical_high: Option<f64>,
+    pub unit: String,
+}
+
+impl DefaultRange {
+    fn applies(&self, sex: Option<&str>, age: Option<u32>) -> bool {
+        let sex_ok = self.sex.is_none() || self.sex == sex;
+        let age_ok = match age {
+            Some(age) => self.age_min.is_none_or(|min| age >= min) && self.age_max.is_none_or(|max| age < max),
+            None => self.age_min.is_none() && self.age_max.is_none(),
+        };
+        sex_ok && age_ok
+    }
+
+    // Las filas de un sexo o una edad concretos ganan a las generales
+    fn specificity(&self) -> u8 {
+        self.sex.is_some() as u8 + (self.age_min.is_some() || self.age_max.is_some()) as u8
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReferenceRangeTable {
+    ranges: Vec<DefaultRange>,
+}
+
+impl ReferenceRangeTable {
+    pub fn defaults() -> ReferenceRangeTable {
+        DEFAULTS.clone()
+    }
+
+    // CSV o TSV con cabecera: loinc, sex (M, F o vacío), age_min, age_max (años), low, high,
+    // critical_low, critical_high y unit (UCUM). Devuelve el número de rangos cargados
+    pub fn load(&mut self, content: &str, replace: bool) -> Result<usize, String> {
+        let table = RuleTable::parse(content)?;
+        let loinc = table.required(&LOINC_HEADERS)?;
+        let unit = table.required(&UNIT_HEADERS)?;
+        let sex = table.column(&SEX_HEADERS);
+        let age_min = table.column(&AGE_MIN_HEADERS);
+        let age_max = table.column(&AGE_MAX_HEADERS);
+        let low = table.column(&LOW_HEADERS);
+        let high = table.column(&HIGH_HEADERS);
+        let critical_low = table.column(&CRITICAL_LOW_HEADERS);
+        let critical_high = table.column(&CRITICAL_HIGH_HEADERS);
+
+        let mut ranges = Vec::new();
+        for (number, fields) in &table.rows {
+            let code = alerts::field(fields, Some(loinc)).ok_or_else(|| format!("line {}: missing LOINC code", number))?;
+            if let Some(Err(reason)) = code_systems::lookup(code_systems::LOINC).map(|s| s.check(code)) {
+                return Err(format!("line {}: {}", number, reason));
+            }
+            let unit_text = alerts::field(fields, Some(unit)).ok_or_else(|| format!("line {}: missing unit", number))?;
+            let unit = Unit::parse(unit_text).map_err(|e| format!("line {}: {}", number, e))?;
+            let sex = match alerts::field(fields, sex) {
+                Some(text) => match gender::from_text(text) {
+                    gender::MALE => Some(gender::MALE),
+                    gender::FEMALE => Some(gender::FEMALE),
+                    _ => return Err(format!("line {}: unknown sex '{}'", number, text)),
+                },
+                None => None,
+            };
+            let age = |index: Option<usize>| -> Result<Option<u32>, String> {
+                alerts::field(fields, index)
+                    .map(|text| text.parse().map_err(|_| format!("line {}: invalid age '{}'", number, text)))
+                    .transpose()
+            };
+            let bound = |index: Option<usize>| -> Result<Option<f64>, String> {
+                alerts::field(fields, index)
+                    .map(|text| parse_number(text).ok_or_else(|| format!("line {}: invalid limit '{}'", number, text)))
+                    .transpose()
+            };
+            let range = DefaultRange {
+                code: code.to_string(),
+                sex,
+                age_min: age(age_min)?,
+                age_max: age(age_max)?,
+                low: bound(low)?,
+                high: bound(high)?,
+                critical_low: bound(critical_low)?,
+                critical_high: bound(critical_high)?,
+                unit: unit.code,
+            };
+            if range.low.is_none() && range.high.is_none() {
+                return Err(format!("line {}: range needs a low or a high limit", number));
+            }
+            if let (Some(low), Some(high)) = (range.low, range.high) {
+                if low > high {
+                    return Err(format!("line {}: low limit {} is above high limit {}", number, low, high));
+                }
+            }
+            ranges.push(range);
+        }
+
+        let count = ranges.len();
+        if replace {
+            self.ranges.clear();
+        }
+        // Las filas cargadas después sustituyen a las del mismo código, sexo y edad
+        for range in ranges {
+            self.ranges.retain(|r| {
+                r.code != range.code || r.sex != range.sex || r.age_min != range.age_min || r.age_max != range.age_max
+            });
+            self.ranges.push(range);
+        }
+        Ok(count)
+    }
+
+    pub fn lookup(&self, code: &str, sex: Option<&str>, age: Option<u32>) -> Option<&DefaultRange> {
+        self.ranges
+            .iter()
+            .filter(|r| r.code == code && r.applies(sex, age))
+            .max_by_key(|r| r.specificity())
+    }
+}
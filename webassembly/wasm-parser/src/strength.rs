@@ -0,0 +1,271 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::atc::name_tokens;
+
+// "850 mg", "20/12,5 mg", "100 microgramos"; las unidades largas antes que sus prefijos
+static STRENGTH: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)(\d+(?:[.,]\d+)?(?:\s*/\s*\d+(?:[.,]\d+)?)*)\s*(microgramos?|micrograms?|mcg|µg|μg|ug|mg|gramos?|gr|g|ml|unidades|units?|u\.i\.|ui|iu|meq|mmol|%)").unwrap()
+});
+
+// Denominador de la concentración: "/5 ml", "/dosis", "/24 h"
+static PER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)^\s*/\s*(\d+(?:[.,]\d+)?)?\s*(ml|dosis|doses|dose|pulsaci[oó]n|pulsaciones|actuation|inhalaci[oó]n|puff|horas|hours?|h|g)").unwrap()
+});
+
+// Separadores de principios activos en una combinación
+static INGREDIENT_SEPARATOR: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\s*(?:/|\+|,|\s+y\s+|\s+and\s+|\s+con\s+)\s*").unwrap()
+});
+
+// Formas farmacéuticas y sus nombres en español e inglés. El orden importa: "suspensión
+// para inhalación" es un inhalador y "solución inyectable" un inyectable
+const DOSE_FORMS: [(&str, &[&str]); 16] = [
+    ("inhaler", &["inhalador", "inhaladores", "inhalacion", "inhalation", "inhaler", "inhalant", "aerosol", "nebulizador", "turbuhaler", "accuhaler"]),
+    ("injection", &["inyectable", "inyeccion", "injection", "injectable", "jeringa precargada", "prefilled syringe", "pluma precargada", "pen injector", "vial", "ampolla", "ampollas"]),
+    ("eye_drops", &["colirio", "gotas oftalmicas", "ophthalmic solution", "eye drops"]),
+    ("oral_suspension", &["suspension oral", "oral suspension", "suspension"]),
+    ("oral_solution", &["solucion oral", "oral solution", "gotas orales", "oral drops"]),
+    ("syrup", &["jarabe", "syrup"]),
+    ("tablet", &["comprimido", "comprimidos", "comp", "tableta", "tabletas", "tablet", "tablets", "tab", "gragea", "grageas"]),
+    ("capsule", &["capsula", "capsulas", "capsule", "capsules", "caps"]),
+    ("sachet", &["sobre", "sobres", "sachet", "sachets", "granulado"]),
+    ("patch", &["parche", "parches", "patch", "transdermal", "transdermico"]),
+    ("cream", &["crema", "cream"]),
+    ("ointment", &["pomada", "unguento", "ointment"]),
+    ("gel", &["gel"]),
+    ("suppository", &["supositorio", "supositorios", "suppository"]),
+    ("drops", &["gotas", "drops"]),
+    ("solution", &["solucion", "solution"]),
+];
+
+// Sales y palabras de enlace que no cambian el principio activo ("hidrocloruro de metformina")
+const SALTS: [&str; 24] = [
+    "hidrocloruro", "clorhidrato", "hydrochloride", "hcl", "sodico", "sodica", "sodium", "potasico",
+    "potassium", "calcico", "calcium", "besilato", "besylate", "maleato", "maleate", "mesilato",
+    "succinato", "tartrato", "fumarato", "monohidrato", "dihidrato", "trihidrato", "hemihidrato", "de",
+];
+
+// Concentración: 850 mg, 100 mcg por dosis, 250 mg por 5 ml
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Strength {
+    pub value: f64,
+    pub unit: String,
+    pub per_value: Option<f64>,
+    pub per_unit: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ingredient {
+    pub name: String,
+    pub strength: Option<Strength>,
+}
+
+// Nombre de producto descompuesto en principios activos, concentraciones y forma farmacéutica
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProductName {
+    pub ingredients: Vec<Ingredient>,
+    pub dose_form: Option<String>,
+}
+
+// Agrupación de top_medications
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MedicationGrouping {
+    // Nombre tal como aparece en el documento ("Metformin 850mg")
+    Name,
+    // Principios activos sin concentración ni forma ("Metformina")
+    Ingredient,
+}
+
+impl MedicationGrouping {
+    pub fn from_name(name: &str) -> Option<MedicationGrouping> {
+        match name.trim().to_lowercase().as_str() {
+            "name" => Some(MedicationGrouping::Name),
+            "ingredient" => Some(MedicationGrouping::Ingredient),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            MedicationGrouping::Name => "name".to_string(),
+            MedicationGrouping::Ingredient => "ingredient".to_string(),
+        }
+    }
+}
+
+// "Metformin 850mg", "Enalapril/Hidroclorotiazida 20/12,5 mg comprimidos",
+// "hydrochlorothiazide 12.5 MG / lisinopril 10 MG Oral Tablet", "Salbutamol inhalador".
+// `normalize` traduce cada principio activo a su nombre canónico
+pub fn parse(name: &str, normalize: impl Fn(&str) -> String) -> ProductName {
+    let mut ingredients = Vec::new();
+    let mut rest = 0;
+    for captures in STRENGTH.captures_iter(name) {
+        let (whole, unit) = (captures.get(0).unwrap(), captures.get(2).unwrap());
+        // "5 mgx" o "20 gastrorresistentes" no son concentraciones
+        if name[unit.end()..].chars().next().is_some_and(|c| c.is_alphanumeric()) || whole.start() < rest {
+            continue;
+        }
+        let mut end = whole.end();
+        let mut per = (None, None);
+        if let Some(per_captures) = PER.captures(&name[end..]) {
+            let per_unit = per_captures.get(2).unwrap();
+            if !name[end + per_unit.end()..].chars().next().is_some_and(|c| c.is_alphanumeric()) {
+                per = (per_captures.get(1).and_then(|v| parse_number(v.as_str())), Some(unit_name(per_unit.as_str())));
+                end += per_captures.get(0).unwrap().end();
+            }
+        }
+
+        let names = ingredient_names(&name[rest..whole.start()], &normalize);
+        let values: Vec<Option<f64>> = captures[1].split('/').map(parse_number).collect();
+        let unit = unit_name(unit.as_str());
+        for (index, ingredient) in names.iter().enumerate() {
+            // Tantos valores como principios activos: "20/12,5 mg"
+            let value = if values.len() == names.len() { values[index] } else { None };
+            ingredients.push(Ingredient {
+                name: ingredient.clone(),
+                strength: value.map(|value| Strength {
+                    value,
+                    unit: unit.clone(),
+                    per_value: per.0,
+                    per_unit: per.1.clone(),
+                }),
+            });
+        }
+        rest = end;
+    }
+
+    let tail = &name[rest..];
+    let (form, position) = match dose_form(tail) {
+        Some((form, position)) => (Some(form.to_string()), Some(position)),
+        None => (None, None),
+    };
+    if ingredients.is_empty() {
+        // Sin concentración, la forma farmacéutica cierra el nombre: "Salbutamol inhalador"
+        let words: Vec<&str> = tail.split_whitespace().collect();
+        let end = position.filter(|p| *p > 0).unwrap_or(words.len());
+        ingredients = ingredient_names(&words[..end].join(" "), &normalize)
+            .into_iter()
+            .map(|name| Ingredient { name, strength: None })
+            .collect();
+    }
+    ProductName { ingredients, dose_form: form }
+}
+
+// Concentración de un texto con un único valor: "850 mg" de <doseQuantity>
+pub fn parse_strength(text: &str) -> Option<Strength> {
+    let captures = STRENGTH.captures(text)?;
+    let value = parse_number(&captures[1])?;
+    Some(Strength { value, unit: unit_name(&captures[2]), per_value: None, per_unit: None })
+}
+
+// Forma farmacéutica del texto y posición (en palabras) de la primera mención
+fn dose_form(text: &str) -> Option<(&'static str, usize)> {
+    let words: Vec<Vec<String>> = text.split_whitespace().map(name_tokens).collect();
+    let tokens: Vec<&String> = words.iter().flatten().collect();
+    let (form, _) = DOSE_FORMS.iter().find(|(_, aliases)| {
+        aliases.iter().any(|alias| position(&tokens, alias).is_some())
+    })?;
+    let start = DOSE_FORMS
+        .iter()
+        .flat_map(|(_, aliases)| aliases.iter())
+        .filter_map(|alias| position(&tokens, alias))
+        .min()?;
+    // Posición en tokens -> posición en palabras
+    let mut seen = 0;
+    let word = words.iter().position(|w| {
+        seen += w.len();
+        seen > start
+    })?;
+    Some((form, word))
+}
+
+fn position(tokens: &[&String], alias: &str) -> Option<usize> {
+    let alias: Vec<&str> = alias.split(' ').collect();
+    tokens
+        .windows(alias.len())
+        .position(|w| w.iter().zip(&alias).all(|(t, a)| t.as_str() == *a))
+}
+
+fn ingredient_names(text: &str, normalize: &impl Fn(&str) -> String) -> Vec<String> {
+    INGREDIENT_SEPARATOR
+        .split(text)
+        .filter_map(|part| {
+            let words: Vec<&str> = part
+                .split_whitespace()
+                .filter(|w| !name_tokens(w).iter().all(|t| SALTS.contains(&t.as_str())))
+                .collect();
+            if words.is_empty() {
+                None
+            } else {
+                Some(normalize(&words.join(" ")))
+            }
+        })
+        .collect()
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    text.trim().replace(',', ".").parse().ok()
+}
+
+fn unit_name(unit: &str) -> String {
+    let unit = unit.to_lowercase();
+    let name = match unit.as_str() {
+        "mg" => "mg",
+        "g" | "gr" | "gramo" | "gramos" => "g",
+        "mcg" | "µg" | "μg" | "ug" | "microgramo" | "microgramos" | "microgram" | "micrograms" => "mcg",
+        "ml" => "ml",
+        "unidades" | "unit" | "units" | "u.i." | "ui" | "iu" => "UI",
+        "meq" => "mEq",
+        "mmol" => "mmol",
+        "dosis" | "dose" | "doses" | "pulsacion" | "pulsación" | "pulsaciones" | "actuation" | "inhalacion"
+        | "inhalación" | "puff" => "dose",
+        "h" | "horas" | "hour" | "hours" => "h",
+        other => other,
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strength(value: f64, unit: &str) -> Option<Strength> {
+        Some(Strength { value, unit: unit.to_string(), per_value: None, per_unit: None })
+    }
+
+    #[test]
+    fn combination_with_one_strength_per_ingredient() {
+        let product = parse("Enalapril/Hidroclorotiazida 20/12,5 mg comprimidos", |n| n.to_string());
+        assert_eq!(
+            product.ingredients,
+            [
+                Ingredient { name: "Enalapril".to_string(), strength: strength(20.0, "mg") },
+                Ingredient { name: "Hidroclorotiazida".to_string(), strength: strength(12.5, "mg") },
+            ]
+        );
+        assert_eq!(product.dose_form.as_deref(), Some("tablet"));
+    }
+
+    #[test]
+    fn strength_per_volume_and_salts() {
+        let product = parse("Amoxicilina 250 mg/5 ml suspensión oral", |n| n.to_string());
+        let ingredient = &product.ingredients[0];
+        assert_eq!(ingredient.name, "Amoxicilina");
+        let strength = ingredient.strength.as_ref().unwrap();
+        assert_eq!((strength.per_value, strength.per_unit.as_deref()), (Some(5.0), Some("ml")));
+        assert_eq!(product.dose_form.as_deref(), Some("oral_suspension"));
+
+        let salt = parse("Metformina hidrocloruro 850 mg", |n| n.to_lowercase());
+        assert_eq!(salt.ingredients[0].name, "metformina");
+    }
+
+    #[test]
+    fn dose_form_without_strength() {
+        let product = parse("Salbutamol inhalador", |n| n.to_string());
+        assert_eq!(product.ingredients, [Ingredient { name: "Salbutamol".to_string(), strength: None }]);
+        assert_eq!(product.dose_form.as_deref(), Some("inhaler"));
+        assert_eq!(parse_strength("850 mg"), strength(850.0, "mg"));
+    }
+}
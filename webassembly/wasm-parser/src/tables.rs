@@ -0,0 +1,220 @@
+use serde::{Deserialize, Serialize};
+
+use crate::narrative::NarrativeNode;
+
+// Cabeceras reconocidas (en minúsculas y sin tildes), en español e inglés
+const MEDICATION_NAME_HEADERS: [&str; 9] = [
+    "medicamento", "medicacion", "farmaco", "principio activo", "tratamiento",
+    "medication", "medicine", "drug", "product",
+];
+const DOSE_HEADERS: [&str; 5] = ["dosis", "posologia", "dose", "dosage", "strength"];
+const FREQUENCY_HEADERS: [&str; 4] = ["frecuencia", "pauta", "frequency", "schedule"];
+const ROUTE_HEADERS: [&str; 2] = ["via", "route"];
+const OBSERVATION_NAME_HEADERS: [&str; 10] = [
+    "prueba", "analisis", "determinacion", "parametro", "examen",
+    "test", "analyte", "component", "observation", "vital sign",
+];
+const VALUE_HEADERS: [&str; 4] = ["resultado", "valor", "result", "value"];
+const UNIT_HEADERS: [&str; 4] = ["unidad", "unidades", "unit", "units"];
+const DATE_HEADERS: [&str; 2] = ["fecha", "date"];
+const RANGE_HEADERS: [&str; 4] = ["rango", "referencia", "reference", "normal range"];
+
+// Tabla del bloque narrativo; cada fila guarda sus celdas por columna, en el orden de
+// `headers`, para no perder columnas con la misma cabecera
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NarrativeTable {
+    pub caption: Option<String>,
+    pub headers: Vec<String>,
+    pub records: Vec<Vec<String>>,
+}
+
+impl NarrativeTable {
+    // Primer valor no vacío de las columnas cuya cabecera encaja con alguna palabra clave
+    pub fn cell<'a>(&self, record: &'a [String], keywords: &[&str]) -> Option<&'a str> {
+        self.headers
+            .iter()
+            .zip(record)
+            .filter(|(h, _)| header_matches(h, keywords))
+            .map(|(_, v)| v.as_str())
+            .find(|v| !v.is_empty())
+    }
+
+    pub fn kind(&self) -> TableKind {
+        let has = |keywords: &[&str]| self.headers.iter().any(|h| header_matches(h, keywords));
+
+        if has(&MEDICATION_NAME_HEADERS) {
+            TableKind::Medications
+        } else if has(&OBSERVATION_NAME_HEADERS) && has(&VALUE_HEADERS) {
+            TableKind::Observations
+        } else {
+            TableKind::Unknown
+        }
+    }
+
+    pub fn medication_rows(&self) -> Vec<MedicationRow<'_>> {
+        self.records
+            .iter()
+            .filter_map(|r| {
+                Some(MedicationRow {
+                    name: self.cell(r, &MEDICATION_NAME_HEADERS)?,
+                    dose: self.cell(r, &DOSE_HEADERS),
+                    frequency: self.cell(r, &FREQUENCY_HEADERS),
+                    route: self.cell(r, &ROUTE_HEADERS),
+                })
+            })
+            .collect()
+    }
+
+    pub fn observation_rows(&self) -> Vec<ObservationRow<'_>> {
+        self.records
+            .iter()
+            .filter_map(|r| {
+                Some(ObservationRow {
+                    name: self.cell(r, &OBSERVATION_NAME_HEADERS)?,
+                    value: self.cell(r, &VALUE_HEADERS),
+                    unit: self.cell(r, &UNIT_HEADERS),
+                    date: self.cell(r, &DATE_HEADERS),
+                    reference_range: self.cell(r, &RANGE_HEADERS),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableKind {
+    Medications,
+    Observations,
+    Unknown,
+}
+
+// Campos de una fila de medicación
+pub struct MedicationRow<'a> {
+    pub name: &'a str,
+    pub dose: Option<&'a str>,
+    pub frequency: Option<&'a str>,
+    pub route: Option<&'a str>,
+}
+
+// Campos de una fila de resultados
+pub struct ObservationRow<'a> {
+    pub name: &'a str,
+    pub value: Option<&'a str>,
+    pub unit: Option<&'a str>,
+    pub date: Option<&'a str>,
+    pub reference_range: Option<&'a str>,
+}
+
+// Palabras completas, admitiendo el plural ("medicamentos", "valores"): "via" no encaja en
+// "desviacion" ni "date" en "updated"
+fn header_matches(header: &str, keywords: &[&str]) -> bool {
+    let header = normalize_header(header);
+    let words: Vec<&str> = header.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect();
+    let same = |word: &str, keyword: &str| {
+        word == keyword || word.strip_suffix('s') == Some(keyword) || word.strip_suffix("es") == Some(keyword)
+    };
+    keywords.iter().any(|keyword| {
+        let keyword: Vec<&str> = keyword.split(' ').collect();
+        words.windows(keyword.len()).any(|w| w.iter().zip(&keyword).all(|(word, k)| same(word, k)))
+    })
+}
+
+pub fn normalize_header(header: &str) -> String {
+    header
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'á' | 'à' | 'ä' => 'a',
+            'é' | 'è' | 'ë' => 'e',
+            'í' | 'ì' | 'ï' => 'i',
+            'ó' | 'ò' | 'ö' => 'o',
+            'ú' | 'ù' | 'ü' => 'u',
+            _ => c,
+        })
+        .collect()
+}
+
+// <table> cuyas filas se extraen como medicamentos o resultados
+pub fn is_record_table(node: &NarrativeNode) -> bool {
+    node.tag() == Some("table") && build_table(node).is_some_and(|t| t.kind() != TableKind::Unknown)
+}
+
+// Recorre el árbol narrativo y devuelve todas sus tablas
+pub fn extract_tables(root: &NarrativeNode) -> Vec<NarrativeTable> {
+    let mut tables = Vec::new();
+    collect_tables(root, &mut tables);
+    tables
+}
+
+fn collect_tables(node: &NarrativeNode, tables: &mut Vec<NarrativeTable>) {
+    match node.tag() {
+        Some("table") => {
+            if let Some(table) = build_table(node) {
+                tables.push(table);
+            }
+        }
+        Some(_) => {
+            for child in node.children() {
+                collect_tables(child, tables);
+            }
+        }
+        None => {}
+    }
+}
+
+fn build_table(table: &NarrativeNode) -> Option<NarrativeTable> {
+    let caption = table
+        .children()
+        .iter()
+        .find(|c| c.tag() == Some("caption"))
+        .map(|c| c.text_content());
+
+    let mut header_rows = Vec::new();
+    let mut body_rows = Vec::new();
+    for child in table.children() {
+        match child.tag() {
+            Some("thead") => header_rows.extend(rows_of(child)),
+            Some("tbody") | Some("tfoot") => body_rows.extend(rows_of(child)),
+            Some("tr") => body_rows.push(child),
+            _ => {}
+        }
+    }
+
+    // Sin <thead>: la primera fila hace de cabecera si sólo tiene <th>
+    if header_rows.is_empty() {
+        let first_is_header = body_rows
+            .first()
+            .map(|r| r.children().iter().all(|c| c.tag() != Some("td")))
+            .unwrap_or(false);
+        if first_is_header {
+            header_rows.push(body_rows.remove(0));
+        }
+    }
+
+    let headers: Vec<String> = header_rows.last()?.children().iter()
+        .filter(|c| matches!(c.tag(), Some("th") | Some("td")))
+        .map(|c| c.text_content())
+        .collect();
+    if headers.is_empty() {
+        return None;
+    }
+
+    let records = body_rows
+        .iter()
+        .map(|row| {
+            row.children()
+                .iter()
+                .filter(|c| matches!(c.tag(), Some("th") | Some("td")))
+                .take(headers.len())
+                .map(|cell| cell.text_content())
+                .collect::<Vec<String>>()
+        })
+        .filter(|record| record.iter().any(|v| !v.is_empty()))
+        .collect();
+
+    Some(NarrativeTable { caption, headers, records })
+}
+
+fn rows_of(node: &NarrativeNode) -> Vec<&NarrativeNode> {
+    node.children().iter().filter(|c| c.tag() == Some("tr")).collect()
+}
@@ -0,0 +1,318 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::code_systems;
+use crate::tables::normalize_header;
+
+// Primera línea del índice compacto. Formato, una línea por código:
+//   @<OID del sistema>
+//   <código>\t<nombre en inglés>\t<nombre en español>
+pub const INDEX_HEADER: &str = "# cda-terminology-index v1";
+
+// RF2: tipos de descripción y aceptabilidad en los refsets de idioma
+const RF2_FSN: &str = "900000000000003001";
+const RF2_PREFERRED: &str = "900000000000548007";
+
+const CSV_CODE_HEADERS: [&str; 6] = ["code", "codigo", "conceptid", "concept_id", "loinc_num", "snomed_id"];
+const CSV_ENGLISH_HEADERS: [&str; 6] = ["en", "display_en", "term_en", "english", "long_common_name", "nombre_en"];
+const CSV_SPANISH_HEADERS: [&str; 6] = ["es", "display_es", "term_es", "spanish", "descripcion", "nombre_es"];
+const CSV_TERM_HEADERS: [&str; 4] = ["term", "display", "description", "nombre"];
+const CSV_LANGUAGE_HEADERS: [&str; 4] = ["language", "languagecode", "lang", "idioma"];
+
+// Nombres preferidos de un código
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TermNames {
+    pub es: Option<String>,
+    pub en: Option<String>,
+}
+
+impl TermNames {
+    // Etiqueta para informes: español y, si no hay, inglés
+    pub fn preferred(&self) -> Option<String> {
+        self.es.clone().or_else(|| self.en.clone())
+    }
+
+    fn merge(&mut self, other: TermNames) {
+        if other.es.is_some() {
+            self.es = other.es;
+        }
+        if other.en.is_some() {
+            self.en = other.en;
+        }
+    }
+}
+
+// Tablas locales de nombres: OID del sistema -> código -> nombres
+#[derive(Debug, Clone, Default)]
+pub struct Terminology {
+    systems: HashMap<String, HashMap<String, TermNames>>,
+}
+
+impl Terminology {
+    pub fn is_empty(&self) -> bool {
+        self.systems.values().all(|codes| codes.is_empty())
+    }
+
+    pub fn clear(&mut self) {
+        self.systems.clear();
+    }
+
+    pub fn lookup(&self, code_system: &str, code: &str) -> Option<&TermNames> {
+        let oid = code_systems::resolve(code_system)?;
+        self.systems.get(&oid)?.get(code.trim())
+    }
+
+    // Códigos cargados por sistema, con el nombre del registro si se conoce
+    pub fn summary(&self) -> HashMap<String, usize> {
+        self.systems
+            .iter()
+            .map(|(oid, codes)| {
+                let name = code_systems::lookup(oid).map(|s| s.name.to_string()).unwrap_or_else(|| oid.clone());
+                (name, codes.len())
+            })
+            .collect()
+    }
+
+    fn insert(&mut self, oid: &str, code: &str, names: TermNames) {
+        let code = code.trim();
+        if code.is_empty() || (names.es.is_none() && names.en.is_none()) {
+            return;
+        }
+        self.systems
+            .entry(oid.to_string())
+            .or_default()
+            .entry(code.to_string())
+            .or_default()
+            .merge(names);
+    }
+
+    // Carga un índice compacto; devuelve el número de códigos leídos
+    pub fn load_index(&mut self, index: &str) -> Result<usize, String> {
+        let mut lines = index.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+        match lines.next() {
+            Some((_, header)) if header.trim() == INDEX_HEADER => {}
+            _ => return Err(format!("not a terminology index: expected '{}' on the first line", INDEX_HEADER)),
+        }
+
+        let mut loaded = Terminology::default();
+        let mut current: Option<String> = None;
+        let mut count = 0;
+        for (number, line) in lines {
+            if line.starts_with('#') {
+                continue;
+            }
+            if let Some(system) = line.strip_prefix('@') {
+                current = Some(code_systems::resolve(system).ok_or_else(|| {
+                    format!("line {}: unknown code system '{}'", number + 1, system.trim())
+                })?);
+                continue;
+            }
+            let oid = current.as_deref().ok_or_else(|| format!("line {}: code before any @system line", number + 1))?;
+            let mut fields = line.split('\t');
+            let code = fields.next().unwrap_or_default();
+            let names = TermNames { en: non_empty(fields.next()), es: non_empty(fields.next()) };
+            loaded.insert(oid, code, names);
+            count += 1;
+        }
+
+        // Sólo se fusiona un índice leído por completo
+        for (oid, codes) in loaded.systems {
+            for (code, names) in codes {
+                self.insert(&oid, &code, names);
+            }
+        }
+        Ok(count)
+    }
+
+    // Índice compacto, ordenado para que dos construcciones iguales den el mismo fichero
+    pub fn to_index(&self) -> String {
+        let mut index = format!("{}\n", INDEX_HEADER);
+        let mut oids: Vec<&String> = self.systems.keys().collect();
+        oids.sort();
+        for oid in oids {
+            index.push_str(&format!("@{}\n", oid));
+            let codes = &self.systems[oid];
+            let mut sorted: Vec<&String> = codes.keys().collect();
+            sorted.sort();
+            for code in sorted {
+                let names = &codes[code];
+                index.push_str(&format!(
+                    "{}\t{}\t{}\n",
+                    code,
+                    names.en.as_deref().unwrap_or_default(),
+                    names.es.as_deref().unwrap_or_default()
+                ));
+            }
+        }
+        index
+    }
+
+    // CSV con cabecera: código y columnas en/es, o término + idioma. Separador , ; o tabulador
+    pub fn from_csv(code_system: &str, csv: &str) -> Result<Terminology, String> {
+        let oid = code_systems::resolve(code_system).ok_or_else(|| format!("unknown code system '{}'", code_system))?;
+        let mut lines = csv.lines().filter(|l| !l.trim().is_empty());
+        let header_line = lines.next().ok_or("empty CSV file")?;
+        let delimiter = [',', ';', '\t'].into_iter().max_by_key(|d| header_line.matches(*d).count()).unwrap_or(',');
+
+        let headers: Vec<String> = split_csv_line(header_line.trim_start_matches('\u{FEFF}'), delimiter)
+            .iter()
+            .map(|h| normalize_header(h.trim()))
+            .collect();
+        let column = |names: &[&str]| headers.iter().position(|h| names.contains(&h.as_str()));
+
+        let code_column = column(&CSV_CODE_HEADERS).ok_or("CSV has no code column")?;
+        let english = column(&CSV_ENGLISH_HEADERS);
+        let spanish = column(&CSV_SPANISH_HEADERS);
+        let term = column(&CSV_TERM_HEADERS);
+        let language = column(&CSV_LANGUAGE_HEADERS);
+        let active = column(&["active", "activo"]);
+        if english.is_none() && spanish.is_none() && term.is_none() {
+            return Err("CSV has no display name column (en, es or term)".to_string());
+        }
+
+        let mut terminology = Terminology::default();
+        for line in lines {
+            let fields = split_csv_line(line, delimiter);
+            let field = |index: Option<usize>| non_empty(index.and_then(|i| fields.get(i)).map(|f| f.as_str()));
+            if field(active).as_deref() == Some("0") {
+                continue;
+            }
+            let code = match field(Some(code_column)) {
+                Some(code) => code,
+                None => continue,
+            };
+
+            let mut names = TermNames { en: field(english), es: field(spanish) };
+            if let Some(term) = field(term) {
+                match field(language).map(|l| l.to_lowercase()) {
+                    Some(l) if l.starts_with("es") => names.es = names.es.or(Some(term)),
+                    Some(l) if !l.starts_with("en") => {}
+                    // Sin columna de idioma el término se toma como inglés
+                    _ => names.en = names.en.or(Some(term)),
+                }
+            }
+            terminology.insert(&oid, &code, names);
+        }
+        Ok(terminology)
+    }
+
+    // Fichero de descripciones RF2 de SNOMED CT (Snapshot). Con el refset de idioma se
+    // toma el término preferido; sin él, el primer sinónimo activo o el FSN sin etiqueta
+    pub fn from_rf2(descriptions: &str, language_refset: Option<&str>) -> Result<Terminology, String> {
+        let preferred: Vec<&str> = match language_refset {
+            Some(refset) => {
+                let rows = Rf2Rows::new(refset, &["active", "referencedcomponentid", "acceptabilityid"])?;
+                rows.filter(|r| r[0] == "1" && r[2] == RF2_PREFERRED).map(|r| r[1]).collect()
+            }
+            None => Vec::new(),
+        };
+        let preferred: std::collections::HashSet<&str> = preferred.into_iter().collect();
+
+        // Mejor término por concepto e idioma: (rango, término)
+        let mut best: HashMap<(&str, bool), (u8, String)> = HashMap::new();
+        let rows = Rf2Rows::new(descriptions, &["id", "active", "conceptid", "languagecode", "typeid", "term"])?;
+        for row in rows.filter(|r| r[1] == "1") {
+            let spanish = match row[3] {
+                "es" => true,
+                "en" => false,
+                _ => continue,
+            };
+            let (rank, term) = if preferred.contains(row[0]) {
+                (3, row[5].to_string())
+            } else if row[4] == RF2_FSN {
+                (1, strip_semantic_tag(row[5]))
+            } else if preferred.is_empty() {
+                (2, row[5].to_string())
+            } else {
+                continue;
+            };
+            let entry = best.entry((row[2], spanish)).or_insert((0, String::new()));
+            if rank > entry.0 {
+                *entry = (rank, term);
+            }
+        }
+
+        let mut terminology = Terminology::default();
+        for ((concept, spanish), (_, term)) in best {
+            let names = if spanish {
+                TermNames { es: Some(term), en: None }
+            } else {
+                TermNames { es: None, en: Some(term) }
+            };
+            terminology.insert(code_systems::SNOMED_CT, concept, names);
+        }
+        Ok(terminology)
+    }
+}
+
+// Filas de un fichero RF2 (tabulado, con cabecera) con las columnas pedidas
+struct Rf2Rows<'a> {
+    lines: std::str::Lines<'a>,
+    columns: Vec<usize>,
+}
+
+impl<'a> Rf2Rows<'a> {
+    fn new(content: &'a str, wanted: &[&str]) -> Result<Self, String> {
+        let mut lines = content.lines();
+        let headers: Vec<String> = lines
+            .next()
+            .ok_or("empty RF2 file")?
+            .split('\t')
+            .map(|h| h.trim().trim_start_matches('\u{FEFF}').to_lowercase())
+            .collect();
+        let columns = wanted
+            .iter()
+            .map(|w| headers.iter().position(|h| h == w).ok_or_else(|| format!("RF2 file has no {} column", w)))
+            .collect::<Result<Vec<usize>, String>>()?;
+        Ok(Rf2Rows { lines, columns })
+    }
+}
+
+impl<'a> Iterator for Rf2Rows<'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let fields: Vec<&str> = self.lines.next()?.split('\t').collect();
+            if let Some(row) = self.columns.iter().map(|&c| fields.get(c).map(|f| f.trim())).collect() {
+                return Some(row);
+            }
+        }
+    }
+}
+
+// "Diabetes mellitus (disorder)" -> "Diabetes mellitus"
+fn strip_semantic_tag(term: &str) -> String {
+    match term.rfind(" (") {
+        Some(start) if term.ends_with(')') => term[..start].to_string(),
+        _ => term.to_string(),
+    }
+}
+
+// Campo recortado y sin tabuladores ni saltos, que romperían el índice
+fn non_empty(field: Option<&str>) -> Option<String> {
+    field
+        .map(|f| f.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|f| !f.is_empty())
+}
+
+// Separa una línea CSV respetando comillas ("a, b" y "" como comilla escapada)
+pub fn split_csv_line(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
@@ -0,0 +1,526 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::alerts::{self, RuleTable};
+use crate::code_systems;
+use crate::datatypes::parse_number;
+
+// Tabla por defecto, embebida en el WASM
+const DEFAULT_TABLE: &str = include_str!("../resources/canonical_units.tsv");
+
+const LOINC_HEADERS: [&str; 3] = ["loinc", "code", "codigo"];
+const UNIT_HEADERS: [&str; 3] = ["unit", "unidad", "ucum"];
+const MOLAR_MASS_HEADERS: [&str; 2] = ["molar_mass", "masa_molar"];
+
+// Dimensiones base: longitud, masa, tiempo, cantidad de sustancia, temperatura y unidades
+// internacionales, que no se pueden convertir a las demás
+const DIMENSIONS: usize = 6;
+const METER: usize = 0;
+const GRAM: usize = 1;
+const SECOND: usize = 2;
+const MOLE: usize = 3;
+const KELVIN: usize = 4;
+const INTERNATIONAL_UNIT: usize = 5;
+
+type Dimension = [i32; DIMENSIONS];
+
+const PREFIXES: [(&str, f64); 13] = [
+    ("da", 1e1),
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("u", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+];
+
+// Unidades mal escritas frecuentes en los informes que no se deducen símbolo a símbolo
+const ALIASES: [(&str, &str); 16] = [
+    ("mmhg", "mm[Hg]"),
+    ("mm hg", "mm[Hg]"),
+    ("cmh2o", "cm[H2O]"),
+    ("bpm", "/min"),
+    ("lat/min", "/min"),
+    ("latidos/min", "/min"),
+    ("rpm", "/min"),
+    ("resp/min", "/min"),
+    ("°c", "Cel"),
+    ("ºc", "Cel"),
+    ("°f", "[degF]"),
+    ("ºf", "[degF]"),
+    ("ui", "[iU]"),
+    ("iu", "[iU]"),
+    ("ui/l", "U/L"),
+    ("ratio", "{ratio}"),
+];
+
+static DEFAULTS: Lazy<CanonicalUnits> = Lazy::new(|| {
+    let mut table = CanonicalUnits::default();
+    table.load(DEFAULT_TABLE, true).expect("resources/canonical_units.tsv no es válido");
+    table
+});
+
+// "x10^9", "10*3", "10E6"
+static POWER_OF_TEN: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[xX]?10(?:\*|\^|[eE])([+-]?\d+)$").unwrap());
+
+// "1.73m2" del filtrado glomerular es una anotación, no un factor
+static BODY_SURFACE: Lazy<Regex> = Lazy::new(|| Regex::new(r"1[.,]73\s*m(?:2|²)").unwrap());
+
+struct Atom {
+    symbol: &'static str,
+    factor: f64,
+    dimension: Dimension,
+    // Admite prefijos SI
+    metric: bool,
+    // Sólo temperaturas: valor en K = valor * factor + offset
+    offset: f64,
+}
+
+static ATOMS: Lazy<Vec<Atom>> = Lazy::new(|| {
+    let atom = |symbol, factor, dimension: &[(usize, i32)], metric| {
+        let mut d = [0; DIMENSIONS];
+        for (index, exponent) in dimension {
+            d[*index] = *exponent;
+        }
+        Atom { symbol, factor, dimension: d, metric, offset: 0.0 }
+    };
+    let pressure = [(METER, -1), (GRAM, 1), (SECOND, -2)];
+    vec![
+        atom("m", 1.0, &[(METER, 1)], true),
+        atom("g", 1.0, &[(GRAM, 1)], true),
+        atom("s", 1.0, &[(SECOND, 1)], true),
+        atom("mol", 1.0, &[(MOLE, 1)], true),
+        atom("K", 1.0, &[(KELVIN, 1)], true),
+        atom("L", 1e-3, &[(METER, 3)], true),
+        atom("Pa", 1e3, &pressure, true),
+        atom("bar", 1e8, &pressure, true),
+        atom("m[Hg]", 133.322e6, &pressure, true),
+        atom("m[H2O]", 9.80665e6, &pressure, true),
+        // UCUM define el equivalente como un mol
+        atom("eq", 1.0, &[(MOLE, 1)], true),
+        // Unidad enzimática: µmol/min
+        atom("U", 1e-6 / 60.0, &[(MOLE, 1), (SECOND, -1)], true),
+        atom("kat", 1.0, &[(MOLE, 1), (SECOND, -1)], true),
+        atom("Hz", 1.0, &[(SECOND, -1)], true),
+        atom("cal", 4184.0, &[(METER, 2), (GRAM, 1), (SECOND, -2)], true),
+        atom("[iU]", 1.0, &[(INTERNATIONAL_UNIT, 1)], true),
+        atom("[IU]", 1.0, &[(INTERNATIONAL_UNIT, 1)], true),
+        atom("min", 60.0, &[(SECOND, 1)], false),
+        atom("h", 3600.0, &[(SECOND, 1)], false),
+        atom("d", 86400.0, &[(SECOND, 1)], false),
+        atom("wk", 604800.0, &[(SECOND, 1)], false),
+        atom("mo", 2629800.0, &[(SECOND, 1)], false),
+        atom("a", 31557600.0, &[(SECOND, 1)], false),
+        atom("%", 1e-2, &[], false),
+        atom("[ppth]", 1e-3, &[], false),
+        atom("[ppm]", 1e-6, &[], false),
+        atom("[lb_av]", 453.59237, &[(GRAM, 1)], false),
+        atom("[oz_av]", 28.349523125, &[(GRAM, 1)], false),
+        atom("[in_i]", 0.0254, &[(METER, 1)], false),
+        atom("[ft_i]", 0.3048, &[(METER, 1)], false),
+        Atom { offset: 273.15, ..atom("Cel", 1.0, &[(KELVIN, 1)], false) },
+        Atom { offset: 459.67 * 5.0 / 9.0, ..atom("[degF]", 5.0 / 9.0, &[(KELVIN, 1)], false) },
+    ]
+});
+
+// Unidad UCUM analizada: código, factor respecto a las unidades base y dimensión
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    pub code: String,
+    factor: f64,
+    offset: f64,
+    dimension: Dimension,
+}
+
+impl Unit {
+    // Código UCUM ("mg/dL", "mm[Hg]", "10*3/uL", "mL/min/{1.73_m2}"). Si no lo es, se prueban
+    // las variantes habituales ("mg/dl", "mmHg", "x10^9/L", "µg") y el código es el UCUM
+    pub fn parse(text: &str) -> Result<Unit, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(Unit::dimensionless());
+        }
+        let strict = Parser::new(text, false).parse();
+        if strict.is_ok() {
+            return strict;
+        }
+        let lower = text.to_lowercase();
+        if let Some((_, code)) = ALIASES.iter().find(|(alias, _)| *alias == lower) {
+            return Parser::new(code, false).parse();
+        }
+        let cleaned = BODY_SURFACE
+            .replace_all(text, "{1.73_m2}")
+            .replace(['µ', 'μ'], "u")
+            .replace('²', "2")
+            .replace('³', "3");
+        Parser::new(&cleaned, true).parse().map_err(|_| strict.unwrap_err())
+    }
+
+    fn dimensionless() -> Unit {
+        Unit { code: "1".to_string(), factor: 1.0, offset: 0.0, dimension: [0; DIMENSIONS] }
+    }
+}
+
+// Convierte entre unidades de la misma dimensión. Con la masa molar del analito también
+// entre masa y cantidad de sustancia (glucosa: mg/dL <-> mmol/L)
+pub fn convert(value: f64, from: &Unit, to: &Unit, molar_mass: Option<f64>) -> Result<f64, String> {
+    let base = value * from.factor + from.offset;
+    if from.dimension == to.dimension {
+        return Ok(round((base - to.offset) / to.factor));
+    }
+
+    let mut difference = [0; DIMENSIONS];
+    for (index, d) in difference.iter_mut().enumerate() {
+        *d = from.dimension[index] - to.dimension[index];
+    }
+    let only_mass_and_substance = difference
+        .iter()
+        .enumerate()
+        .all(|(index, d)| *d == 0 || index == GRAM || index == MOLE);
+    if !only_mass_and_substance || difference[GRAM] != -difference[MOLE] {
+        return Err(format!("cannot convert {} to {}", from.code, to.code));
+    }
+    match molar_mass {
+        Some(molar_mass) => Ok(round(base * molar_mass.powi(difference[MOLE]) / to.factor)),
+        None => Err(format!("cannot convert {} to {} without a molar mass", from.code, to.code)),
+    }
+}
+
+// Sin el ruido de coma flotante de los factores: 12 cifras significativas
+pub fn round(value: f64) -> f64 {
+    if value == 0.0 || !value.is_finite() {
+        return value;
+    }
+    let scale = 10f64.powi(11 - value.abs().log10().floor() as i32);
+    (value * scale).round() / scale
+}
+
+// Análisis descendente de la gramática UCUM: términos unidos por "." y "/", con exponentes,
+// potencias de diez, anotaciones {…} y paréntesis. En modo tolerante no distingue mayúsculas
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+    lenient: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str, lenient: bool) -> Parser<'a> {
+        Parser { text, position: 0, lenient }
+    }
+
+    fn parse(mut self) -> Result<Unit, String> {
+        let unit = self.expression()?;
+        if self.position < self.text.len() {
+            return Err(format!("unexpected '{}' in unit '{}'", &self.text[self.position..], self.text));
+        }
+        Ok(unit)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn expression(&mut self) -> Result<Unit, String> {
+        let mut unit = if self.peek() == Some('/') {
+            Unit { code: String::new(), ..Unit::dimensionless() }
+        } else {
+            self.term()?
+        };
+        while let Some(operator) = self.peek().filter(|c| *c == '.' || *c == '/') {
+            self.position += 1;
+            let term = self.term()?;
+            let sign = if operator == '/' { -1 } else { 1 };
+            if unit.offset != 0.0 || term.offset != 0.0 {
+                return Err(format!("temperature unit in '{}' cannot be combined with other units", self.text));
+            }
+            unit.factor *= term.factor.powi(sign);
+            for (index, d) in unit.dimension.iter_mut().enumerate() {
+                *d += sign * term.dimension[index];
+            }
+            unit.code = format!("{}{}{}", unit.code, operator, term.code);
+        }
+        Ok(unit)
+    }
+
+    fn term(&mut self) -> Result<Unit, String> {
+        if self.peek() == Some('(') {
+            self.position += 1;
+            let inner = self.expression()?;
+            if self.peek() != Some(')') {
+                return Err(format!("unbalanced parentheses in unit '{}'", self.text));
+            }
+            self.position += 1;
+            let exponent = self.exponent();
+            return Ok(power(Unit { code: format!("({})", inner.code), ..inner }, exponent));
+        }
+
+        let start = self.position;
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            match c {
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth -= 1,
+                '.' | '/' | '(' | ')' if depth == 0 => break,
+                _ => {}
+            }
+            self.position += c.len_utf8();
+        }
+        let text = &self.text[start..self.position];
+        if text.is_empty() {
+            return Err(format!("missing unit term in '{}'", self.text));
+        }
+
+        // Anotación final: "mL{total}" es mL; "{ratio}" sola es adimensional
+        let (symbol, annotation) = match text.find('{') {
+            Some(index) if text.ends_with('}') => (&text[..index], &text[index..]),
+            _ => (text, ""),
+        };
+        if symbol.is_empty() {
+            return Ok(Unit { code: annotation.to_string(), ..Unit::dimensionless() });
+        }
+        let mut unit = self.component(symbol)?;
+        unit.code.push_str(annotation);
+        Ok(unit)
+    }
+
+    // Exponente tras ")": "(m.s)2"
+    fn exponent(&mut self) -> i32 {
+        let rest = &self.text[self.position..];
+        let length = rest
+            .char_indices()
+            .take_while(|(i, c)| c.is_ascii_digit() || (*i == 0 && (*c == '-' || *c == '+')))
+            .count();
+        match rest[..length].parse() {
+            Ok(exponent) => {
+                self.position += length;
+                exponent
+            }
+            Err(_) => 1,
+        }
+    }
+
+    // Símbolo con prefijo y exponente: "mg", "m2", "s-1", "10*3", "mm[Hg]"
+    fn component(&self, symbol: &str) -> Result<Unit, String> {
+        if let Some(captures) = POWER_OF_TEN.captures(symbol) {
+            if self.lenient || !symbol.starts_with(['x', 'X']) && symbol.contains('*') {
+                let exponent: i32 = captures[1].parse().map_err(|_| format!("invalid power of ten '{}'", symbol))?;
+                return Ok(Unit { code: format!("10*{}", exponent), factor: 10f64.powi(exponent), ..Unit::dimensionless() });
+            }
+        }
+        if symbol.bytes().all(|b| b.is_ascii_digit()) {
+            let factor: f64 = symbol.parse().map_err(|_| format!("invalid number '{}'", symbol))?;
+            return Ok(Unit { code: symbol.to_string(), factor, ..Unit::dimensionless() });
+        }
+
+        let symbol = if self.lenient { symbol.trim_end_matches('^') } else { symbol };
+        let (name, exponent) = split_exponent(symbol, self.lenient);
+        let name = if self.lenient && name.eq_ignore_ascii_case("mcg") { "ug" } else { name };
+        let (prefix, atom) = match self.atom(name) {
+            Some(found) => found,
+            None if self.lenient => return self.misspelled(symbol),
+            None => return Err(format!("unknown unit '{}' in '{}'", name, self.text)),
+        };
+        if atom.offset != 0.0 && (prefix.is_some() || exponent != 1) {
+            return Err(format!("temperature unit in '{}' cannot be combined with other units", self.text));
+        }
+        let (prefix_symbol, prefix_factor) = prefix.unwrap_or(("", 1.0));
+        let unit = Unit {
+            code: format!("{}{}", prefix_symbol, atom.symbol),
+            factor: prefix_factor * atom.factor,
+            offset: atom.offset,
+            dimension: atom.dimension,
+        };
+        let mut unit = power(unit, exponent);
+        if exponent != 1 {
+            unit.code = format!("{}{}{}", prefix_symbol, atom.symbol, exponent);
+        }
+        Ok(unit)
+    }
+
+    // Escrituras habituales fuera de UCUM: "mUI" (m[iU]), "24h" (24.h) y palabras sueltas
+    // como "cells" o "copias", que pasan a ser anotaciones
+    fn misspelled(&self, symbol: &str) -> Result<Unit, String> {
+        let lower = symbol.to_lowercase();
+        if let Some(prefix) = lower.strip_suffix("ui").or_else(|| lower.strip_suffix("iu")) {
+            if prefix.is_empty() || PREFIXES.iter().any(|(p, _)| p.eq_ignore_ascii_case(prefix)) {
+                return self.component(&format!("{}[iU]", &symbol[..prefix.len()]));
+            }
+        }
+        let digits = symbol.len() - symbol.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits > 0 && digits < symbol.len() {
+            let number: f64 = symbol[..digits].parse().map_err(|_| format!("invalid number in '{}'", symbol))?;
+            let unit = self.component(&symbol[digits..])?;
+            return Ok(Unit { code: format!("({}.{})", &symbol[..digits], unit.code), factor: number * unit.factor, ..unit });
+        }
+        if symbol.chars().count() >= 3 && symbol.chars().all(char::is_alphabetic) {
+            return Ok(Unit { code: format!("{{{}}}", symbol), ..Unit::dimensionless() });
+        }
+        Err(format!("unknown unit '{}' in '{}'", symbol, self.text))
+    }
+
+    // El símbolo completo primero ("min", "mo", "cal") y luego prefijo + símbolo métrico
+    fn atom(&self, name: &str) -> Option<(Option<(&'static str, f64)>, &'static Atom)> {
+        let same = |a: &str, b: &str| if self.lenient { a.eq_ignore_ascii_case(b) } else { a == b };
+        if let Some(atom) = ATOMS.iter().find(|a| same(a.symbol, name)) {
+            return Some((None, atom));
+        }
+        let mut prefixes: Vec<&(&str, f64)> = PREFIXES.iter().collect();
+        if self.lenient {
+            // "MG/DL" son miligramos: antes los submúltiplos, más frecuentes en clínica
+            prefixes.sort_by_key(|(_, factor)| *factor > 1.0);
+        }
+        prefixes.into_iter().find_map(|(prefix, factor)| {
+            if name.len() <= prefix.len() || !name.is_char_boundary(prefix.len()) || !same(&name[..prefix.len()], prefix) {
+                return None;
+            }
+            let rest = &name[prefix.len()..];
+            let atom = ATOMS.iter().find(|a| a.metric && same(a.symbol, rest))?;
+            Some((Some((*prefix, *factor)), atom))
+        })
+    }
+}
+
+// "cm3" -> ("cm", 3), "s-1" -> ("s", -1); en modo tolerante también "m^2"
+fn split_exponent(symbol: &str, lenient: bool) -> (&str, i32) {
+    if symbol.ends_with(']') {
+        return (symbol, 1);
+    }
+    let digits = symbol.len() - symbol.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 || digits == symbol.len() {
+        return (symbol, 1);
+    }
+    let mut end = symbol.len() - digits;
+    if symbol[..end].ends_with(['-', '+']) {
+        end -= 1;
+    }
+    let exponent = symbol[end..].parse().unwrap_or(1);
+    let mut name = &symbol[..end];
+    if lenient {
+        name = name.trim_end_matches('^');
+    }
+    (name, exponent)
+}
+
+fn power(unit: Unit, exponent: i32) -> Unit {
+    Unit {
+        factor: unit.factor.powi(exponent),
+        dimension: unit.dimension.map(|d| d * exponent),
+        ..unit
+    }
+}
+
+// Valor de una observación en la unidad canónica de su código LOINC
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NormalizedValue {
+    pub value: f64,
+    pub unit: String,
+    // Unidad del documento, si hubo que convertir o corregir su escritura
+    pub original_unit: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct CanonicalUnit {
+    unit: Unit,
+    molar_mass: Option<f64>,
+}
+
+// Unidad canónica de cada código LOINC, con la masa molar del analito si se mide en masa y moles
+#[derive(Debug, Clone, Default)]
+pub struct CanonicalUnits {
+    units: HashMap<String, CanonicalUnit>,
+}
+
+impl CanonicalUnits {
+    pub fn defaults() -> CanonicalUnits {
+        DEFAULTS.clone()
+    }
+
+    // CSV o TSV con cabecera: loinc, unit (UCUM) y molar_mass opcional (g/mol).
+    // Devuelve el número de códigos cargados
+    pub fn load(&mut self, content: &str, replace: bool) -> Result<usize, String> {
+        let table = RuleTable::parse(content)?;
+        let loinc = table.required(&LOINC_HEADERS)?;
+        let unit = table.required(&UNIT_HEADERS)?;
+        let molar_mass = table.column(&MOLAR_MASS_HEADERS);
+
+        let mut units = HashMap::new();
+        for (number, fields) in &table.rows {
+            let code = alerts::field(fields, Some(loinc)).ok_or_else(|| format!("line {}: missing LOINC code", number))?;
+            if let Some(Err(reason)) = code_systems::lookup(code_systems::LOINC).map(|s| s.check(code)) {
+                return Err(format!("line {}: {}", number, reason));
+            }
+            let unit_text = alerts::field(fields, Some(unit)).ok_or_else(|| format!("line {}: missing unit", number))?;
+            let unit = Unit::parse(unit_text).map_err(|e| format!("line {}: {}", number, e))?;
+            let molar_mass = match alerts::field(fields, molar_mass) {
+                Some(text) => Some(
+                    parse_number(text)
+                        .filter(|m| *m > 0.0)
+                        .ok_or_else(|| format!("line {}: invalid molar mass '{}'", number, text))?,
+                ),
+                None => None,
+            };
+            units.insert(code.to_string(), CanonicalUnit { unit, molar_mass });
+        }
+
+        let count = units.len();
+        if replace {
+            self.units.clear();
+        }
+        self.units.extend(units);
+        Ok(count)
+    }
+
+    // Sin unidad canónica para el código, el valor se queda en su unidad escrita en UCUM
+    pub fn normalize(&self, code: Option<&str>, value: f64, unit: Option<&str>) -> Result<NormalizedValue, String> {
+        let from = Unit::parse(unit.unwrap_or(""))?;
+        let (value, to) = match code.and_then(|c| self.units.get(c)) {
+            Some(canonical) => (convert(value, &from, &canonical.unit, canonical.molar_mass)?, &canonical.unit),
+            None => (value, &from),
+        };
+        let original_unit = unit.filter(|u| *u != to.code).map(|u| u.to_string());
+        Ok(NormalizedValue { value, unit: to.code.clone(), original_unit })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLUCOSE_MOLAR_MASS: f64 = 180.156;
+
+    fn convert_text(value: f64, from: &str, to: &str, molar_mass: Option<f64>) -> Result<f64, String> {
+        convert(value, &Unit::parse(from)?, &Unit::parse(to)?, molar_mass)
+    }
+
+    #[test]
+    fn glucose_between_mass_and_substance() {
+        let mmol = convert_text(100.0, "mg/dL", "mmol/L", Some(GLUCOSE_MOLAR_MASS)).unwrap();
+        assert!((mmol - 5.5507).abs() < 1e-4, "{}", mmol);
+        let mg = convert_text(5.5, "mmol/L", "mg/dL", Some(GLUCOSE_MOLAR_MASS)).unwrap();
+        assert!((mg - 99.0858).abs() < 1e-4, "{}", mg);
+        assert!(convert_text(100.0, "mg/dL", "mmol/L", None).is_err());
+    }
+
+    #[test]
+    fn mercury_pressure() {
+        assert_eq!(Unit::parse("mmHg").unwrap().code, "mm[Hg]");
+        let kpa = convert_text(120.0, "mm[Hg]", "kPa", None).unwrap();
+        assert!((kpa - 15.99864).abs() < 1e-5, "{}", kpa);
+        assert!(convert_text(120.0, "mm[Hg]", "mg/dL", None).is_err());
+    }
+
+    #[test]
+    fn common_spellings() {
+        assert_eq!(Unit::parse("mg/dl").unwrap().code, "mg/dL");
+        assert_eq!(Unit::parse("x10^9/L").unwrap().code, "10*9/L");
+        assert_eq!(Unit::parse("ml/min/1.73m2").unwrap().code, "mL/min/{1.73_m2}");
+    }
+}